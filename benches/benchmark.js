@@ -16,11 +16,11 @@ var langs = {
     },
     build: (task) => {
       exec("cp ../../examples/"+task+"/main.hvm main.hvm");
-      exec("hvm compile main.hvm");
+      exec("hvm compile main.hvm --hvm-path ../..");
       exec("cd main; cargo build --release; mv target/release/main ../main.bin");
     },
     bench: (task, size, tids) => {
-      return bench('./main.bin run -t '+tids+' "(Main ' + size + ')" 2>/dev/null');
+      return bench('./main.bin -t '+tids+' "(Main ' + size + ')" 2>/dev/null');
     },
     clean: () => {
       exec("rm main.hvm");
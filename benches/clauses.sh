@@ -0,0 +1,71 @@
+#!/bin/sh
+# Compares the selection of rules on functions with many clauses, which walks a decision tree,
+# against the linear scan over their rules it replaced.
+#
+# Usage, from the repository's root: sh benches/clauses.sh <linear revision>
+# The linear revision must be one which still scanned the rules, such as the parent of the first
+# one with `MatchTree`, which this prints:
+#   git log --reverse --format=%h^ -S"pub enum MatchTree" -- src/runtime/base/program.rs | head -1
+# Both run interpreted, and results are the best of 3 runs, on a single thread.
+
+set -e
+if [ -z "$1" ]; then
+  echo "usage: sh benches/clauses.sh <linear revision>" >&2
+  exit 1
+fi
+LINEAR=$1
+ROOT=$(pwd)
+WORK=$ROOT/target/bench-clauses
+mkdir -p "$WORK"
+
+# Builds `hvm`, at the linear revision, from a copy of its files removed on exit, and at the
+# working tree
+rm -rf "$WORK/linear-hvm"
+trap 'rm -rf "$WORK/linear-hvm"' EXIT
+mkdir "$WORK/linear-hvm"
+git archive "$LINEAR" | tar -x -C "$WORK/linear-hvm"
+(cd "$WORK/linear-hvm" && cargo build --release --target-dir "$WORK/target-linear")
+cargo build --release --target-dir "$WORK/target-tree"
+
+# A function with `n` clauses, each matching a constructor and returning a number, so that selecting
+# the rule is most of the rewrite, called on all of them in turn
+clauses() {
+  n=$1
+  i=0
+  while [ $i -lt $n ]; do
+    echo "(Step C$i) = $i"
+    i=$((i + 1))
+  done
+  echo "(Run List.nil x) = x"
+  echo "(Run (List.cons c cs) x) = (Run cs (+ x (Step c)))"
+  echo "(Loop 0 code x) = x"
+  echo "(Loop k code x) = (Loop (- k 1) code (Run code x))"
+  printf "(Code) = ["
+  i=0
+  while [ $i -lt $n ]; do
+    printf "C%s" $(( (i * 37) % n ))
+    [ $i -lt $((n - 1)) ] && printf ", "
+    i=$((i + 1))
+  done
+  echo "]"
+  echo "(Main k) = (Loop (<< 1 k) (Code) 0)"
+}
+for n in 16 64 256; do
+  clauses $n > "$WORK/step$n.hvm"
+done
+cp "$ROOT/examples/machine/main.hvm" "$WORK/machine.hvm"
+
+best() {
+  for _ in 1 2 3; do
+    "$@" 2>&1 | grep -o "TIME: [0-9.]*s" | cut -d' ' -f2
+  done | sort -n | head -1
+}
+
+printf "%-24s %10s %10s\n" "program" "linear" "tree"
+for task in "machine (Main 16)" "step16 (Main 16)" "step64 (Main 14)" "step256 (Main 12)"; do
+  name=${task%% *}
+  term=${task#* }
+  linear=$(best "$WORK/target-linear/release/hvm" run -t 1 -c true -f "$WORK/$name.hvm" "$term")
+  tree=$(best "$WORK/target-tree/release/hvm" run -t 1 -c true -f "$WORK/$name.hvm" "$term")
+  printf "%-24s %10s %10s\n" "$name" "$linear" "$tree"
+done
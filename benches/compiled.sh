@@ -0,0 +1,59 @@
+#!/bin/sh
+# Compares the crates `hvm compile` writes, which depend on `hvm` and call the compiled functions
+# through `Program::fast`, against the ones it wrote before, which copied the whole runtime and
+# patched the compiled functions into the reducer's source.
+#
+# Usage, from the repository's root: sh benches/compiled.sh <patched revision>
+# The patched revision must be one which still wrote such crates, such as the parent of the first
+# one with `RuntimeBuilder::add_compiled`, which this prints:
+#   git log --reverse --format=%h^ -S"pub fn add_compiled(" -- src/runtime/mod.rs | head -1
+# Results are the best of 3 runs, on a single thread.
+
+set -e
+if [ -z "$1" ]; then
+  echo "usage: sh benches/compiled.sh <patched revision>" >&2
+  exit 1
+fi
+PATCHED=$1
+ROOT=$(pwd)
+WORK=$ROOT/target/bench-compiled
+mkdir -p "$WORK"
+
+# Builds `hvm`, at the patched revision, from a copy of its files removed on exit, and at the
+# working tree
+rm -rf "$WORK/patched-hvm"
+trap 'rm -rf "$WORK/patched-hvm"' EXIT
+mkdir "$WORK/patched-hvm"
+git archive "$PATCHED" | tar -x -C "$WORK/patched-hvm"
+(cd "$WORK/patched-hvm" && cargo build --release --target-dir "$WORK/target-patched-hvm")
+cargo build --release --target-dir "$WORK/target-hvm"
+
+best() {
+  for _ in 1 2 3; do
+    "$@" 2>&1 | grep -o "TIME: [0-9.]*s" | cut -d' ' -f2
+  done | sort -n | head -1
+}
+
+printf "%-24s %10s %10s\n" "program" "patched" "thin"
+for task in "sort/radix (Main 18)" "machine (Main 15)" "sort/quick (Main 20)"; do
+  example=${task%% *}
+  term=${task#* }
+  name=$(echo "$example" | tr '/' '_')
+  cp "$ROOT/examples/$example/main.hvm" "$WORK/$name.hvm"
+
+  # The patched crate is written next to the file, named after it, with a copy of the manifest
+  rm -rf "$WORK/patched" "$WORK/$name"
+  mkdir -p "$WORK/patched"
+  cp "$WORK/$name.hvm" "$WORK/patched/"
+  (cd "$WORK/patched" && "$WORK/target-patched-hvm/release/hvm" compile "$name.hvm" > /dev/null)
+  mkdir -p "$WORK/patched/$name/benches"
+  echo "fn main() {}" > "$WORK/patched/$name/benches/runtime.rs"
+  (cd "$WORK/patched/$name" && cargo build --release --target-dir "$WORK/target-patched" 2> /dev/null)
+
+  "$WORK/target-hvm/release/hvm" compile "$WORK/$name.hvm" --out "$WORK/$name" --name "$name" --hvm-path "$ROOT" --force > /dev/null
+  (cd "$WORK/$name" && cargo build --release --target-dir "$WORK/target-thin" 2> /dev/null)
+
+  patched=$(best "$WORK/target-patched/release/$name" run -t 1 -c true "$term")
+  thin=$(best "$WORK/target-thin/release/$name" -t 1 -c "$term")
+  printf "%-24s %10s %10s\n" "$example" "$patched" "$thin"
+done
@@ -53,6 +53,7 @@ fn run_example(c: &mut Criterion, name: &str, code: &str, term: &str) {
 benchmark_example!(radix_sort, "../examples/sort/radix/main.hvm", "(Main 16)");
 benchmark_example!(piadic_clifford, "../examples/lambda/padic_clifford/main.hvm", "(Main)");
 benchmark_example!(lambda_multiplication, "../examples/lambda/multiplication/main.hvm", "(Main 16)");
+benchmark_example!(many_clauses, "../examples/machine/main.hvm", "(Main 12)");
 
-criterion_group!(benches, radix_sort, piadic_clifford, lambda_multiplication);
+criterion_group!(benches, radix_sort, piadic_clifford, lambda_multiplication, many_clauses);
 criterion_main!(benches);
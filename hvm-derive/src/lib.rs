@@ -0,0 +1,129 @@
+//! Derive macros for `hvm::syntax::IntoTerm` and `hvm::syntax::FromTerm`.
+//!
+//! Structs become constructors named after the type, such as `(Point x y)`, and enum variants
+//! become constructors named `Type.Variant`, such as `(Shape.Circle r)`. Fields are converted in
+//! the order they're declared, using their own `IntoTerm` and `FromTerm` implementations.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident};
+
+#[proc_macro_derive(IntoTerm)]
+pub fn derive_into_term(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  let name = &input.ident;
+  let body = match &input.data {
+    Data::Struct(data) => {
+      let (pattern, args) = destructure(&data.fields);
+      let ctr = name.to_string();
+      quote! {
+        let #name #pattern = self;
+        ::hvm::syntax::Term::constructor(#ctr, [#(#args),*])
+      }
+    }
+    Data::Enum(data) => {
+      let arms = data.variants.iter().map(|variant| {
+        let (pattern, args) = destructure(&variant.fields);
+        let var = &variant.ident;
+        let ctr = format!("{}.{}", name, var);
+        quote! {
+          #name::#var #pattern => ::hvm::syntax::Term::constructor(#ctr, [#(#args),*]),
+        }
+      });
+      quote! {
+        match self {
+          #(#arms)*
+        }
+      }
+    }
+    Data::Union(_) => {
+      return syn::Error::new(Span::call_site(), "IntoTerm can't be derived for unions").to_compile_error().into();
+    }
+  };
+  let generics = add_bound(&input.generics, quote!(::hvm::syntax::IntoTerm));
+  let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+  quote! {
+    impl #impl_generics ::hvm::syntax::IntoTerm for #name #type_generics #where_clause {
+      fn into_term(self) -> ::hvm::syntax::Term {
+        #body
+      }
+    }
+  }.into()
+}
+
+#[proc_macro_derive(FromTerm)]
+pub fn derive_from_term(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  let name = &input.ident;
+  let arms = match &input.data {
+    Data::Struct(data) => {
+      vec![from_ctr(&name.to_string(), quote!(#name), &data.fields)]
+    }
+    Data::Enum(data) => {
+      data.variants.iter().map(|variant| {
+        let var = &variant.ident;
+        from_ctr(&format!("{}.{}", name, var), quote!(#name::#var), &variant.fields)
+      }).collect()
+    }
+    Data::Union(_) => {
+      return syn::Error::new(Span::call_site(), "FromTerm can't be derived for unions").to_compile_error().into();
+    }
+  };
+  let generics = add_bound(&input.generics, quote!(::hvm::syntax::FromTerm));
+  let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+  quote! {
+    impl #impl_generics ::hvm::syntax::FromTerm for #name #type_generics #where_clause {
+      fn from_term(term: ::hvm::syntax::Term) -> ::std::result::Result<Self, ::hvm::syntax::Term> {
+        match term {
+          #(#arms)*
+          term => ::std::result::Result::Err(term),
+        }
+      }
+    }
+  }.into()
+}
+
+// Binds each field to a variable, returning the pattern and the conversion of each variable
+fn destructure(fields: &Fields) -> (TokenStream, Vec<TokenStream>) {
+  let vars: Vec<Ident> = (0 .. fields.len()).map(|i| format_ident!("field{}", i)).collect();
+  let args = vars.iter().map(|var| quote!(::hvm::syntax::IntoTerm::into_term(#var))).collect();
+  let pattern = match fields {
+    Fields::Named(fields) => {
+      let names = fields.named.iter().map(|field| &field.ident);
+      quote!({ #(#names: #vars),* })
+    }
+    Fields::Unnamed(_) => quote!(( #(#vars),* )),
+    Fields::Unit => quote!(),
+  };
+  (pattern, args)
+}
+
+// Builds the match arm that converts the constructor with the given name to `path`
+fn from_ctr(ctr: &str, path: TokenStream, fields: &Fields) -> TokenStream {
+  let arity = fields.len();
+  let vals = (0 .. arity).map(|_| quote!(::hvm::syntax::FromTerm::from_term(*args.next().unwrap())?));
+  let value = match fields {
+    Fields::Named(fields) => {
+      let names = fields.named.iter().map(|field| &field.ident);
+      quote!(#path { #(#names: #vals),* })
+    }
+    Fields::Unnamed(_) => quote!(#path( #(#vals),* )),
+    Fields::Unit => quote!(#path),
+  };
+  quote! {
+    ::hvm::syntax::Term::Ctr { name, args } if name == #ctr && args.len() == #arity => {
+      #[allow(unused_mut, unused_variables)]
+      let mut args = args.into_iter();
+      ::std::result::Result::Ok(#value)
+    }
+  }
+}
+
+// Requires every type parameter to implement the derived trait too
+fn add_bound(generics: &syn::Generics, bound: TokenStream) -> syn::Generics {
+  let mut generics = generics.clone();
+  for param in generics.type_params_mut() {
+    param.bounds.push(syn::parse2(bound.clone()).unwrap());
+  }
+  generics
+}
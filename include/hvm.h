@@ -0,0 +1,132 @@
+#ifndef HVM_H
+#define HVM_H
+
+/* Generated by cbindgen from src/ffi.rs. Don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * the result of a fallible call
+ */
+typedef enum HvmStatus {
+  HVM_STATUS_OK = 0,
+  /**
+   * a required pointer was null
+   */
+  HVM_STATUS_NULL_POINTER = 1,
+  /**
+   * a string wasn't valid UTF-8
+   */
+  HVM_STATUS_INVALID_UTF8 = 2,
+  /**
+   * the code or expression failed to parse
+   */
+  HVM_STATUS_PARSE_ERROR = 3,
+  /**
+   * the evaluation took more rewrites than allowed
+   */
+  HVM_STATUS_LIMIT_EXCEEDED = 4,
+  /**
+   * the evaluation failed, for example due to an unbound symbol
+   */
+  HVM_STATUS_RUNTIME_ERROR = 5,
+  /**
+   * the code parsed, but the runtime couldn't be built from it, for example due to inconsistent arities
+   */
+  HVM_STATUS_BUILD_ERROR = 6,
+  /**
+   * the evaluation called `HVM.exit`, whose code is returned by `hvm_runtime_exit_code`
+   */
+  HVM_STATUS_EXITED = 7,
+} HvmStatus;
+
+/**
+ * a runtime, with the code added to it so far
+ */
+typedef struct HvmRuntime HvmRuntime;
+
+/**
+ * creates a runtime with the given heap size (in 64-bit nodes) and number of threads,
+ * where 0 picks the same defaults as the `hvm` command.
+ *
+ * it must be released with `hvm_runtime_free`.
+ */
+struct HvmRuntime *hvm_runtime_new(size_t heap_size, size_t thread_count);
+
+/**
+ * releases a runtime created by `hvm_runtime_new`.
+ *
+ * # Safety
+ *
+ * `runtime` must be null, or a runtime which wasn't released yet.
+ */
+void hvm_runtime_free(struct HvmRuntime *runtime);
+
+/**
+ * adds the rules written in the given HVM source code.
+ *
+ * on failure, if `error` isn't null, it receives the error message.
+ *
+ * # Safety
+ *
+ * `runtime` must be a live runtime, and `code` a nul-terminated string.
+ */
+enum HvmStatus hvm_runtime_add_code(struct HvmRuntime *runtime, const char *code, char **error);
+
+/**
+ * evaluates the given expression to normal form.
+ *
+ * if `output` isn't null, it receives the result on success, or the error message on failure.
+ *
+ * # Safety
+ *
+ * `runtime` must be a live runtime, and `expr` a nul-terminated string.
+ */
+enum HvmStatus hvm_runtime_eval(struct HvmRuntime *runtime, const char *expr, char **output);
+
+/**
+ * evaluates the given expression to normal form, like `hvm_runtime_eval`,
+ * giving up with `LimitExceeded` once it takes more than `max_rewrites` graph rewrites.
+ *
+ * # Safety
+ *
+ * `runtime` must be a live runtime, and `expr` a nul-terminated string.
+ */
+enum HvmStatus hvm_runtime_eval_limited(struct HvmRuntime *runtime,
+                                        const char *expr,
+                                        uint64_t max_rewrites,
+                                        char **output);
+
+/**
+ * returns the number of graph rewrites made by the runtime, since its creation.
+ *
+ * # Safety
+ *
+ * `runtime` must be null, or a live runtime.
+ */
+uint64_t hvm_runtime_cost(const struct HvmRuntime *runtime);
+
+/**
+ * returns the code given to `HVM.exit` by the last evaluation which returned `Exited`,
+ * or 0 if there was none.
+ *
+ * # Safety
+ *
+ * `runtime` must be null, or a live runtime.
+ */
+int32_t hvm_runtime_exit_code(const struct HvmRuntime *runtime);
+
+/**
+ * releases a string returned by the runtime.
+ *
+ * # Safety
+ *
+ * `text` must be null, or a string returned by the runtime which wasn't released yet.
+ */
+void hvm_string_free(char *text);
+
+#endif /* HVM_H */
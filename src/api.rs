@@ -2,17 +2,29 @@ use crate::language;
 use crate::runtime;
 
 // Evaluates a HVM term to normal form
+#[allow(clippy::too_many_arguments)]
 pub fn eval(
   file: &str,
   term: &str,
-  funs: Vec<(String, runtime::Function)>,
+  args: &[String],
+  compiled: &[runtime::CompiledFunction],
   size: usize,
   tids: usize,
   dbug: bool,
+  io: bool,
+  opts: &runtime::Optimizations,
+  options: language::readback::ReadbackOptions,
 ) -> Result<(String, u64, u64), String> {
 
-  // Parses and reads the input file
-  let file = language::syntax::read_file(&format!("{}\nHVM_MAIN_CALL = {}", file, term))?;
+  // Parses and reads the input file. A `Main` taking an argument receives the command-line arguments
+  let main_args = term.trim() == "Main" && language::syntax::read_file(file)?.rules.iter().any(|rule| {
+    matches!(&*rule.lhs, language::syntax::Term::Ctr { name, args } if name == "Main" && args.len() == 1)
+  });
+  let file = if main_args {
+    language::syntax::read_file(&format!("{}\n(HVM_MAIN_CALL args) = (Main args)", file))?
+  } else {
+    language::syntax::read_file(&format!("{}\nHVM_MAIN_CALL = {}", file, term))?
+  };
 
   // Converts the file to a Rulebook
   let book = language::rulebook::gen_rulebook(&file);
@@ -24,27 +36,38 @@ pub fn eval(
 
   // Adds the interpreted functions (from the Rulebook)
   prog.add_book(&book);
+  runtime::optimize(&mut prog.funs, opts);
 
-  // Adds the extra functions
-  for (name, fun) in funs {
-    prog.add_function(name, fun);
-  }
+  // Adds the functions compiled ahead of time
+  prog.add_compiled(&book, compiled)?;
 
   // Creates the runtime heap
   let heap = runtime::new_heap(size, tids);
   let tids = runtime::new_tids(tids);
 
   // Allocates the main term
-  runtime::link(&heap, 0, runtime::Fun(*book.name_to_id.get("HVM_MAIN_CALL").unwrap(), 0));
+  let main = *book.name_to_id.get("HVM_MAIN_CALL").unwrap();
+  runtime::link(&heap, 0, runtime::Fun(main, 0));
+  if main_args {
+    // Allocated after the root, so that they don't take its place
+    let args : Vec<runtime::Ptr> = args.iter().map(|arg| runtime::make_string(&heap, tids[0], arg)).collect();
+    let call = runtime::alloc(&heap, tids[0], 1);
+    runtime::link(&heap, call, runtime::make_list(&heap, tids[0], &args));
+    runtime::link(&heap, 0, runtime::Fun(main, call));
+  }
   let host = 0;
 
-  // Normalizes it
+  // Normalizes it, or runs it as an IO action
   let init = instant::Instant::now();
-  runtime::normalize(&heap, &prog, &tids, host, dbug);
+  if io {
+    runtime::run_io(&heap, &prog, &tids, host, &mut std::io::stdin().lock(), &mut std::io::stdout(), dbug)?;
+  } else {
+    runtime::normalize(&heap, &prog, &tids, host, dbug);
+  }
   let time = init.elapsed().as_millis() as u64;
 
   // Reads it back to a string
-  let code = format!("{}", language::readback::as_term(&heap, &prog, host));
+  let code = format!("{}", language::readback::as_term_with(&heap, &prog, host, &options));
 
   // Frees used memory
   runtime::collect(&heap, &prog.aris, tids[0], runtime::load_ptr(&heap, host));
@@ -53,3 +76,87 @@ pub fn eval(
   // Returns the result, rewrite cost and time elapsed
   Ok((code, runtime::get_cost(&heap), time))
 }
+
+// Explains which arguments of the code's functions are strict, and why
+pub fn explain_strictness(code: &str) -> Result<String, String> {
+  let file = language::syntax::read_file(code)?;
+  language::rulebook::check_file(&file)?;
+  Ok(language::rulebook::explain_strictness(&language::rulebook::gen_rulebook(&file)))
+}
+
+// Ends the process like a failed evaluation asks: with the code given to `HVM.exit`, or showing
+// the error
+pub fn exit_with(failure: runtime::Failure) -> ! {
+  match failure {
+    runtime::Failure::Error(message) => {
+      eprintln!("{}", message);
+      std::process::exit(1);
+    }
+    runtime::Failure::Exit(code) => {
+      std::io::Write::flush(&mut std::io::stdout()).ok();
+      std::process::exit(code);
+    }
+  }
+}
+
+// Shows the time and rewrites an evaluation took
+pub fn print_cost(cost: u64, time: u64) {
+  eprintln!();
+  eprintln!("\x1b[32m[TIME: {:.2}s | COST: {} | RPS: {:.2}m]\x1b[0m", ((time as f64)/1000.0), cost - 1, (cost as f64) / (time as f64) / 1000.0);
+}
+
+// The command line of programs compiled by `hvm compile`, which works like `hvm run`
+#[derive(clap::Parser)]
+struct CompiledCli {
+  /// Set the heap size (in 64-bit nodes).
+  #[clap(short = 's', long)]
+  size: Option<usize>,
+
+  /// Set the number of threads to use.
+  #[clap(short = 't', long)]
+  tids: Option<usize>,
+
+  /// Shows the number of graph rewrites performed.
+  #[clap(short = 'c', long)]
+  cost: bool,
+
+  /// Runs `Main` as an IO action, performing its effects in order.
+  #[clap(long)]
+  io: bool,
+
+  /// The expression to run.
+  #[clap(default_value = "Main")]
+  expr: String,
+
+  /// Arguments given to `Main`, as a list of strings, when it takes one.
+  #[clap(last = true)]
+  args: Vec<String>,
+}
+
+// The entry point of programs compiled by `hvm compile`, given the code they were compiled from
+pub fn run_compiled(code: &str, compiled: &[runtime::CompiledFunction]) {
+  let cli = <CompiledCli as clap::Parser>::parse();
+  let size = cli.size.unwrap_or_else(runtime::default_heap_size);
+  let tids = cli.tids.unwrap_or_else(runtime::default_heap_tids);
+  let options = language::readback::ReadbackOptions::default();
+  let result = runtime::catch_failure(|| {
+    eval(code, &cli.expr, &cli.args, compiled, size, tids, false, cli.io, &runtime::Optimizations::default(), options)
+  });
+  match result {
+    Ok(Ok((norm, cost, time))) => {
+      // IO programs show their output themselves
+      if !cli.io {
+        println!("{}", norm);
+      }
+      if cli.cost {
+        print_cost(cost, time);
+      }
+    }
+    Ok(Err(err)) => {
+      exit_with(runtime::Failure::Error(err));
+    }
+    Err(failure) => {
+      exit_with(failure);
+    }
+  }
+}
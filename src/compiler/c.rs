@@ -0,0 +1,629 @@
+// Compiles HVM programs to C, completing the runtime at `runtime.c` with the program's ids and a
+// reducer for each function. It mirrors `compile.rs`, which compiles them to Rust.
+
+use std::collections::HashMap;
+use crate::language as language;
+use crate::runtime as runtime;
+use super::compile::{build_name, get_arg_name, get_var, line};
+
+// The builtins `runtime.c` implements, with their visit and apply functions
+const BUILTINS : &[(u64, Option<&str>, &str)] = &[
+  (runtime::U60_IF, Some("u60_if_visit"), "u60_if_apply"),
+  (runtime::U60_SWAP, Some("u60_swap_visit"), "u60_swap_apply"),
+  (runtime::HVM_LOG, None, "hvm_log_apply"),
+  (runtime::HVM_PRINT, None, "hvm_print_apply"),
+  (runtime::HVM_PUT, None, "hvm_put_apply"),
+  (runtime::HVM_EPRINT, None, "hvm_eprint_apply"),
+  (runtime::HVM_EXIT, None, "hvm_exit_apply"),
+];
+
+// Compiles the code, tree-shaken from the given entries, if any, returning what was removed
+pub fn build_code(code: &str, opts: &runtime::Optimizations, entries: Option<&[String]>) -> Result<(String, Option<language::rulebook::Shaken>), String> {
+  let file = language::syntax::read_file(code)?;
+  language::rulebook::check_file(&file)?;
+  let mut book = language::rulebook::gen_rulebook(&file);
+  let shaken = entries.map(|entries| language::rulebook::tree_shake(&mut book, entries)).transpose()?;
+  check_builtins(&book)?;
+  Ok((build_rulebook(&book, opts), shaken))
+}
+
+// The other builtins need a host to perform their effects, which the C runtime doesn't have
+fn check_builtins(book: &language::rulebook::RuleBook) -> Result<(), String> {
+  fn check(term: &language::syntax::Term) -> Result<(), String> {
+    use language::syntax::Term;
+    match term {
+      Term::Var { .. } | Term::U6O { .. } | Term::F6O { .. } => Ok(()),
+      Term::Dup { expr, body, .. } | Term::Let { expr, body, .. } => { check(expr)?; check(body) }
+      Term::Sup { val0, val1 } | Term::Op2 { val0, val1, .. } => { check(val0)?; check(val1) }
+      Term::App { func, argm } => { check(func)?; check(argm) }
+      Term::Lam { body, .. } => check(body),
+      Term::Ctr { name, args } => {
+        let unsupported = runtime::PRECOMP.iter().any(|precomp| {
+          precomp.name == name && precomp.funs.is_some() && !BUILTINS.iter().any(|(id, _, _)| *id == precomp.id)
+        });
+        if unsupported {
+          return Err(format!("The C target doesn't support '{}'.", name));
+        }
+        args.iter().try_for_each(|arg| check(arg))
+      }
+    }
+  }
+  for (_, rules) in book.rule_group.values() {
+    for rule in rules {
+      check(&rule.rhs)?;
+    }
+  }
+  Ok(())
+}
+
+// Builds a C program with the rulebook's functions, by filling the sections of `runtime.c`
+pub fn build_rulebook(book: &language::rulebook::RuleBook, opts: &runtime::Optimizations) -> String {
+  let ids : Vec<&u64> = itertools::sorted(book.id_to_name.keys()).collect();
+  let count = ids.last().map(|id| **id + 1).unwrap_or(0);
+
+  // ids, arities and names
+  let mut consts = String::new();
+  for id in &ids {
+    line(&mut consts, 0, &format!("#define {} ((u64){})", build_name(&book.id_to_name[id]), id));
+  }
+  line(&mut consts, 0, "");
+  line(&mut consts, 0, &format!("#define ID_COUNT ((u64){})", count));
+  line(&mut consts, 0, "");
+  line(&mut consts, 0, "static const u64 ARITY[] = {");
+  for id in 0 .. count {
+    let arity = book.id_to_smap.get(&id).map(|smap| smap.len()).unwrap_or(0);
+    line(&mut consts, 1, &format!("{}, // {}", arity, book.id_to_name.get(&id).map(|name| name.as_str()).unwrap_or("")));
+  }
+  line(&mut consts, 0, "};");
+  line(&mut consts, 0, "");
+  line(&mut consts, 0, "static const char* NAMES[] = {");
+  for id in 0 .. count {
+    match book.id_to_name.get(&id) {
+      Some(name) => line(&mut consts, 1, &format!("{:?},", name)),
+      None => line(&mut consts, 1, "NULL,"),
+    }
+  }
+  line(&mut consts, 0, "};");
+
+  // functions
+  let mut funs = runtime::gen_functions(book);
+  runtime::optimize(&mut funs, opts);
+  let mut funcs = String::new();
+  let mut visits = vec![String::from("NULL"); count as usize];
+  let mut applys = vec![String::from("NULL"); count as usize];
+  for (id, visit, apply) in BUILTINS {
+    if let Some(visit) = visit {
+      visits[*id as usize] = visit.to_string();
+    }
+    applys[*id as usize] = apply.to_string();
+  }
+  for id in &ids {
+    if **id >= runtime::PRECOMP_COUNT {
+      let name = &book.id_to_name[id];
+      if let Some(func) = funs.get(id) {
+        let (got_visit, got_apply) = build_function(book, name, func);
+        line(&mut funcs, 0, &got_visit);
+        line(&mut funcs, 0, &got_apply);
+        visits[**id as usize] = format!("{}_visit", build_name(name));
+        applys[**id as usize] = format!("{}_apply", build_name(name));
+      }
+    }
+  }
+
+  // tables
+  line(&mut funcs, 0, "static void (*const VISIT_FN[])(Ptr) = {");
+  for visit in &visits {
+    line(&mut funcs, 1, &format!("{},", visit));
+  }
+  line(&mut funcs, 0, "};");
+  line(&mut funcs, 0, "");
+  line(&mut funcs, 0, "static int (*const APPLY[])(u64, Ptr) = {");
+  for apply in &applys {
+    line(&mut funcs, 1, &format!("{},", apply));
+  }
+  line(&mut funcs, 0, "};");
+
+  let template = include_str!("runtime.c");
+  let code = template
+    .replace("/*! GENERATED_CONSTANTS !*/", consts.trim_end())
+    .replace("/*! GENERATED_FUNCTIONS !*/", funcs.trim_end());
+  format!("// Generated by the HVM compiler. Don't edit it by hand.\n\n{}", code)
+}
+
+// Builds the header declaring the library functions of the C file named `name`, from `runtime.h`
+pub fn build_header(name: &str) -> String {
+  let header = include_str!("runtime.h").replace("<name>", name);
+  format!("// Generated by the HVM compiler. Don't edit it by hand.\n\n{}", header)
+}
+
+pub fn build_function(
+  book  : &language::rulebook::RuleBook,
+  fname : &str,
+  func  : &runtime::Function,
+) -> (String, String) {
+  if let runtime::Function::Interpreted {
+    smap: fn_smap,
+    visit: fn_visit,
+    apply: fn_apply,
+  } = func {
+
+    // Visit
+    // -----
+
+    let mut visit = String::new();
+    line(&mut visit, 0, &format!("static void {}_visit(Ptr term) {{", &build_name(fname)));
+    if fn_visit.strict_idx.is_empty() {
+      line(&mut visit, 1, "(void)term;");
+    }
+    // Pushed in reverse, so that they are reduced in order
+    for sidx in fn_visit.strict_idx.iter().rev() {
+      line(&mut visit, 1, &format!("if (!is_whnf(load_arg(term, {}))) {{", *sidx));
+      line(&mut visit, 2, &format!("visit(get_loc(term, {}));", *sidx));
+      line(&mut visit, 1, "}");
+    }
+    line(&mut visit, 0, "}");
+
+    // Apply
+    // -----
+
+    let mut apply = String::new();
+    line(&mut apply, 0, &format!("static int {}_apply(u64 host, Ptr term) {{", &build_name(fname)));
+
+    // Transmute Optimization
+    // ----------------------
+    // When a function has the shape:
+    // (Foo a b c ...) = (Bar a b c ...)
+    // It just transmutes the pointer.
+
+    if let Some(ext) = get_transmute(&fn_apply.rules) {
+      line(&mut apply, 1, &format!("link(host, Ctr({}, get_loc(term, 0)));", ext));
+      line(&mut apply, 1, "return 0;");
+      line(&mut apply, 0, "}");
+      return (visit, apply);
+    }
+
+    // Loads strict arguments
+    for i in 0 .. fn_smap.len() {
+      line(&mut apply, 1, &format!("Ptr arg{} = load_arg(term, {});", i, i));
+    }
+
+    // Applies the fun_sup rule to superposed args
+    for (i, is_strict) in fn_visit.strict_map.iter().enumerate() {
+      if *is_strict {
+        line(&mut apply, 1, &format!("if (get_tag(arg{}) == SUP) {{", i));
+        line(&mut apply, 2, &format!("superpose(host, term, arg{}, {});", i, i));
+        line(&mut apply, 2, "return 1;");
+        line(&mut apply, 1, "}");
+      }
+    }
+
+    // Selects the rule to apply with the function's decision tree
+    build_match_tree(book, &mut apply, 1, &fn_apply.tree, &mut |code, tab, index| {
+      build_function_rule(book, code, tab, &fn_apply.rules[index], fn_visit.strict_map.len() as u64);
+    });
+    line(&mut apply, 0, "}");
+
+    (visit, apply)
+  } else {
+    panic!("Unexpected error.");
+  }
+}
+
+// Builds the code applying a rule, once it matched
+fn build_function_rule(book: &language::rulebook::RuleBook, code: &mut String, tab: u64, rule: &runtime::program::Rule, arity: u64) {
+  // Increments the gas count
+  line(code, tab, "inc_cost();");
+
+  // Builds the free vector
+  let mut free : Vec<Option<(String,u64)>> = vec![];
+  for (idx, path, ari) in &rule.free {
+    free.push(Some((format!("get_loc({}, 0)", get_arg_name(*idx, path)), *ari)));
+  }
+  free.push(Some(("get_loc(term, 0)".to_string(), arity)));
+
+  // Builds the right-hand side term, and links the host location to it
+  let done = build_function_rule_rhs(book, code, &mut free, tab, &rule.core, &rule.vars);
+  line(code, tab, &format!("Ptr done = {};", done));
+  line(code, tab, "link(host, done);");
+
+  // Collects unused variables
+  for dynvar @ runtime::RuleVar { erase, .. } in rule.vars.iter() {
+    if *erase {
+      line(code, tab, &format!("collect({});", get_var(dynvar)));
+    }
+  }
+
+  // Clears the matched ctrs, and the call itself
+  for (loc, ari) in free.iter().flatten() {
+    line(code, tab, &format!("free_node({}, {});", loc, ari));
+  }
+
+  let ret_ptr = match rule.body.0 {
+    runtime::RuleBodyCell::Val { value }     => value,
+    runtime::RuleBodyCell::Ptr { value, .. } => value,
+    runtime::RuleBodyCell::Var { .. }        => runtime::Var(0),
+  };
+  line(code, tab, &format!("return {};", if runtime::is_whnf(ret_ptr) { 0 } else { 1 }));
+}
+
+// Builds the code walking a match tree, like `compile::build_match_tree`, with `switch`es
+fn build_match_tree(
+  book : &language::rulebook::RuleBook,
+  code : &mut String,
+  tab  : u64,
+  tree : &runtime::MatchTree,
+  leaf : &mut dyn FnMut(&mut String, u64, usize),
+) {
+  match tree {
+    runtime::MatchTree::Rule { index } => {
+      line(code, tab, "{");
+      leaf(code, tab + 1, *index);
+      line(code, tab, "}");
+    }
+    runtime::MatchTree::Fail => {
+      line(code, tab, "return 0;");
+    }
+    runtime::MatchTree::Switch { arg, path, ctrs, u60s, f60s, value, other } => {
+      let name = get_arg_name(*arg, path);
+      if let Some((field, parent)) = path.split_last() {
+        let fields = path.iter().map(|field| format!("{}", field)).collect::<Vec<String>>().join(", ");
+        let loc = format!("get_loc({}, {})", get_arg_name(*arg, parent), field);
+        line(code, tab, &format!("Ptr {} = load_ptr({});", name, loc));
+        line(code, tab, &format!("if (!is_whnf({})) {{", name));
+        line(code, tab + 1, &format!("return visit_field(host, {});", loc));
+        line(code, tab, "}");
+        line(code, tab, &format!("if (get_tag({}) == SUP) {{", name));
+        line(code, tab + 1, &format!("superpose_field(host, term, {}, (u64[]){{{}}}, {});", arg, fields, path.len()));
+        line(code, tab + 1, "return 1;");
+        line(code, tab, "}");
+      }
+      for (tag, get, cases) in [("CTR", "get_ext", ctrs), ("U60", "get_num", u60s), ("F60", "get_num", f60s)] {
+        if !cases.is_empty() {
+          line(code, tab, &format!("if (get_tag({}) == {}) {{", name, tag));
+          line(code, tab + 1, &format!("switch ({}({})) {{", get, name));
+          for (key, tree) in cases {
+            let name = if tag == "CTR" { build_name(book.id_to_name.get(key).unwrap_or(&format!("{}", key))) } else { format!("{}ULL", key) };
+            line(code, tab + 2, &format!("case {}: {{", name));
+            build_match_tree(book, code, tab + 3, tree, leaf);
+            line(code, tab + 2, "}");
+          }
+          line(code, tab + 1, "}");
+          line(code, tab, "}");
+        }
+      }
+      line(code, tab, &format!("if (get_tag({}) != CTR && get_tag({}) != U60 && get_tag({}) != F60) {{", name, name, name));
+      build_match_tree(book, code, tab + 1, other, leaf);
+      line(code, tab, "}");
+      build_match_tree(book, code, tab, value, leaf);
+    }
+    runtime::MatchTree::Hoas { arg, path, then, other } => {
+      line(code, tab, &format!("if (is_hoas_arg({})) {{", get_arg_name(*arg, path)));
+      build_match_tree(book, code, tab + 1, then, leaf);
+      line(code, tab, "}");
+      build_match_tree(book, code, tab, other, leaf);
+    }
+  }
+}
+
+// The constructor a function transmutes its call to, if it has that shape, and returns a constructor
+fn get_transmute(rules: &[runtime::program::Rule]) -> Option<u64> {
+  if rules.len() != 1 {
+    return None;
+  }
+  let runtime::program::Rule { cond, vars, body, .. } = &rules[0];
+  // Checks if it doesn't do any pattern-matching, and if its rhs only allocs one node
+  if cond.iter().any(|mat| !matches!(mat, runtime::RuleCond::Var)) || body.1.len() != 1 {
+    return None;
+  }
+  // Checks if it returns the same variables in order
+  let cell = &body.1[0];
+  if cell.len() != vars.len() {
+    return None;
+  }
+  for (i, cell) in cell.iter().enumerate() {
+    match cell {
+      runtime::RuleBodyCell::Var { index } if *index == i as u64 => {}
+      _ => return None,
+    }
+  }
+  match body.0 {
+    runtime::RuleBodyCell::Ptr { value, targ: 0, slot: 0 } if runtime::get_tag(value) == runtime::CTR => Some(runtime::get_ext(value)),
+    _ => None,
+  }
+}
+
+struct Ctx<'a> {
+  book : &'a language::rulebook::RuleBook,
+  code : &'a mut String,
+  free : &'a mut Vec<Option<(String,u64)>>,
+  vars : Vec<String>,
+  nams : u64,
+  lams : HashMap<u64, String>,
+  dups : HashMap<u64, (String,String)>,
+}
+
+impl Ctx<'_> {
+  fn fresh(&mut self, name: &str) -> String {
+    let name = format!("{}_{}", name, self.nams);
+    self.nams += 1;
+    name
+  }
+
+  fn line(&mut self, tab: u64, text: &str) {
+    line(self.code, tab, text);
+  }
+
+  // Reuses a node of the left-hand side with the same arity, if there is one
+  fn alloc_node(&mut self, arit: u64, reuse: bool) -> String {
+    if reuse {
+      for free in self.free.iter_mut() {
+        if let Some((loc, ari)) = free.clone() {
+          if ari == arit {
+            *free = None;
+            return format!("{}/*reuse:{}*/", loc, arit);
+          }
+        }
+      }
+    }
+    format!("alloc_node({})", arit)
+  }
+
+  fn alloc_lam(&mut self, tab: u64, glob: u64) -> String {
+    if let Some(got) = self.lams.get(&glob) {
+      return got.clone();
+    }
+    let name = self.fresh("lam");
+    let node = self.alloc_node(2, true);
+    self.line(tab, &format!("u64 {} = {};", name, node));
+    if glob != 0 {
+      // The sanitizer can't tell if a scopeless lambda uses its variable, so it starts erased
+      self.line(tab, &format!("link({} + 0, Era());", name));
+      self.lams.insert(glob, name.clone());
+    }
+    name
+  }
+
+  fn alloc_dup(&mut self, tab: u64, glob: u64) -> (String, String) {
+    if let Some(got) = self.dups.get(&glob) {
+      return got.clone();
+    }
+    let coln = self.fresh("col");
+    let name = self.fresh("dup");
+    let node = self.alloc_node(3, true);
+    self.line(tab, &format!("u64 {} = gen_dup();", coln));
+    self.line(tab, &format!("u64 {} = {};", name, node));
+    self.line(tab, &format!("link({} + 0, Era());", name));
+    self.line(tab, &format!("link({} + 1, Era());", name));
+    if glob != 0 {
+      self.dups.insert(glob, (coln.clone(), name.clone()));
+    }
+    (coln, name)
+  }
+
+  // Allocates a call with the given arguments, returning the node's name
+  fn alloc_call(&mut self, tab: u64, args: &[String]) -> String {
+    let name = self.fresh("cal");
+    let node = self.alloc_node(args.len() as u64, true);
+    self.line(tab, &format!("u64 {} = {};", name, node));
+    for (i, arg) in args.iter().enumerate() {
+      self.line(tab, &format!("link({} + {}, {});", name, i, arg));
+    }
+    name
+  }
+
+  fn func_name(&self, func: u64) -> String {
+    build_name(self.book.id_to_name.get(&func).unwrap_or(&format!("{}", func)))
+  }
+
+  fn build_term(&mut self, tab: u64, term: &runtime::Core) -> String {
+    match term {
+      runtime::Core::Var { bidx } => {
+        match self.vars.get(*bidx as usize) {
+          Some(var) => var.clone(),
+          None => panic!("Unbound variable."),
+        }
+      }
+      runtime::Core::Glo { glob, misc } => {
+        match *misc {
+          runtime::VAR => format!("Var({})", self.alloc_lam(tab, *glob)),
+          runtime::DP0 => {
+            let (coln, name) = self.alloc_dup(tab, *glob);
+            format!("Dp0({}, {})", coln, name)
+          }
+          runtime::DP1 => {
+            let (coln, name) = self.alloc_dup(tab, *glob);
+            format!("Dp1({}, {})", coln, name)
+          }
+          _ => panic!("Unexpected error."),
+        }
+      }
+      runtime::Core::Dup { eras, glob, expr, body } => {
+        let copy = self.fresh("cpy");
+        let dup0 = self.fresh("dp0");
+        let dup1 = self.fresh("dp1");
+        let expr = self.build_term(tab, expr);
+        self.line(tab, &format!("Ptr {} = {};", copy, expr));
+        self.line(tab, &format!("Ptr {};", dup0));
+        self.line(tab, &format!("Ptr {};", dup1));
+        // Declared out of the branch, as global variables may refer to the node later
+        let (coln, name) = match self.dups.get(glob) {
+          Some(got) => got.clone(),
+          None => {
+            let (coln, name) = (self.fresh("col"), self.fresh("dup"));
+            self.line(tab, &format!("u64 {} = 0;", coln));
+            self.line(tab, &format!("u64 {} = 0;", name));
+            if *glob != 0 {
+              self.dups.insert(*glob, (coln.clone(), name.clone()));
+            }
+            (coln, name)
+          }
+        };
+        // Numbers are copied right away
+        self.line(tab, &format!("if (get_tag({}) == U60 || get_tag({}) == F60) {{", copy, copy));
+        self.line(tab + 1, "inc_cost();");
+        self.line(tab + 1, &format!("{} = {};", dup0, copy));
+        self.line(tab + 1, &format!("{} = {};", dup1, copy));
+        self.line(tab, "} else {");
+        self.line(tab + 1, &format!("{} = gen_dup();", coln));
+        self.line(tab + 1, &format!("{} = alloc_node(3);", name));
+        self.line(tab + 1, &format!("link({} + 0, Era());", name));
+        self.line(tab + 1, &format!("link({} + 1, Era());", name));
+        if eras.0 {
+          self.line(tab + 1, &format!("link({} + 0, Era());", name));
+        }
+        if eras.1 {
+          self.line(tab + 1, &format!("link({} + 1, Era());", name));
+        }
+        self.line(tab + 1, &format!("link({} + 2, {});", name, copy));
+        self.line(tab + 1, &format!("{} = Dp0({}, {});", dup0, coln, name));
+        self.line(tab + 1, &format!("{} = Dp1({}, {});", dup1, coln, name));
+        self.line(tab, "}");
+        self.vars.push(dup0);
+        self.vars.push(dup1);
+        let body = self.build_term(tab, body);
+        self.vars.pop();
+        self.vars.pop();
+        body
+      }
+      runtime::Core::Sup { val0, val1 } => {
+        let name = self.fresh("sup");
+        let val0 = self.build_term(tab, val0);
+        let val1 = self.build_term(tab, val1);
+        let coln = self.fresh("col");
+        let node = self.alloc_node(2, true);
+        self.line(tab, &format!("u64 {} = gen_dup();", coln));
+        self.line(tab, &format!("u64 {} = {};", name, node));
+        self.line(tab, &format!("link({} + 0, {});", name, val0));
+        self.line(tab, &format!("link({} + 1, {});", name, val1));
+        format!("Sup({}, {})", coln, name)
+      }
+      runtime::Core::Let { expr, body } => {
+        let expr = self.build_term(tab, expr);
+        self.vars.push(expr);
+        let body = self.build_term(tab, body);
+        self.vars.pop();
+        body
+      }
+      runtime::Core::Lam { eras, glob, body } => {
+        let name = self.alloc_lam(tab, *glob);
+        self.vars.push(format!("Var({})", name));
+        let body = self.build_term(tab, body);
+        self.vars.pop();
+        if *eras {
+          self.line(tab, &format!("link({} + 0, Era());", name));
+        }
+        self.line(tab, &format!("link({} + 1, {});", name, body));
+        format!("Lam({})", name)
+      }
+      runtime::Core::App { func, argm } => {
+        let name = self.fresh("app");
+        let func = self.build_term(tab, func);
+        let argm = self.build_term(tab, argm);
+        let node = self.alloc_node(2, true);
+        self.line(tab, &format!("u64 {} = {};", name, node));
+        self.line(tab, &format!("link({} + 0, {});", name, func));
+        self.line(tab, &format!("link({} + 1, {});", name, argm));
+        format!("App({})", name)
+      }
+      runtime::Core::Ctr { func, args } => {
+        let cargs : Vec<String> = args.iter().map(|arg| self.build_term(tab, arg)).collect();
+        let name = self.fresh("ctr");
+        let node = self.alloc_node(cargs.len() as u64, true);
+        self.line(tab, &format!("u64 {} = {};", name, node));
+        for (i, arg) in cargs.iter().enumerate() {
+          self.line(tab, &format!("link({} + {}, {});", name, i, arg));
+        }
+        format!("Ctr({}, {})", self.func_name(*func), name)
+      }
+      runtime::Core::Fun { func, args } => {
+        let fargs : Vec<String> = args.iter().map(|arg| self.build_term(tab, arg)).collect();
+        let fnam = self.func_name(*func);
+        // Inlined U60.if and U60.swap
+        if (*func == runtime::U60_IF || *func == runtime::U60_SWAP) && fargs.len() == 3 {
+          let ret = self.fresh("ret");
+          self.line(tab, &format!("Ptr {};", ret));
+          self.line(tab, &format!("if (get_tag({}) == U60) {{", fargs[0]));
+          self.line(tab + 1, "inc_cost();");
+          if *func == runtime::U60_IF {
+            self.line(tab + 1, &format!("if (get_num({}) == 0) {{", fargs[0]));
+            self.line(tab + 2, &format!("collect({});", fargs[1]));
+            self.line(tab + 2, &format!("{} = {};", ret, fargs[2]));
+            self.line(tab + 1, "} else {");
+            self.line(tab + 2, &format!("collect({});", fargs[2]));
+            self.line(tab + 2, &format!("{} = {};", ret, fargs[1]));
+            self.line(tab + 1, "}");
+          } else {
+            let both = self.fresh("both");
+            let node = self.alloc_node(2, true);
+            self.line(tab + 1, &format!("u64 {} = {};", both, node));
+            self.line(tab + 1, &format!("if (get_num({}) == 0) {{", fargs[0]));
+            self.line(tab + 2, &format!("link({} + 0, {});", both, fargs[1]));
+            self.line(tab + 2, &format!("link({} + 1, {});", both, fargs[2]));
+            self.line(tab + 1, "} else {");
+            self.line(tab + 2, &format!("link({} + 0, {});", both, fargs[2]));
+            self.line(tab + 2, &format!("link({} + 1, {});", both, fargs[1]));
+            self.line(tab + 1, "}");
+            self.line(tab + 1, &format!("{} = Ctr({}, {});", ret, build_name("Both"), both));
+          }
+          self.line(tab, "} else {");
+          let name = self.alloc_call(tab + 1, &fargs);
+          self.line(tab + 1, &format!("{} = Fun({}, {});", ret, fnam, name));
+          self.line(tab, "}");
+          ret
+        // Other functions
+        } else {
+          let name = self.alloc_call(tab, &fargs);
+          format!("Fun({}, {})", fnam, name)
+        }
+      }
+      runtime::Core::U6O { numb } => {
+        format!("U6O({}ULL)", numb)
+      }
+      runtime::Core::F6O { numb } => {
+        format!("F6O({}ULL)", numb)
+      }
+      runtime::Core::Op2 { oper, val0, val1 } => {
+        let retx = self.fresh("ret");
+        let name = self.fresh("op2");
+        let val0 = self.build_term(tab, val0);
+        let val1 = self.build_term(tab, val1);
+        // Operates right away when both operands are numbers, avoiding the Op2 allocation
+        self.line(tab, &format!("Ptr {};", retx));
+        self.line(tab, &format!("if (get_tag({}) == U60 && get_tag({}) == U60) {{", val0, val1));
+        self.line(tab + 1, &format!("{} = U6O(u60_op({}, get_num({}), get_num({})));", retx, oper, val0, val1));
+        self.line(tab + 1, "inc_cost();");
+        self.line(tab, &format!("}} else if (get_tag({}) == F60 && get_tag({}) == F60) {{", val0, val1));
+        self.line(tab + 1, &format!("{} = F6O(f60_op({}, get_num({}), get_num({})));", retx, oper, val0, val1));
+        self.line(tab + 1, "inc_cost();");
+        self.line(tab, "} else {");
+        let node = self.alloc_node(2, false);
+        self.line(tab + 1, &format!("u64 {} = {};", name, node));
+        self.line(tab + 1, &format!("link({} + 0, {});", name, val0));
+        self.line(tab + 1, &format!("link({} + 1, {});", name, val1));
+        self.line(tab + 1, &format!("{} = Op2({}, {});", retx, oper, name));
+        self.line(tab, "}");
+        retx
+      }
+    }
+  }
+}
+
+pub fn build_function_rule_rhs(
+  book : &language::rulebook::RuleBook,
+  code : &mut String,
+  free : &mut Vec<Option<(String,u64)>>,
+  tab  : u64,
+  term : &runtime::Core,
+  rvrs : &[runtime::RuleVar],
+) -> String {
+  let mut vars = vec![];
+  for runtime::RuleVar { param, path, erase: _ } in rvrs {
+    let name = get_arg_name(*param, path);
+    if let Some((field, parent)) = path.split_last() {
+      line(code, tab, &format!("Ptr {} = load_arg({}, {});", name, get_arg_name(*param, parent), field));
+    }
+    vars.push(name);
+  }
+  let mut ctx = Ctx { book, code, free, vars, nams: 0, lams: HashMap::new(), dups: HashMap::new() };
+  ctx.build_term(tab, term)
+}
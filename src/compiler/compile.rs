@@ -14,153 +14,104 @@ pub fn build_name(name: &str) -> String {
   format!("_{}_", name)
 }
 
-pub fn build_code(code: &str) -> Result<(String,String), String> {
+// Compiles the code, tree-shaken from the given entries, if any, returning what was removed
+pub fn build_code(code: &str, opts: &runtime::Optimizations, entries: Option<&[String]>) -> Result<(String, Option<language::rulebook::Shaken>), String> {
   let file = language::syntax::read_file(code)?;
-  let book = language::rulebook::gen_rulebook(&file);
-  runtime::gen_functions(&book);
-  Ok(build_rulebook(&book))
+  language::rulebook::check_file(&file)?;
+  let mut book = language::rulebook::gen_rulebook(&file);
+  let shaken = entries.map(|entries| language::rulebook::tree_shake(&mut book, entries)).transpose()?;
+  Ok((build_rulebook(&book, opts), shaken))
 }
 
-pub fn build_rulebook(book: &language::rulebook::RuleBook) -> (String, String) {
-  // precomp ids
-  let mut precomp_ids = String::new();
+// Builds a Rust module with the rulebook's functions, and a `FUNCTIONS` list to install them with
+// `RuntimeBuilder::add_compiled_code`, along with the code the rulebook came from.
+pub fn build_rulebook(book: &language::rulebook::RuleBook, opts: &runtime::Optimizations) -> String {
+  let mut code = String::new();
+  line(&mut code, 0, "// Generated by the HVM compiler. Don't edit it by hand.");
+  line(&mut code, 0, "");
+  line(&mut code, 0, "use hvm::runtime::{*};");
+  line(&mut code, 0, "use std::sync::atomic::Ordering;");
+  line(&mut code, 0, "");
+
+  // ids
   for (id, name) in itertools::sorted(book.id_to_name.iter()) {
-    //if id >= &runtime::PRECOMP_COUNT {
-    line(&mut precomp_ids, 0, &format!("pub const {} : u64 = {};", &build_name(name), id));
-    //}
+    line(&mut code, 0, &format!("pub const {} : u64 = {};", &build_name(name), id));
   }
+  line(&mut code, 0, "");
 
-  // precomp els
-  let mut precomp_els = String::new();
+  // functions
+  let mut funs = runtime::gen_functions(book);
+  runtime::optimize(&mut funs, opts);
+  let mut compiled = String::new();
   for id in itertools::sorted(book.id_to_name.keys()) {
     if id >= &runtime::PRECOMP_COUNT {
       let name = book.id_to_name.get(id).unwrap();
-      line(&mut precomp_els, 0, &format!(r#"  Precomp {{"#));
-      line(&mut precomp_els, 0, &format!(r#"    id: {},"#, &build_name(&name)));
-      line(&mut precomp_els, 0, &format!(r#"    name: "{}","#, &name));
-      line(&mut precomp_els, 0, &format!(r#"    smap: &{:?},"#, book.id_to_smap.get(id).unwrap()));
-      if *book.ctr_is_fun.get(name).unwrap_or(&false) {
-        line(&mut precomp_els, 0, &format!(r#"    funs: Some(PrecompFuns {{"#));
-        line(&mut precomp_els, 0, &format!(r#"      visit: {}_visit,"#, &build_name(&name)));
-        line(&mut precomp_els, 0, &format!(r#"      apply: {}_apply,"#, &build_name(&name)));
-        line(&mut precomp_els, 0, &format!(r#"    }}),"#));
-      } else {
-        line(&mut precomp_els, 0, &format!(r#"    funs: None,"#));
-      }
-      line(&mut precomp_els, 0, &format!(r#"  }},"#));
-    }
-  }
-
-  // precomp fns
-  let mut precomp_fns = String::new();
-  for id in itertools::sorted(book.id_to_name.keys()) {
-    if id >= &runtime::PRECOMP_COUNT {
-      let name = book.id_to_name.get(id).unwrap();
-      if let Some(rules) = book.rule_group.get(name) {
-        let (got_visit, got_apply) = build_function(book, &name, &rules.1);
-        line(&mut precomp_fns, 0, &format!("{}", got_visit));
-        line(&mut precomp_fns, 0, &format!("{}", got_apply));
+      if let Some(func) = funs.get(id) {
+        let (got_visit, got_apply) = build_function(book, name, func);
+        line(&mut code, 0, &got_visit);
+        line(&mut code, 0, &got_apply);
+        line(&mut compiled, 1, "CompiledFunction {");
+        line(&mut compiled, 2, &format!("id: {},", &build_name(name)));
+        line(&mut compiled, 2, &format!("name: {:?},", name));
+        line(&mut compiled, 2, &format!("smap: &{:?},", book.id_to_smap.get(id).unwrap()));
+        line(&mut compiled, 2, &format!("visit: {}_visit,", &build_name(name)));
+        line(&mut compiled, 2, &format!("apply: {}_apply,", &build_name(name)));
+        line(&mut compiled, 1, "},");
       }
     }
   }
 
-  // fast visit
-  let mut fast_visit = String::new();
-  line(&mut fast_visit, 7, &format!("match fid {{"));
-  for id in itertools::sorted(book.id_to_name.keys()) {
-    if id >= &runtime::PRECOMP_COUNT {
-      let name = book.id_to_name.get(id).unwrap();
-      if let Some(rules) = book.rule_group.get(name) {
-        line(&mut fast_visit, 8, &format!("{} => {{", &build_name(&name)));
-        line(&mut fast_visit, 9, &format!("if {}_visit(ReduceCtx {{ heap, prog, tid, hold, term, visit, redex, cont: &mut cont, host: &mut host }}) {{", &build_name(&name)));
-        line(&mut fast_visit, 10, &format!("continue 'visit;"));
-        line(&mut fast_visit, 9, &format!("}} else {{"));
-        line(&mut fast_visit, 10, &format!("break 'visit;"));
-        line(&mut fast_visit, 9, &format!("}}"));
-        line(&mut fast_visit, 8, &format!("}}"));
-      };
-    }
-  }
-  line(&mut fast_visit, 8, &format!("_ => {{}}"));
-  line(&mut fast_visit, 7, &format!("}}"));
-
-  // fast apply
-  let mut fast_apply = String::new();
-  line(&mut fast_apply, 8, &format!("match fid {{"));
-  for id in itertools::sorted(book.id_to_name.keys()) {
-    if id >= &runtime::PRECOMP_COUNT {
-      let name = book.id_to_name.get(id).unwrap();
-      if let Some(rules) = book.rule_group.get(name) {
-        line(&mut fast_apply, 9, &format!("{} => {{", &build_name(&name)));
-        line(&mut fast_apply, 10, &format!("if {}_apply(ReduceCtx {{ heap, prog, tid, hold, term, visit, redex, cont: &mut cont, host: &mut host }}) {{", &build_name(&name)));
-        line(&mut fast_apply, 11, &format!("continue 'work;"));
-        line(&mut fast_apply, 10, &format!("}} else {{"));
-        line(&mut fast_apply, 11, &format!("break 'apply;"));
-        line(&mut fast_apply, 10, &format!("}}"));
-        line(&mut fast_apply, 9, &format!("}}"));
-      };
-    }
-  }
-  line(&mut fast_apply, 9, &format!("_ => {{}}"));
-  line(&mut fast_apply, 8, &format!("}}"));
-
-  // precomp.rs
-  let precomp_rs : &str = include_str!("./../runtime/base/precomp.rs");
-  let precomp_rs = precomp_rs.replace("//[[CODEGEN:PRECOMP-IDS]]//\n", &precomp_ids);
-  let precomp_rs = precomp_rs.replace("//[[CODEGEN:PRECOMP-ELS]]//\n", &precomp_els);
-  let precomp_rs = precomp_rs.replace("//[[CODEGEN:PRECOMP-FNS]]//\n", &precomp_fns);
-
-  // reducer.rs
-  let reducer_rs : &str = include_str!("./../runtime/base/reducer.rs");
-  let reducer_rs = reducer_rs.replace("//[[CODEGEN:FAST-VISIT]]//\n", &fast_visit);
-  let reducer_rs = reducer_rs.replace("//[[CODEGEN:FAST-APPLY]]//\n", &fast_apply);
+  // registration
+  line(&mut code, 0, "pub const FUNCTIONS : &[CompiledFunction] = &[");
+  code.push_str(&compiled);
+  line(&mut code, 0, "];");
 
-  return (precomp_rs, reducer_rs);
+  code
 }
 
 pub fn build_function(
   book  : &language::rulebook::RuleBook,
   fname : &str,
-  rules : &[language::syntax::Rule],
+  func  : &runtime::Function,
 ) -> (String, String) {
   if let runtime::Function::Interpreted {
     smap: fn_smap,
     visit: fn_visit,
     apply: fn_apply,
-  } = runtime::build_function(book, fname, rules) {
+  } = func {
 
     // Visit
     // -----
 
     let mut visit = String::new();
-    line(&mut visit, 0, &format!("#[inline(always)]"));
+    line(&mut visit, 0, "#[inline(always)]");
     line(&mut visit, 0, &format!("pub fn {}_visit(ctx: ReduceCtx) -> bool {{", &build_name(fname)));
     if fn_visit.strict_idx.is_empty() {
       line(&mut visit, 1, "return false;");
     } else {
-      line(&mut visit, 1, &format!("let mut vlen = 0;"));
-      line(&mut visit, 1, &format!("let vbuf = unsafe {{ ctx.heap.vbuf.get_unchecked(ctx.tid) }};"));
+      line(&mut visit, 1, "let mut vlen = 0;");
+      line(&mut visit, 1, "let vbuf = unsafe { ctx.heap.vbuf.get_unchecked(ctx.tid) };");
       for sidx in &fn_visit.strict_idx {
         line(&mut visit, 1, &format!("if !is_whnf(load_arg(ctx.heap, ctx.term, {})) {{", *sidx));
         line(&mut visit, 2, &format!("unsafe {{ vbuf.get_unchecked(vlen) }}.store(get_loc(ctx.term, {}), Ordering::Relaxed);", *sidx));
-        line(&mut visit, 2, &format!("vlen += 1"));
-        line(&mut visit, 1, &format!("}}"));
+        line(&mut visit, 2, "vlen += 1");
+        line(&mut visit, 1, "}");
       }
-      line(&mut visit, 1, &format!("if vlen == 0 {{"));
-      line(&mut visit, 2, &format!("return false;"));
-      line(&mut visit, 1, &format!("}} else {{"));
-      line(&mut visit, 2, &format!("let goup = ctx.redex.insert(ctx.tid, new_redex(*ctx.host, *ctx.cont, vlen as u64));"));
+      line(&mut visit, 1, "if vlen == 0 {");
+      line(&mut visit, 2, "return false;");
+      line(&mut visit, 1, "} else {");
+      line(&mut visit, 2, "let goup = ctx.redex.insert(ctx.tid, new_redex(*ctx.host, *ctx.cont, vlen as u64));");
       for i in 0 .. fn_visit.strict_idx.len() {
         line(&mut visit, 2, &format!("if {} < vlen - 1 {{", i));
         line(&mut visit, 3, &format!("ctx.visit.push(new_visit(unsafe {{ vbuf.get_unchecked({}).load(Ordering::Relaxed) }}, ctx.hold, goup));", i));
-        line(&mut visit, 2, &format!("}}"));
+        line(&mut visit, 2, "}");
       }
-      line(&mut visit, 2, &format!("*ctx.cont = goup;"));
-      line(&mut visit, 2, &format!("*ctx.host = unsafe {{ vbuf.get_unchecked(vlen - 1).load(Ordering::Relaxed) }};"));
-      line(&mut visit, 2, &format!("return true;"));
-      line(&mut visit, 1, &format!("}}"));
+      line(&mut visit, 2, "*ctx.cont = goup;");
+      line(&mut visit, 2, "*ctx.host = unsafe { vbuf.get_unchecked(vlen - 1).load(Ordering::Relaxed) };");
+      line(&mut visit, 2, "return true;");
+      line(&mut visit, 1, "}");
     }
-    line(&mut visit, 0, &format!("}}"));
+    line(&mut visit, 0, "}");
 
     // Apply
     // -----
@@ -180,7 +131,7 @@ pub fn build_function(
       let runtime::program::Rule { hoas, cond, vars, core, body, .. } = &fn_apply.rules[0];
       // Checks if it doesn't do any pattern-matching
       for mat in cond {
-        if *mat != runtime::Var(0) {
+        if !matches!(mat, runtime::RuleCond::Var) {
           break 'TransmuteOptimization;
         }
       }
@@ -194,8 +145,8 @@ pub fn build_function(
         break 'TransmuteOptimization;
       }
       // Checks if it returns the same variables in order
-      for i in 0 .. cell.len() {
-        if let runtime::RuleBodyCell::Var { index } = cell[i] {
+      for (i, arg) in cell.iter().enumerate() {
+        if let runtime::RuleBodyCell::Var { index } = *arg {
           if index != i as u64 {
             break 'TransmuteOptimization;
           }
@@ -203,29 +154,35 @@ pub fn build_function(
           break 'TransmuteOptimization;
         }
       }
-      // Gets the new ptr
+      // Gets the new ptr, which must be a constructor's
       let ptr;
       if let runtime::RuleBodyCell::Ptr { value, targ: 0, slot: 0 } = body.0 {
         ptr = value;
       } else {
         break 'TransmuteOptimization;
       }
+      if runtime::get_tag(ptr) != runtime::CTR {
+        break 'TransmuteOptimization;
+      }
       // If all is true, compile as a transmuter
-      line(&mut apply, 0, &format!("#[inline(always)]"));
+      line(&mut apply, 0, "#[inline(always)]");
       line(&mut apply, 0, &format!("pub fn {}_apply(ctx: ReduceCtx) -> bool {{", &build_name(fname)));
       line(&mut apply, 1, &format!("let done = Ctr({}, get_loc(ctx.term, 0));", runtime::get_ext(ptr)));
       line(&mut apply, 1, "link(ctx.heap, *ctx.host, done);");
       line(&mut apply, 1, "return false;");
-      line(&mut apply, 0, &format!("}}"));
+      line(&mut apply, 0, "}");
     }
 
     // Normal Function
     // ---------------
     
-    if apply.len() == 0 {
-      line(&mut apply, 0, &format!("#[inline(always)]"));
+    if apply.is_empty() {
+      line(&mut apply, 0, "#[inline(always)]");
       line(&mut apply, 0, &format!("pub fn {}_apply(ctx: ReduceCtx) -> bool {{", &build_name(fname)));
 
+      // Runs the function's tail calls on numbers as a loop
+      build_function_loop(book, &mut apply, fname, fn_visit, fn_apply);
+
       // Loads strict arguments
       for i in 0 .. fn_smap.len() {
         line(&mut apply, 1, &format!("let arg{} = load_arg(ctx.heap, ctx.term, {});", i, i));
@@ -236,125 +193,313 @@ pub fn build_function(
         if *is_strict {
           line(&mut apply, 1, &format!("if get_tag(arg{}) == SUP {{", i));
           line(&mut apply, 2, &format!("fun::superpose(ctx.heap, &ctx.prog.aris, ctx.tid, *ctx.host, ctx.term, arg{}, {});", i, i));
+          line(&mut apply, 2, "return true;");
           line(&mut apply, 1, "}");
         }
       }
 
-      // For each rule condition vector
-      for (r, rule) in fn_apply.rules.iter().enumerate() {
-        let mut matched: Vec<String> = Vec::new();
+      // Selects the rule to apply with the function's decision tree
+      build_match_tree(book, &mut apply, 1, &fn_apply.tree, &mut |code, tab, index| {
+        build_function_rule(book, code, tab, &fn_apply.rules[index], fn_visit.strict_map.len() as u64);
+      });
 
-        // Tests each rule condition (ex: `get_tag(args[0]) == SUCC`)
-        for (i, cond) in rule.cond.iter().enumerate() {
-          let i = i as u64;
-          if runtime::get_tag(*cond) == runtime::U60 {
-            let same_tag = format!("get_tag(arg{}) == U60", i);
-            let same_val = format!("get_num(arg{}) == {}", i, runtime::get_num(*cond));
-            matched.push(format!("({} && {})", same_tag, same_val));
-          }
-          if runtime::get_tag(*cond) == runtime::F60 {
-            let same_tag = format!("get_tag(arg{}) == F60", i);
-            let same_val = format!("get_num(arg{}) == {}", i, runtime::get_num(*cond));
-            matched.push(format!("({} && {})", same_tag, same_val));
-          }
-          if runtime::get_tag(*cond) == runtime::CTR {
-            let some_tag = format!("get_tag(arg{}) == CTR", i);
-            let some_ext = format!("get_ext(arg{}) == {}", i, runtime::get_ext(*cond));
-            matched.push(format!("({} && {})", some_tag, some_ext));
-          }
-            // If this is a strict argument, then we're in a default variable
-          if runtime::get_tag(*cond) == runtime::VAR && fn_visit.strict_map[i as usize] {
-
-            // This is a Kind2-specific optimization. Check 'HOAS_OPT'.
-            if rule.hoas && r != fn_apply.rules.len() - 1 {
-
-              // Matches number literals
-              let is_num = format!("({} || {})",
-                format!("get_tag(arg{}) == U60", i),
-                format!("get_tag(arg{}) == F60", i));
-
-              // Matches constructor labels
-              let is_ctr = format!("({} && {})",
-                format!("get_tag(arg{}) == CTR", i),
-                format!("arity_of(heap, arg{}) == 0u", i));
-
-              // Matches HOAS numbers and constructors
-              let is_hoas_ctr_num = format!("({} && {} && {})",
-                format!("get_tag(arg{}) == CTR", i),
-                format!("get_ext(arg{}) >= HOAS_CT0", i),
-                format!("get_ext(arg{}) <= HOAS_F60", i));
-
-              matched.push(format!("({} || {} || {})", is_num, is_ctr, is_hoas_ctr_num));
-
-            // Only match default variables on CTRs, U60s, F60s
-            } else {
-              let is_ctr = format!("get_tag(arg{}) == CTR", i);
-              let is_u60 = format!("get_tag(arg{}) == U60", i);
-              let is_f60 = format!("get_tag(arg{}) == F60", i);
-              matched.push(format!("({} || {} || {})", is_ctr, is_u60, is_f60));
-            }
+      line(&mut apply, 0, "}");
+    }
+
+    (visit, apply)
+  } else {
+    panic!("Unexpected error.");
+  }
+}
 
+// Tail Loop Optimization
+// ----------------------
+// When every argument of a call is a number, the rules calling the function back, with numbers
+// computed from them, run as a loop on local variables, instead of allocating a call each. Lazy
+// arguments are fine, as the step it replaces also operates on numbers right away. Each iteration
+// counts the rewrites of that step: the rule's, and its operations' and dups'. Once another rule
+// matches, or an operation mixes U60s and F60s, the numbers are written back to the call, which is
+// then reduced as usual. Reductions with a rewrite limit don't loop, as they check it each rewrite.
+// Every `LOOP_LIMIT_CHECK` steps, the rewrites so far are counted, so that progress shows, and a
+// limit set since, as cancelling an evaluation does, leaves the loop the same way.
+
+fn build_function_loop(
+  book  : &language::rulebook::RuleBook,
+  code  : &mut String,
+  fname : &str,
+  visit : &runtime::VisitObj,
+  apply : &runtime::ApplyObj,
+) {
+  // Builds the code computing a number, or with `tail`, the arguments of a call to the function,
+  // returning the variables holding them, and the rewrites it takes
+  fn build_term(code: &mut String, tab: u64, nams: &mut u64, vars: &mut Vec<String>, term: &runtime::Core, fid: u64, tail: bool) -> Option<(Vec<String>, u64)> {
+    match term {
+      runtime::Core::Var { bidx } if !tail => {
+        Some((vec![vars[*bidx as usize].clone()], 0))
+      }
+      runtime::Core::U6O { numb } if !tail => {
+        Some((vec![format!("U6O({})", numb)], 0))
+      }
+      runtime::Core::F6O { numb } if !tail => {
+        Some((vec![format!("F6O({})", numb)], 0))
+      }
+      runtime::Core::Op2 { oper, val0, val1 } if !tail => {
+        let (val0, cost0) = build_term(code, tab, nams, vars, val0, fid, false)?;
+        let (val1, cost1) = build_term(code, tab, nams, vars, val1, fid, false)?;
+        let name = format!("num_{}", nams);
+        *nams += 1;
+        let oper = get_oper_fn(*oper)?;
+        line(code, tab, &format!("let {} = if get_tag({}) == U60 && get_tag({}) == U60 {{", name, val0[0], val1[0]));
+        line(code, tab + 1, &format!("U6O(u60::{}(get_num({}), get_num({})))", oper, val0[0], val1[0]));
+        line(code, tab, &format!("}} else if get_tag({}) == F60 && get_tag({}) == F60 {{", val0[0], val1[0]));
+        line(code, tab + 1, &format!("F6O(f60::{}(get_num({}), get_num({})))", oper, val0[0], val1[0]));
+        line(code, tab, "} else {");
+        line(code, tab + 1, "break;");
+        line(code, tab, "};");
+        Some((vec![name], cost0 + cost1 + 1))
+      }
+      runtime::Core::Let { expr, body } => {
+        let (expr, cost0) = build_term(code, tab, nams, vars, expr, fid, false)?;
+        vars.push(expr[0].clone());
+        let body = build_term(code, tab, nams, vars, body, fid, tail);
+        vars.pop();
+        let (body, cost1) = body?;
+        Some((body, cost0 + cost1))
+      }
+      // Dups of numbers copy them, in a rewrite
+      runtime::Core::Dup { expr, body, .. } => {
+        let (expr, cost0) = build_term(code, tab, nams, vars, expr, fid, false)?;
+        vars.push(expr[0].clone());
+        vars.push(expr[0].clone());
+        let body = build_term(code, tab, nams, vars, body, fid, tail);
+        vars.truncate(vars.len() - 2);
+        let (body, cost1) = body?;
+        Some((body, cost0 + cost1 + 1))
+      }
+      runtime::Core::Fun { func, args } if tail && *func == fid => {
+        let mut vals = Vec::new();
+        let mut cost = 1;
+        for arg in args {
+          let (val, arg_cost) = build_term(code, tab, nams, vars, arg, fid, false)?;
+          vals.extend(val);
+          cost += arg_cost;
+        }
+        Some((vals, cost))
+      }
+      _ => None,
+    }
+  }
+  // Builds the step of a rule calling the function back, if it does, which assigns the new arguments
+  fn build_step(code: &mut String, tab: u64, rule: &runtime::program::Rule, fid: u64) -> bool {
+    if !rule.cond.iter().all(|cond| matches!(cond, runtime::RuleCond::Var | runtime::RuleCond::U6O { .. } | runtime::RuleCond::F6O { .. })) {
+      return false;
+    }
+    let mut step = String::new();
+    let mut nams = 0;
+    let mut vars = rule.vars.iter().map(get_var).collect();
+    if let Some((args, cost)) = build_term(&mut step, tab, &mut nams, &mut vars, &rule.core, fid, true) {
+      code.push_str(&step);
+      // Through temporaries, as the new arguments may be the old ones, swapped
+      for (i, arg) in args.iter().enumerate() {
+        line(code, tab, &format!("let next{} = {};", i, arg));
+      }
+      for i in 0 .. args.len() {
+        line(code, tab, &format!("arg{} = next{};", i, i));
+      }
+      line(code, tab, &format!("cost += {};", cost));
+      line(code, tab, "continue;");
+      true
+    } else {
+      false
+    }
+  }
+  // Builds the code walking the match tree on numbers, stepping on the rules calling the function
+  // back, and leaving the loop on the others
+  fn build_tree(code: &mut String, tab: u64, tree: &runtime::MatchTree, rules: &[runtime::program::Rule], fid: u64) {
+    match tree {
+      runtime::MatchTree::Rule { index } => {
+        if !build_step(code, tab, &rules[*index], fid) {
+          line(code, tab, "break;");
+        }
+      }
+      runtime::MatchTree::Switch { arg, path, u60s, f60s, value, .. } if path.is_empty() => {
+        for (tag, cases) in [("U60", u60s), ("F60", f60s)] {
+          if !cases.is_empty() {
+            line(code, tab, &format!("if get_tag(arg{}) == {} {{", arg, tag));
+            line(code, tab + 1, &format!("match get_num(arg{}) {{", arg));
+            for (key, tree) in cases {
+              line(code, tab + 2, &format!("{} => {{", key));
+              build_tree(code, tab + 3, tree, rules, fid);
+              line(code, tab + 2, "}");
+            }
+            line(code, tab + 2, "_ => {}");
+            line(code, tab + 1, "}");
+            line(code, tab, "}");
           }
         }
+        build_tree(code, tab, value, rules, fid);
+      }
+      _ => {
+        line(code, tab, "break;");
+      }
+    }
+  }
 
-        let conds = if matched.is_empty() { String::from("true") } else { matched.join(" && ") };
-        line(&mut apply, 1, &format!("if {} {{", conds));
+  let arity = visit.strict_map.len();
+  let fid = book.name_to_id[fname];
+  if arity == 0 || apply.rules.iter().any(|rule| rule.hoas) {
+    return;
+  }
+  if !apply.rules.iter().any(|rule| build_step(&mut String::new(), 0, rule, fid)) {
+    return;
+  }
+  const LOOP_LIMIT_CHECK : u64 = 0x10000;
+  let is_num = (0 .. arity).map(|i| format!("(get_tag(arg{}) == U60 || get_tag(arg{}) == F60)", i, i)).collect::<Vec<_>>().join(" && ");
+  line(code, 1, "if !has_limit(ctx.heap, ctx.tid) {");
+  for i in 0 .. arity {
+    line(code, 2, &format!("let mut arg{} = load_arg(ctx.heap, ctx.term, {});", i, i));
+  }
+  line(code, 2, &format!("if {} {{", is_num));
+  line(code, 3, "let mut cost = 0;");
+  line(code, 3, "let mut steps : u64 = 0;");
+  line(code, 3, "loop {");
+  line(code, 4, "steps += 1;");
+  line(code, 4, &format!("if steps % {} == 0 {{", LOOP_LIMIT_CHECK));
+  line(code, 5, "add_cost(ctx.heap, ctx.tid, cost);");
+  line(code, 5, "cost = 0;");
+  line(code, 5, "if has_limit(ctx.heap, ctx.tid) {");
+  line(code, 6, "break;");
+  line(code, 5, "}");
+  line(code, 4, "}");
+  build_tree(code, 4, &apply.tree, &apply.rules, fid);
+  line(code, 3, "}");
+  line(code, 3, "add_cost(ctx.heap, ctx.tid, cost);");
+  for i in 0 .. arity {
+    line(code, 3, &format!("link(ctx.heap, get_loc(ctx.term, {}), arg{});", i, i));
+  }
+  line(code, 2, "}");
+  line(code, 1, "}");
+}
 
-        // Increments the gas count
-        line(&mut apply, 2, "inc_cost(ctx.heap, ctx.tid);");
+// The name of the `u60` and `f60` function computing an operation
+fn get_oper_fn(oper: u64) -> Option<&'static str> {
+  match oper {
+    runtime::ADD => Some("add"),
+    runtime::SUB => Some("sub"),
+    runtime::MUL => Some("mul"),
+    runtime::DIV => Some("div"),
+    runtime::MOD => Some("mdl"),
+    runtime::AND => Some("and"),
+    runtime::OR  => Some("or"),
+    runtime::XOR => Some("xor"),
+    runtime::SHL => Some("shl"),
+    runtime::SHR => Some("shr"),
+    runtime::LTN => Some("ltn"),
+    runtime::LTE => Some("lte"),
+    runtime::EQL => Some("eql"),
+    runtime::GTE => Some("gte"),
+    runtime::GTN => Some("gtn"),
+    runtime::NEQ => Some("neq"),
+    _            => None,
+  }
+}
 
-        // Builds the free vector
-        let mut free : Vec<Option<(String,u64)>> = vec![];
-        for (idx, ari) in &rule.free {
-          free.push(Some((format!("get_loc(arg{}, 0)", idx), *ari)));
-        }
-        free.push(Some(("get_loc(ctx.term, 0)".to_string(), fn_visit.strict_map.len() as u64)));
+// Builds the code applying a rule, once it matched
+fn build_function_rule(book: &language::rulebook::RuleBook, code: &mut String, tab: u64, rule: &runtime::program::Rule, arity: u64) {
+  // Increments the gas count
+  line(code, tab, "inc_cost(ctx.heap, ctx.tid);");
 
-        // Builds the right-hand side term (ex: `(Succ (Add a b))`)
-        //let done = build_function_rule_body(&mut apply, 2, &rule.body, &rule.vars);
-        let done = build_function_rule_rhs(book, &mut apply, &mut free, 2, &rule.core, &rule.vars);
-        line(&mut apply, 2, &format!("let done = {};", done));
+  // Builds the free vector
+  let mut free : Vec<Option<(String,u64)>> = vec![];
+  for (idx, path, ari) in &rule.free {
+    free.push(Some((format!("get_loc({}, 0)", get_arg_name(*idx, path)), *ari)));
+  }
+  free.push(Some(("get_loc(ctx.term, 0)".to_string(), arity)));
 
-        // Links the host location to it
-        line(&mut apply, 2, "link(ctx.heap, *ctx.host, done);");
+  // Builds the right-hand side term (ex: `(Succ (Add a b))`)
+  let done = build_function_rule_rhs(book, code, &mut free, tab, &rule.core, &rule.vars);
+  line(code, tab, &format!("let done = {};", done));
 
-        // Collects unused variables (none in this example)
-        for dynvar @ runtime::RuleVar { param: _, field: _, erase } in rule.vars.iter() {
-          if *erase {
-            line(&mut apply, 2, &format!("collect(ctx.heap, &ctx.prog.aris, ctx.tid, {});", get_var(dynvar)));
-          }
-        }
+  // Links the host location to it
+  line(code, tab, "link(ctx.heap, *ctx.host, done);");
+
+  // Collects unused variables
+  for dynvar @ runtime::RuleVar { param: _, path: _, erase } in rule.vars.iter() {
+    if *erase {
+      line(code, tab, &format!("collect(ctx.heap, &ctx.prog.aris, ctx.tid, {});", get_var(dynvar)));
+    }
+  }
+
+  // Clears the matched ctrs (the `(Succ ...)` and the `(Add ...)` ctrs)
+  for (loc, ari) in free.iter().flatten() {
+    line(code, tab, &format!("free(ctx.heap, ctx.tid, {}, {});", loc, ari));
+  }
+
+  let ret_ptr = match rule.body.0 {
+    runtime::RuleBodyCell::Val { value }     => value,
+    runtime::RuleBodyCell::Ptr { value, .. } => value,
+    runtime::RuleBodyCell::Var { .. }        => runtime::Var(0),
+  };
+  line(code, tab, &format!("return {};", if runtime::is_whnf(ret_ptr) { "false" } else { "true" }));
+}
 
-        // Clears the matched ctrs (the `(Succ ...)` and the `(Add ...)` ctrs)
-        for must_free in &free {
-          if let Some((loc, ari)) = must_free {
-            line(&mut apply, 2, &format!("free(ctx.heap, ctx.tid, {}, {});", loc, ari));
+// Builds the code walking a match tree, as nested `if`s and `match`es on the loaded arguments and
+// fields, with `leaf` building the code applying each rule. Branches return, so missing ones fall
+// through to the next test: constructors and numbers not listed go to `value`, other terms to
+// `other`. Fields are loaded once their constructor matched, and reduced first.
+pub fn build_match_tree(
+  book : &language::rulebook::RuleBook,
+  code : &mut String,
+  tab  : u64,
+  tree : &runtime::MatchTree,
+  leaf : &mut dyn FnMut(&mut String, u64, usize),
+) {
+  match tree {
+    runtime::MatchTree::Rule { index } => {
+      leaf(code, tab, *index);
+    }
+    runtime::MatchTree::Fail => {
+      line(code, tab, "return false;");
+    }
+    runtime::MatchTree::Switch { arg, path, ctrs, u60s, f60s, value, other } => {
+      let name = get_arg_name(*arg, path);
+      if let Some((field, parent)) = path.split_last() {
+        let loc = format!("get_loc({}, {})", get_arg_name(*arg, parent), field);
+        line(code, tab, &format!("let {} = load_ptr(ctx.heap, {});", name, loc));
+        line(code, tab, &format!("if !is_whnf({}) {{", name));
+        line(code, tab + 1, &format!("return fun::visit_field(ctx, {});", loc));
+        line(code, tab, "}");
+        line(code, tab, &format!("if get_tag({}) == SUP {{", name));
+        line(code, tab + 1, &format!("fun::superpose_field(ctx.heap, &ctx.prog.aris, ctx.tid, *ctx.host, ctx.term, {}, &{:?});", arg, path));
+        line(code, tab + 1, "return true;");
+        line(code, tab, "}");
+      }
+      for (tag, get, cases) in [("CTR", "get_ext", ctrs), ("U60", "get_num", u60s), ("F60", "get_num", f60s)] {
+        if !cases.is_empty() {
+          line(code, tab, &format!("if get_tag({}) == {} {{", name, tag));
+          line(code, tab + 1, &format!("match {}({}) {{", get, name));
+          for (key, tree) in cases {
+            let name = if tag == "CTR" { build_name(book.id_to_name.get(key).unwrap_or(&format!("{}", key))) } else { format!("{}", key) };
+            line(code, tab + 2, &format!("{} => {{", name));
+            build_match_tree(book, code, tab + 3, tree, leaf);
+            line(code, tab + 2, "}");
           }
+          line(code, tab + 2, "_ => {}");
+          line(code, tab + 1, "}");
+          line(code, tab, "}");
         }
-        
-        //for (i, arity) in &rule.free {
-          //let i = *i as u64;
-          //line(&mut apply, 2, &format!("free(ctx.heap, ctx.tid, get_loc(arg{}, 0), {});", i, arity));
-        //}
-        //line(&mut apply, 2, &format!("free(ctx.heap, ctx.tid, get_loc(ctx.term, 0), {});", fn_visit.strict_map.len()));
-        
-        let ret_ptr = match rule.body.0 {
-          runtime::RuleBodyCell::Val { value }     => value,
-          runtime::RuleBodyCell::Ptr { value, .. } => value,
-          runtime::RuleBodyCell::Var { .. }        => runtime::Var(0),
-        };
-        line(&mut apply, 2, &format!("return {};", if runtime::is_whnf(ret_ptr) { "false" } else { "true" }));
-        //line(&mut apply, 2, &format!("return true;"));
-        line(&mut apply, 1, "}");
       }
-      line(&mut apply, 1, "return false;");
-
-      line(&mut apply, 0, "}");
+      line(code, tab, &format!("if get_tag({}) != CTR && get_tag({}) != U60 && get_tag({}) != F60 {{", name, name, name));
+      build_match_tree(book, code, tab + 1, other, leaf);
+      line(code, tab, "}");
+      build_match_tree(book, code, tab, value, leaf);
+    }
+    runtime::MatchTree::Hoas { arg, path, then, other } => {
+      line(code, tab, &format!("if is_hoas_arg(&ctx.prog.aris, {}) {{", get_arg_name(*arg, path)));
+      build_match_tree(book, code, tab + 1, then, leaf);
+      line(code, tab, "}");
+      build_match_tree(book, code, tab, other, leaf);
     }
-
-    (visit, apply)
-  } else {
-    panic!("Unexpected error.");
   }
 }
 
@@ -369,7 +514,7 @@ pub fn build_function_rule_rhs(
   fn alloc_lam(
     code : &mut String,
     tab  : u64,
-    free : &mut Vec<Option<(String,u64)>>,
+    free : &mut [Option<(String,u64)>],
     nams : &mut u64,
     lams : &mut HashMap<u64, String>,
     glob : u64,
@@ -391,13 +536,13 @@ pub fn build_function_rule_rhs(
   fn alloc_dup(
     code : &mut String,
     tab  : u64,
-    free : &mut Vec<Option<(String,u64)>>,
+    free : &mut [Option<(String,u64)>],
     nams : &mut u64,
     dups : &mut HashMap<u64, (String,String)>,
     glob : u64,
   ) -> (String, String) {
     if let Some(got) = dups.get(&glob) {
-      return got.clone();
+      got.clone()
     } else {
       let coln = fresh(nams, "col");
       let name = fresh(nams, "dup");
@@ -408,26 +553,27 @@ pub fn build_function_rule_rhs(
       if glob != 0 {
         dups.insert(glob, (coln.clone(), name.clone()));
       }
-      return (coln, name);
+      (coln, name)
     }
   }
   fn alloc_node(
-    free: &mut Vec<Option<(String,u64)>>,
+    free: &mut [Option<(String,u64)>],
     arit: u64,
   ) -> String {
     // This will avoid calls to alloc() by reusing nodes from the left-hand side. Sadly, this seems
     // to decrease HVM's performance in some cases, probably because of added cache misses. Perhaps
     // this should be turned off. I'll decide later.
-    for i in 0 .. free.len() {
-      if let Some((loc, ari)) = free[i].clone() {
+    for node in free.iter_mut() {
+      if let Some((loc, ari)) = node.clone() {
         if ari == arit {
-          free[i] = None;
-          return format!("{}/*reuse:{}*/", loc.clone(), arit);
+          *node = None;
+          return format!("{}/*reuse:{}*/", loc, arit);
         }
       }
     }
-    return format!("alloc(ctx.heap, ctx.tid, {})", arit);
+    format!("alloc(ctx.heap, ctx.tid, {})", arit)
   }
+  #[allow(clippy::too_many_arguments)]
   fn build_term(
     book : &language::rulebook::RuleBook,
     code : &mut String,
@@ -454,15 +600,15 @@ pub fn build_function_rule_rhs(
       runtime::Core::Glo { glob, misc } => {
         match *misc {
           runtime::VAR => {
-            return format!("Var({})", alloc_lam(code, tab, free, nams, lams, *glob));
+            format!("Var({})", alloc_lam(code, tab, free, nams, lams, *glob))
           }
           runtime::DP0 => {
             let (coln, name) = alloc_dup(code, tab, free, nams, dups, *glob);
-            return format!("Dp0({}, {})", coln, name);
+            format!("Dp0({}, {})", coln, name)
           }
           runtime::DP1 => {
             let (coln, name) = alloc_dup(code, tab, free, nams, dups, *glob);
-            return format!("Dp1({}, {})", coln, name);
+            format!("Dp1({}, {})", coln, name)
           }
           _ => {
             panic!("Unexpected error.");
@@ -484,7 +630,7 @@ pub fn build_function_rule_rhs(
           line(code, tab + 1, &format!("{} = {};", dup1, copy));
           line(code, tab + 0, "} else {");
         }
-        let (coln, name) = alloc_dup(code, tab, &mut vec![], nams, dups, *glob);
+        let (coln, name) = alloc_dup(code, tab, &mut [], nams, dups, *glob);
         if eras.0 {
           line(code, tab + 1, &format!("link(ctx.heap, {} + 0, Era());", name));
         }
@@ -549,7 +695,7 @@ pub fn build_function_rule_rhs(
         for (i, arg) in cargs.iter().enumerate() {
           line(code, tab, &format!("link(ctx.heap, {} + {}, {});", name, i, arg));
         }
-        let fnam = build_name(book.id_to_name.get(&func).unwrap_or(&format!("{}", func)));
+        let fnam = build_name(book.id_to_name.get(func).unwrap_or(&format!("{}", func)));
         format!("Ctr({}, {})", fnam, name)
       }
       runtime::Core::Fun { func, args } => {
@@ -563,20 +709,20 @@ pub fn build_function_rule_rhs(
           line(code, tab + 1, &format!("if get_num({}) == 0 {{", fargs[0]));
           line(code, tab + 2, &format!("collect(ctx.heap, &ctx.prog.aris, ctx.tid, {});", fargs[1]));
           line(code, tab + 2, &format!("{} = {};", ret, fargs[2]));
-          line(code, tab + 1, &format!("}} else {{"));
+          line(code, tab + 1, "} else {");
           line(code, tab + 2, &format!("collect(ctx.heap, &ctx.prog.aris, ctx.tid, {});", fargs[2]));
           line(code, tab + 2, &format!("{} = {};", ret, fargs[1]));
-          line(code, tab + 1, &format!("}}"));
-          line(code, tab + 0, &format!("}} else {{"));
+          line(code, tab + 1, "}");
+          line(code, tab + 0, "} else {");
           let name = fresh(nams, "cal");
           line(code, tab + 1, &format!("let {} = {};", name, alloc_node(free, fargs.len() as u64)));
           for (i, arg) in fargs.iter().enumerate() {
             line(code, tab + 1, &format!("link(ctx.heap, {} + {}, {});", name, i, arg));
           }
-          let fnam = build_name(book.id_to_name.get(&func).unwrap_or(&format!("{}", func)));
+          let fnam = build_name(book.id_to_name.get(func).unwrap_or(&format!("{}", func)));
           line(code, tab + 1, &format!("{} = Fun({}, {})", ret, fnam, name));
-          line(code, tab + 0, &format!("}}"));
-          return ret;
+          line(code, tab + 0, "}");
+          ret
         // Inlined U60.swap
         } else if INLINE_NUMBERS && *func == runtime::U60_SWAP && fargs.len() == 3 {
           let ret = fresh(nams, "ret");
@@ -589,22 +735,22 @@ pub fn build_function_rule_rhs(
           line(code, tab + 2, &format!("link(ctx.heap, {} + 0, {});", both, fargs[1]));
           line(code, tab + 2, &format!("link(ctx.heap, {} + 1, {});", both, fargs[2]));
           line(code, tab + 2, &format!("{} = Ctr(BOTH, {});", ret, both));
-          line(code, tab + 1, &format!("}} else {{"));
+          line(code, tab + 1, "} else {");
           line(code, tab + 2, &format!("let {} = {};", both, alloc_node(free, 2)));
           line(code, tab + 2, &format!("link(ctx.heap, {} + 0, {});", both, fargs[2]));
           line(code, tab + 2, &format!("link(ctx.heap, {} + 1, {});", both, fargs[1]));
           line(code, tab + 2, &format!("{} = Ctr(BOTH, {});", ret, both));
-          line(code, tab + 1, &format!("}}"));
-          line(code, tab + 0, &format!("}} else {{"));
+          line(code, tab + 1, "}");
+          line(code, tab + 0, "} else {");
           let name = fresh(nams, "cal");
           line(code, tab + 1, &format!("let {} = {};", name, alloc_node(free, fargs.len() as u64)));
           for (i, arg) in fargs.iter().enumerate() {
             line(code, tab + 1, &format!("link(ctx.heap, {} + {}, {});", name, i, arg));
           }
-          let fnam = build_name(book.id_to_name.get(&func).unwrap_or(&format!("{}", func)));
+          let fnam = build_name(book.id_to_name.get(func).unwrap_or(&format!("{}", func)));
           line(code, tab + 1, &format!("{} = Fun({}, {})", ret, fnam, name));
-          line(code, tab + 0, &format!("}}"));
-          return ret;
+          line(code, tab + 0, "}");
+          ret
         // Other functions
         } else {
           let name = fresh(nams, "cal");
@@ -612,8 +758,8 @@ pub fn build_function_rule_rhs(
           for (i, arg) in fargs.iter().enumerate() {
             line(code, tab, &format!("link(ctx.heap, {} + {}, {});", name, i, arg));
           }
-          let fnam = build_name(book.id_to_name.get(&func).unwrap_or(&format!("{}", func)));
-          return format!("Fun({}, {})", fnam, name);
+          let fnam = build_name(book.id_to_name.get(func).unwrap_or(&format!("{}", func)));
+          format!("Fun({}, {})", fnam, name)
         }
       }
       runtime::Core::U6O { numb } => {
@@ -678,7 +824,7 @@ pub fn build_function_rule_rhs(
           line(code, tab + 1, "inc_cost(ctx.heap, ctx.tid);");
           line(code, tab + 0, "} else {");
         }
-        line(code, tab + 1, &format!("let {} = {};", name, alloc_node(&mut vec![], 2)));
+        line(code, tab + 1, &format!("let {} = {};", name, alloc_node(&mut [], 2)));
         line(code, tab + 1, &format!("link(ctx.heap, {} + 0, {});", name, val0));
         line(code, tab + 1, &format!("link(ctx.heap, {} + 1, {});", name, val1));
         let oper_name = match *oper {
@@ -715,16 +861,12 @@ pub fn build_function_rule_rhs(
   }
   let mut nams = 0;
   let mut vars : Vec<String> = vec![];
-  for runtime::RuleVar { param, field, erase: _ } in rvrs {
-    match field {
-      Some(field) => {
-        line(code, tab + 0, &format!("let arg{}_{} = load_arg(ctx.heap, arg{}, {});", param, field, param, field));
-        vars.push(format!("arg{}_{}", param, field));
-      }
-      None => {
-        vars.push(format!("arg{}", param));
-      }
+  for runtime::RuleVar { param, path, erase: _ } in rvrs {
+    let name = get_arg_name(*param, path);
+    if let Some((field, parent)) = path.split_last() {
+      line(code, tab + 0, &format!("let {} = load_arg(ctx.heap, {}, {});", name, get_arg_name(*param, parent), field));
     }
+    vars.push(name);
   }
   let mut lams: HashMap<u64, String> = HashMap::new();
   let mut dups: HashMap<u64, (String,String)> = HashMap::new();
@@ -740,9 +882,15 @@ pub fn line(code: &mut String, tab: u64, line: &str) {
 }
 
 pub fn get_var(var: &runtime::RuleVar) -> String {
-  let runtime::RuleVar { param, field, erase: _ } = var;
-  match field {
-    Some(i) => { format!("arg{}_{}", param, i) }
-    None => { format!("arg{}", param) }
+  let runtime::RuleVar { param, path, erase: _ } = var;
+  get_arg_name(*param, path)
+}
+
+// The name of the variable holding an argument, or a field of it (ex: `arg0_1` for `(F (A x y))`'s `y`)
+pub fn get_arg_name(param: u64, path: &[u64]) -> String {
+  let mut name = format!("arg{}", param);
+  for field in path {
+    name.push_str(&format!("_{}", field));
   }
+  name
 }
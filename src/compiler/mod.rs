@@ -1,69 +1,226 @@
 #![allow(unreachable_code)]
 #![allow(clippy::identity_op)]
 
-mod compile;
-
-pub fn compile(code: &str, name: &str) -> std::io::Result<()> {
-  
-  let cargo_rs = include_str!("./../../Cargo.toml")
-    .replace("name = \"hvm\"", &format!("name = \"{}\"", name))
-    .replace("name = \"hvm\"", &format!("name = \"{}\"", name));
-
-  // hvm
-  std::fs::create_dir(format!("./{}",name)).ok();
-  std::fs::write(format!("./{}/Cargo.toml",name), cargo_rs)?;
-  std::fs::write(format!("./{}/rust-toolchain.toml",name), include_str!("./../../rust-toolchain.toml"))?;
-
-  // hvm/src
-  std::fs::create_dir(format!("./{}/src",name)).ok();
-  std::fs::write(format!("./{}/src/main.rs",name), include_str!("./../main.rs"))?;
-  std::fs::write(format!("./{}/src/lib.rs",name), include_str!("./../lib.rs"))?;
-  std::fs::write(format!("./{}/src/api.rs",name), include_str!("./../api.rs"))?;
-
-  // hvm/src/compiler
-  std::fs::create_dir(format!("./{}/src/compiler",name)).ok();
-  std::fs::write(format!("./{}/src/compiler/mod.rs",name)    , include_str!("./../compiler/mod.rs"))?;
-  std::fs::write(format!("./{}/src/compiler/compile.rs",name) , include_str!("./../compiler/compile.rs"))?;
-
-  // hvm/src/language
-  std::fs::create_dir(format!("./{}/src/language",name)).ok();
-  std::fs::write(format!("./{}/src/language/mod.rs",name)      , include_str!("./../language/mod.rs"))?;
-  std::fs::write(format!("./{}/src/language/readback.rs",name) , include_str!("./../language/readback.rs"))?;
-  std::fs::write(format!("./{}/src/language/rulebook.rs",name) , include_str!("./../language/rulebook.rs"))?;
-  std::fs::write(format!("./{}/src/language/syntax.rs",name)   , include_str!("./../language/syntax.rs"))?;
-
-  // hvm/src/runtime
-  std::fs::create_dir(format!("./{}/src/runtime",name)).ok();
-  std::fs::write(format!("./{}/src/runtime/mod.rs",name), include_str!("./../runtime/mod.rs"))?;
-
-  // hvm/src/runtime/base
-  let (precomp_rs, reducer_rs) = compile::build_code(code).unwrap();
-  std::fs::create_dir(format!("./{}/src/runtime/base",name)).ok();
-  std::fs::write(format!("./{}/src/runtime/base/mod.rs",name)     , include_str!("./../runtime/base/mod.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/base/debug.rs",name)   , include_str!("./../runtime/base/debug.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/base/memory.rs",name)  , include_str!("./../runtime/base/memory.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/base/precomp.rs",name) , precomp_rs)?;
-  std::fs::write(format!("./{}/src/runtime/base/program.rs",name) , include_str!("./../runtime/base/program.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/base/reducer.rs",name) , reducer_rs)?;
-
-  // hvm/src/runtime/data
-  std::fs::create_dir(format!("./{}/src/runtime/data",name)).ok();
-  std::fs::write(format!("./{}/src/runtime/data/mod.rs",name)         , include_str!("./../runtime/data/mod.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/data/f60.rs",name)         , include_str!("./../runtime/data/f60.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/data/allocator.rs",name)   , include_str!("./../runtime/data/allocator.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/data/barrier.rs",name)     , include_str!("./../runtime/data/barrier.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/data/redex_bag.rs",name)   , include_str!("./../runtime/data/redex_bag.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/data/u60.rs",name)         , include_str!("./../runtime/data/u60.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/data/u64_map.rs",name)     , include_str!("./../runtime/data/u64_map.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/data/visit_queue.rs",name) , include_str!("./../runtime/data/visit_queue.rs"))?;
-
-  // hvm/src/runtime/rule
-  std::fs::create_dir(format!("./{}/src/runtime/rule",name)).ok();
-  std::fs::write(format!("./{}/src/runtime/rule/mod.rs",name) , include_str!("./../runtime/rule/mod.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/rule/app.rs",name) , include_str!("./../runtime/rule/app.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/rule/dup.rs",name) , include_str!("./../runtime/rule/dup.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/rule/fun.rs",name) , include_str!("./../runtime/rule/fun.rs"))?;
-  std::fs::write(format!("./{}/src/runtime/rule/op2.rs",name) , include_str!("./../runtime/rule/op2.rs"))?;
-
-  return Ok(());
+pub mod c;
+pub mod compile;
+
+use crate::language;
+use crate::runtime;
+
+// Lints the generated code may trigger, allowed on the module it is included in
+const ALLOWED_LINTS : &str = "#[allow(dead_code, non_snake_case, non_upper_case_globals, unused_parens, unused_variables, unused_mut, clippy::all)]";
+
+// The languages `hvm compile` compiles to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+  Rust,
+  C,
+}
+
+impl std::str::FromStr for Target {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text {
+      "rust" => Ok(Target::Rust),
+      "c"    => Ok(Target::C),
+      _      => Err(format!("unknown target '{}', expected 'rust' or 'c'", text)),
+    }
+  }
+}
+
+// Compiles the code to `dir`, for the given target, running the given optimizations on its rules.
+// With `entries`, only what they lead to is compiled, and what was removed is returned. Unless
+// `force` is set, it refuses to write over a directory which isn't empty. Crates depend on this
+// version of `hvm`, from crates.io, or on the one at `hvm_path`, if given.
+#[allow(clippy::too_many_arguments)]
+pub fn compile(code: &str, name: &str, dir: &std::path::Path, force: bool, target: Target, opts: &runtime::Optimizations, entries: Option<&[String]>, hvm_path: Option<&std::path::Path>) -> Result<Option<language::rulebook::Shaken>, String> {
+  match target {
+    Target::Rust => compile_rust(code, name, dir, force, opts, entries, hvm_path),
+    Target::C    => compile_c(code, name, dir, force, opts, entries),
+  }
+}
+
+// Builds what `compile` wrote at `dir`, returning the binary's path
+pub fn build(dir: &std::path::Path, name: &str, target: Target) -> Result<std::path::PathBuf, String> {
+  match target {
+    Target::Rust => build_rust(dir, name),
+    Target::C    => build_c(dir, name),
+  }
+}
+
+// Compiles the code to a crate named `name`, at `dir`, which depends on `hvm`, and installs the
+// compiled functions on it. Its binary takes the same options as `hvm run`, but no file.
+// The functions tree-shaking removed aren't compiled, but the runtime still interprets them.
+fn compile_rust(code: &str, name: &str, dir: &std::path::Path, force: bool, opts: &runtime::Optimizations, entries: Option<&[String]>, hvm_path: Option<&std::path::Path>) -> Result<Option<language::rulebook::Shaken>, String> {
+  check_crate_name(name)?;
+  let (program_rs, shaken) = compile::build_code(code, opts, entries)?;
+
+  // The generated code targets the runtime of this `hvm`, so it depends on the same version
+  let hvm_dependency = match hvm_path {
+    Some(path) => {
+      let path = std::fs::canonicalize(path).map_err(|err| format!("Couldn't find '{}': {}", path.display(), err))?;
+      format!("{{ path = {:?} }}", path.to_string_lossy())
+    }
+    None => format!("\"{}\"", env!("CARGO_PKG_VERSION")),
+  };
+  let cargo_toml = format!(
+    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\nhvm = {}\n\n[profile.release]\nopt-level = 3\n\n[workspace]\n",
+    name,
+    hvm_dependency,
+  );
+
+  let main_rs = [
+    ALLOWED_LINTS,
+    "mod program;",
+    "",
+    "fn main() {",
+    "  hvm::api::run_compiled(include_str!(\"program.hvm\"), program::FUNCTIONS);",
+    "}",
+    "",
+  ].join("\n");
+
+  check_overwrite(dir, force)?;
+  let write = |path: &str, contents: &str| {
+    std::fs::write(dir.join(path), contents).map_err(|err| format!("Couldn't write '{}': {}", dir.join(path).display(), err))
+  };
+  std::fs::create_dir_all(dir.join("src")).map_err(|err| format!("Couldn't create '{}': {}", dir.display(), err))?;
+  write("Cargo.toml", &cargo_toml)?;
+  write("rust-toolchain.toml", include_str!("./../../rust-toolchain.toml"))?;
+  write("src/main.rs", &main_rs)?;
+  write("src/program.hvm", code)?;
+  write("src/program.rs", &program_rs)?;
+
+  Ok(shaken)
+}
+
+// Compiles the code to a single C file, `<name>.c`, at `dir`, which only needs a C compiler. Its
+// binary takes the expression to run, `-s`, `-c`, and the arguments given to `Main`, after `--`.
+// `<name>.h` declares the functions C and C++ programs call when they link it as a library. The
+// functions tree-shaking removed can't be called at all.
+fn compile_c(code: &str, name: &str, dir: &std::path::Path, force: bool, opts: &runtime::Optimizations, entries: Option<&[String]>) -> Result<Option<language::rulebook::Shaken>, String> {
+  check_crate_name(name)?;
+  let (program_c, shaken) = c::build_code(code, opts, entries)?;
+  check_overwrite(dir, force)?;
+  std::fs::create_dir_all(dir).map_err(|err| format!("Couldn't create '{}': {}", dir.display(), err))?;
+  let path = dir.join(format!("{}.c", name));
+  std::fs::write(&path, program_c).map_err(|err| format!("Couldn't write '{}': {}", path.display(), err))?;
+  let path = dir.join(format!("{}.h", name));
+  std::fs::write(&path, c::build_header(name)).map_err(|err| format!("Couldn't write '{}': {}", path.display(), err))?;
+  Ok(shaken)
+}
+
+// Builds the crate `compile` wrote at `dir` with `cargo build --release`
+fn build_rust(dir: &std::path::Path, name: &str) -> Result<std::path::PathBuf, String> {
+  let status = std::process::Command::new("cargo")
+    .args(["build", "--release"])
+    .current_dir(dir)
+    .status()
+    .map_err(|err| format!("Couldn't run cargo: {}", err))?;
+  if !status.success() {
+    return Err(format!("Couldn't build '{}': cargo failed.", dir.display()));
+  }
+  // The target directory can be moved by the environment, relative to the crate
+  let target = std::env::var_os("CARGO_TARGET_DIR").map(|target| dir.join(target)).unwrap_or_else(|| dir.join("target"));
+  Ok(target.join("release").join(format!("{}{}", name, std::env::consts::EXE_SUFFIX)))
+}
+
+// Builds the file `compile` wrote at `dir` with `$CC -O2`, or `cc` if it isn't set
+fn build_c(dir: &std::path::Path, name: &str) -> Result<std::path::PathBuf, String> {
+  let compiler = std::env::var("CC").unwrap_or_else(|_| String::from("cc"));
+  let binary = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+  let status = std::process::Command::new(&compiler)
+    .args(["-O2", "-o", &binary, &format!("{}.c", name), "-lm"])
+    .current_dir(dir)
+    .status()
+    .map_err(|err| format!("Couldn't run {}: {}", compiler, err))?;
+  if !status.success() {
+    return Err(format!("Couldn't build '{}': {} failed.", dir.display(), compiler));
+  }
+  Ok(dir.join(binary))
+}
+
+fn check_overwrite(dir: &std::path::Path, force: bool) -> Result<(), String> {
+  let is_empty = std::fs::read_dir(dir).map(|mut entries| entries.next().is_none()).unwrap_or(true);
+  if !is_empty && !force {
+    return Err(format!("'{}' already exists. Use '--force' to overwrite it.", dir.display()));
+  }
+  Ok(())
+}
+
+// Crate names, as cargo accepts them. Binaries compiled to C follow the same rule.
+fn check_crate_name(name: &str) -> Result<(), String> {
+  let valid = name.chars().all(|chr| chr.is_ascii_alphanumeric() || chr == '_' || chr == '-');
+  match name.chars().next() {
+    Some(chr) if valid && !chr.is_ascii_digit() => Ok(()),
+    _ => Err(format!("Invalid crate name: '{}'. Use '--name' to choose another one.", name)),
+  }
+}
+
+/// compiles the given HVM files to Rust, for a build script, so a crate can run them with
+/// compiled functions on its own [`RuntimeBuilder`](crate::RuntimeBuilder).
+///
+/// writes `hvm.rs` to `OUT_DIR`, which defines `HVM_CODE`, the code of all files, and a
+/// `register` function adding it to a builder, along with its compiled functions:
+///
+/// ```ignore
+/// // build.rs
+/// fn main() {
+///   hvm::compiler::build_to_out_dir(&["src/logic.hvm"]);
+/// }
+///
+/// // src/main.rs
+/// mod logic {
+///   include!(concat!(env!("OUT_DIR"), "/hvm.rs"));
+/// }
+///
+/// let runtime = logic::register(hvm::RuntimeBuilder::default()).build();
+/// ```
+///
+/// paths are relative to the crate's root. panics, with the error, if a file can't be read or
+/// parsed, which fails the build.
+pub fn build_to_out_dir(files: &[&str]) {
+  let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR isn't set. Is this called from a build script?");
+  for file in files {
+    println!("cargo:rerun-if-changed={}", file);
+  }
+  if let Err(err) = build_to_dir(files, std::path::Path::new(&out_dir)) {
+    panic!("{}", err);
+  }
+}
+
+/// same as [`build_to_out_dir`], but writes `hvm.rs` and `hvm.hvm` to the given directory,
+/// returning the error instead of panicking.
+pub fn build_to_dir(files: &[&str], dir: &std::path::Path) -> Result<(), String> {
+  let mut code = String::new();
+  for file in files {
+    let text = std::fs::read_to_string(file).map_err(|err| format!("Couldn't read '{}': {}", file, err))?;
+    code.push_str(&text);
+    code.push('\n');
+  }
+  let (program_rs, _) = compile::build_code(&code, &runtime::Optimizations::default(), None)?;
+
+  let hvm_rs = [
+    "// Generated by `hvm::compiler::build_to_out_dir`. Don't edit it by hand.",
+    "",
+    ALLOWED_LINTS,
+    "mod hvm_compiled {",
+    &program_rs,
+    "}",
+    "",
+    "/// the HVM code the functions were compiled from",
+    "pub const HVM_CODE : &str = include_str!(\"hvm.hvm\");",
+    "",
+    "/// adds `HVM_CODE` to the builder, running it with its compiled functions.",
+    "///",
+    "/// it can be called before or after adding other code to the builder, but only once.",
+    "pub fn register(builder: hvm::RuntimeBuilder) -> hvm::RuntimeBuilder {",
+    "  builder.add_compiled_code(HVM_CODE, hvm_compiled::FUNCTIONS).expect(\"HVM_CODE parses\")",
+    "}",
+    "",
+  ].join("\n");
+
+  std::fs::write(dir.join("hvm.hvm"), code).map_err(|err| err.to_string())?;
+  std::fs::write(dir.join("hvm.rs"), hvm_rs).map_err(|err| err.to_string())?;
+  Ok(())
 }
@@ -0,0 +1,1617 @@
+// HVM's C runtime
+// ---------------
+//
+// A self-contained port of the Rust runtime, used by `hvm compile --target c`. The compiler
+// completes it with the program's ids, arities and names, and with a reducer for each function,
+// so the result only needs a C compiler:
+//
+//   cc -O2 main.c -o main -lm
+//
+// It follows the Rust runtime closely: the memory layout, pointers and rewrite rules are the same,
+// so are the results, and the rewrite counts. Unlike it, it reduces on a single thread, and only
+// implements the builtins that don't need a host: `U60.if`, `U60.swap`, `HVM.log`, `HVM.print`,
+// `HVM.put`, `HVM.eprint` and `HVM.exit`.
+//
+// Compiled with `-DHVM_NO_MAIN`, it leaves out `main`, and is a library for C and C++ programs,
+// declared by the header the compiler writes next to it. See "Library" below.
+
+#include <math.h>
+#include <setjmp.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+
+typedef uint8_t u8;
+typedef uint32_t u32;
+typedef uint64_t u64;
+typedef u64 Ptr;
+
+// Pointers
+// --------
+
+#define VAL ((u64)1)
+#define EXT ((u64)0x100000000)
+#define TAG ((u64)0x1000000000000000)
+
+#define DP0 ((u64)0x0)
+#define DP1 ((u64)0x1)
+#define VAR ((u64)0x2)
+#define ARG ((u64)0x3)
+#define ERA ((u64)0x4)
+#define LAM ((u64)0x5)
+#define APP ((u64)0x6)
+#define SUP ((u64)0x7)
+#define CTR ((u64)0x8)
+#define FUN ((u64)0x9)
+#define OP2 ((u64)0xA)
+#define U60 ((u64)0xB)
+#define F60 ((u64)0xC)
+
+#define ADD ((u64)0x0)
+#define SUB ((u64)0x1)
+#define MUL ((u64)0x2)
+#define DIV ((u64)0x3)
+#define MOD ((u64)0x4)
+#define AND ((u64)0x5)
+#define OR  ((u64)0x6)
+#define XOR ((u64)0x7)
+#define SHL ((u64)0x8)
+#define SHR ((u64)0x9)
+#define LTN ((u64)0xA)
+#define LTE ((u64)0xB)
+#define EQL ((u64)0xC)
+#define GTE ((u64)0xD)
+#define GTN ((u64)0xE)
+#define NEQ ((u64)0xF)
+
+#define U60_MASK ((u64)0xFFFFFFFFFFFFFFF)
+
+static inline Ptr Var(u64 pos) { return (VAR * TAG) | pos; }
+static inline Ptr Dp0(u64 col, u64 pos) { return (DP0 * TAG) | (col * EXT) | pos; }
+static inline Ptr Dp1(u64 col, u64 pos) { return (DP1 * TAG) | (col * EXT) | pos; }
+static inline Ptr Arg(u64 pos) { return (ARG * TAG) | pos; }
+static inline Ptr Era(void) { return ERA * TAG; }
+static inline Ptr Lam(u64 pos) { return (LAM * TAG) | pos; }
+static inline Ptr App(u64 pos) { return (APP * TAG) | pos; }
+static inline Ptr Sup(u64 col, u64 pos) { return (SUP * TAG) | (col * EXT) | pos; }
+static inline Ptr Op2(u64 ope, u64 pos) { return (OP2 * TAG) | (ope * EXT) | pos; }
+static inline Ptr U6O(u64 val) { return (U60 * TAG) | val; }
+static inline Ptr F6O(u64 val) { return (F60 * TAG) | val; }
+static inline Ptr Ctr(u64 fun, u64 pos) { return (CTR * TAG) | (fun * EXT) | pos; }
+static inline Ptr Fun(u64 fun, u64 pos) { return (FUN * TAG) | (fun * EXT) | pos; }
+
+static inline u64 get_tag(Ptr lnk) { return lnk / TAG; }
+static inline u64 get_ext(Ptr lnk) { return (lnk / EXT) & 0xFFFFFFF; }
+static inline u64 get_val(Ptr lnk) { return lnk & 0xFFFFFFFF; }
+static inline u64 get_num(Ptr lnk) { return lnk & U60_MASK; }
+static inline u64 get_loc(Ptr lnk, u64 arg) { return get_val(lnk) + arg; }
+
+/*! GENERATED_CONSTANTS !*/
+
+// Failures
+// --------
+
+// What `hvm_init` and `hvm_eval` return
+#define HVM_OK 0
+#define HVM_ERROR 1
+#define HVM_EXIT 2
+
+static jmp_buf failure; // where `hvm_eval` resumes when the evaluation fails, or exits
+static char* error = NULL; // the message of the last failure
+static int exit_code = 0; // the code given to `HVM.exit`
+
+static void set_error(const char* format, ...) {
+  va_list args;
+  va_start(args, format);
+  int size = vsnprintf(NULL, 0, format, args) + 1;
+  va_end(args);
+  free(error);
+  error = (char*)malloc(size);
+  if (error != NULL) {
+    va_start(args, format);
+    vsnprintf(error, size, format, args);
+    va_end(args);
+  }
+}
+
+// Stops the evaluation, which returns HVM_ERROR, with the message given
+#define fail(...) do { set_error(__VA_ARGS__); longjmp(failure, HVM_ERROR); } while (0)
+
+// Growable arrays of u64
+typedef struct {
+  u64* data;
+  u64 size;
+  u64 capacity;
+} Vec;
+
+static void vec_push(Vec* vec, u64 value) {
+  if (vec->size == vec->capacity) {
+    vec->capacity = vec->capacity == 0 ? 256 : vec->capacity * 2;
+    vec->data = (u64*)realloc(vec->data, vec->capacity * sizeof(u64));
+    if (vec->data == NULL) {
+      fail("Out of memory.");
+    }
+  }
+  vec->data[vec->size++] = value;
+}
+
+// Sets and maps of u64, by open addressing, to remember the pointers and locations seen
+typedef struct {
+  u64* keys; // stored plus one, so that zero marks an empty slot
+  u64* vals;
+  u64 size;
+  u64 capacity;
+} Map;
+
+static u64 map_slot(Map* map, u64 key) {
+  u64 slot = (key * 0x9E3779B97F4A7C15) & (map->capacity - 1);
+  while (map->keys[slot] != 0 && map->keys[slot] != key + 1) {
+    slot = (slot + 1) & (map->capacity - 1);
+  }
+  return slot;
+}
+
+static u64* map_get(Map* map, u64 key) {
+  if (map->capacity == 0) {
+    return NULL;
+  }
+  u64 slot = map_slot(map, key);
+  return map->keys[slot] == 0 ? NULL : &map->vals[slot];
+}
+
+static void map_set(Map* map, u64 key, u64 val) {
+  if ((map->size + 1) * 2 > map->capacity) {
+    Map old = *map;
+    map->capacity = old.capacity == 0 ? 256 : old.capacity * 2;
+    map->keys = (u64*)calloc(map->capacity, sizeof(u64));
+    map->vals = (u64*)calloc(map->capacity, sizeof(u64));
+    map->size = 0;
+    if (map->keys == NULL || map->vals == NULL) {
+      fail("Out of memory.");
+    }
+    for (u64 i = 0; i < old.capacity; ++i) {
+      if (old.keys[i] != 0) {
+        map_set(map, old.keys[i] - 1, old.vals[i]);
+      }
+    }
+    free(old.keys);
+    free(old.vals);
+  }
+  u64 slot = map_slot(map, key);
+  if (map->keys[slot] == 0) {
+    map->keys[slot] = key + 1;
+    map->size += 1;
+  }
+  map->vals[slot] = val;
+}
+
+static void map_free(Map* map) {
+  free(map->keys);
+  free(map->vals);
+}
+
+// Memory
+// ------
+
+#define FREE_LISTS 32
+
+static Ptr* heap;
+static u64 heap_size;
+static u64 heap_next = 1; // the root lives at 0
+static Vec free_list[FREE_LISTS];
+static u64 cost = 0;
+static u64 dups = 0;
+
+static inline Ptr load_ptr(u64 loc) {
+  return heap[loc];
+}
+
+static inline Ptr load_arg(Ptr term, u64 arg) {
+  return heap[get_loc(term, arg)];
+}
+
+static inline Ptr take_arg(Ptr term, u64 arg) {
+  return heap[get_loc(term, arg)];
+}
+
+static inline Ptr link(u64 loc, Ptr ptr) {
+  heap[loc] = ptr;
+  if (get_tag(ptr) <= VAR) {
+    heap[get_loc(ptr, get_tag(ptr) & 0x01)] = Arg(loc);
+  }
+  return ptr;
+}
+
+static inline u64 alloc_node(u64 arity) {
+  if (arity == 0) {
+    return 0;
+  }
+  if (arity < FREE_LISTS && free_list[arity].size > 0) {
+    return free_list[arity].data[--free_list[arity].size];
+  }
+  if (heap_next + arity > heap_size) {
+    fail("Out of memory. Use '-s' to give a larger heap.");
+  }
+  u64 loc = heap_next;
+  heap_next += arity;
+  return loc;
+}
+
+static inline void free_node(u64 loc, u64 arity) {
+  if (arity > 0 && arity < FREE_LISTS) {
+    vec_push(&free_list[arity], loc);
+  }
+}
+
+static inline void inc_cost(void) {
+  cost += 1;
+}
+
+static inline u64 gen_dup(void) {
+  return dups++ & 0xFFFFFFF;
+}
+
+static inline u64 arity_of(Ptr lnk) {
+  u64 fid = get_ext(lnk);
+  return fid < ID_COUNT ? ARITY[fid] : 0;
+}
+
+static inline int is_whnf(Ptr term) {
+  switch (get_tag(term)) {
+    case ERA: case LAM: case SUP: case CTR: case U60: case F60: return 1;
+    default: return 0;
+  }
+}
+
+// HOAS_OPT's condition, a Kind2-specific optimization, like in the Rust runtime
+static inline int is_hoas_arg(Ptr arg) {
+  int is_num = get_tag(arg) == U60 || get_tag(arg) == F60;
+  int is_ctr = get_tag(arg) == CTR && arity_of(arg) == 0;
+  int is_hoas_ctr_num = get_tag(arg) == CTR && get_ext(arg) >= _Kind_Term_ct0_ && get_ext(arg) <= _Kind_Term_f60_;
+  return is_num || is_ctr || is_hoas_ctr_num;
+}
+
+// Numbers
+// -------
+
+static inline u64 u60_new(u64 a) { return a & U60_MASK; }
+
+static u64 u60_op(u64 oper, u64 a, u64 b) {
+  switch (oper) {
+    case ADD: return u60_new(a + b);
+    case SUB: return a >= b ? a - b : 0x1000000000000000 - (b - a);
+    case MUL: return u60_new(a * b);
+    case DIV: if (b == 0) { fail("Division by zero."); } return a / b;
+    case MOD: if (b == 0) { fail("Division by zero."); } return a % b;
+    case AND: return a & b;
+    case OR:  return a | b;
+    case XOR: return a ^ b;
+    case SHL: return u60_new(a << (b & 63));
+    case SHR: return a >> (b & 63);
+    case LTN: return a <  b ? 1 : 0;
+    case LTE: return a <= b ? 1 : 0;
+    case EQL: return a == b ? 1 : 0;
+    case GTE: return a >= b ? 1 : 0;
+    case GTN: return a >  b ? 1 : 0;
+    case NEQ: return a != b ? 1 : 0;
+    default:  return 0;
+  }
+}
+
+static u64 f60_new(double a) {
+  u64 b;
+  memcpy(&b, &a, sizeof(b));
+  return (b & 0xF) > 8 ? (b >> 4) + 1 : b >> 4;
+}
+
+static double f60_val(u64 a) {
+  u64 b = a << 4;
+  double c;
+  memcpy(&c, &b, sizeof(c));
+  return c;
+}
+
+static u64 f60_op(u64 oper, u64 x, u64 y) {
+  double a = f60_val(x);
+  double b = f60_val(y);
+  switch (oper) {
+    case ADD: return f60_new(a + b);
+    case SUB: return f60_new(a - b);
+    case MUL: return f60_new(a * b);
+    case DIV: return f60_new(a / b);
+    case MOD: return f60_new(fmod(a, b));
+    case AND: return f60_new(cos(a) + sin(b));
+    case OR:  return f60_new(atan2(a, b));
+    case XOR: return f60_new(ceil(a) + floor(a));
+    case SHL: return f60_new(pow(b, a));
+    case SHR: return f60_new(log(a) / log(b));
+    case LTN: return f60_new(a <  b ? 1.0 : 0.0);
+    case LTE: return f60_new(a <= b ? 1.0 : 0.0);
+    case EQL: return f60_new(a == b ? 1.0 : 0.0);
+    case GTE: return f60_new(a >= b ? 1.0 : 0.0);
+    case GTN: return f60_new(a >  b ? 1.0 : 0.0);
+    case NEQ: return f60_new(a != b ? 1.0 : 0.0);
+    default:  return 0;
+  }
+}
+
+// Substitution and garbage collection
+// -----------------------------------
+
+static void collect(Ptr term);
+
+// Performs a global [x <- val] substitution
+static void subst(Ptr var, Ptr val) {
+  Ptr arg_ptr = load_ptr(get_loc(var, get_tag(var) & 0x01));
+  if (get_tag(arg_ptr) == ARG) {
+    link(get_loc(arg_ptr, 0), val);
+  } else if (get_tag(arg_ptr) == ERA) {
+    collect(val);
+  }
+}
+
+static void collect(Ptr term) {
+  Vec coll = {0};
+  Ptr next = term;
+  for (;;) {
+    Ptr term = next;
+    int done = 1;
+    switch (get_tag(term)) {
+      case DP0: {
+        link(get_loc(term, 0), Era());
+        if (get_tag(load_arg(term, 1)) == ERA) {
+          vec_push(&coll, take_arg(term, 2));
+          free_node(get_loc(term, 0), 3);
+        }
+        break;
+      }
+      case DP1: {
+        link(get_loc(term, 1), Era());
+        if (get_tag(load_arg(term, 0)) == ERA) {
+          vec_push(&coll, take_arg(term, 2));
+          free_node(get_loc(term, 0), 3);
+        }
+        break;
+      }
+      case VAR: {
+        link(get_loc(term, 0), Era());
+        break;
+      }
+      case LAM: {
+        subst(Var(get_loc(term, 0)), Era());
+        next = take_arg(term, 1);
+        free_node(get_loc(term, 0), 2);
+        done = 0;
+        break;
+      }
+      case APP: case SUP: case OP2: {
+        vec_push(&coll, take_arg(term, 0));
+        next = take_arg(term, 1);
+        free_node(get_loc(term, 0), 2);
+        done = 0;
+        break;
+      }
+      case CTR: case FUN: {
+        u64 arity = arity_of(term);
+        for (u64 i = 0; i < arity; ++i) {
+          if (i < arity - 1) {
+            vec_push(&coll, take_arg(term, i));
+          } else {
+            next = take_arg(term, i);
+          }
+        }
+        free_node(get_loc(term, 0), arity);
+        done = arity == 0;
+        break;
+      }
+    }
+    if (done) {
+      if (coll.size == 0) {
+        break;
+      }
+      next = coll.data[--coll.size];
+    }
+  }
+  free(coll.data);
+}
+
+// Rewrite rules
+// -------------
+//
+// Like in the Rust runtime, `apply` functions return 1 when the term at `host` must be reduced
+// again, and `visit` functions push the arguments to reduce before applying a rule.
+
+static Vec stack; // locations to reduce; the ones being visited have VISIT set
+#define VISIT ((u64)1 << 63)
+
+static inline void visit(u64 loc) {
+  vec_push(&stack, loc | VISIT);
+}
+
+static int app_apply(u64 host, Ptr term) {
+  Ptr arg0 = load_arg(term, 0);
+
+  // (λx(body) a)
+  // ------------ APP-LAM
+  // x <- a
+  // body
+  if (get_tag(arg0) == LAM) {
+    inc_cost();
+    subst(Var(get_loc(arg0, 0)), take_arg(term, 1));
+    link(host, take_arg(arg0, 1));
+    free_node(get_loc(term, 0), 2);
+    free_node(get_loc(arg0, 0), 2);
+    return 1;
+  }
+
+  // ({a b} c)
+  // --------------- APP-SUP
+  // dup x0 x1 = c
+  // {(a x0) (b x1)}
+  if (get_tag(arg0) == SUP) {
+    inc_cost();
+    u64 app0 = get_loc(term, 0);
+    u64 app1 = get_loc(arg0, 0);
+    u64 let0 = alloc_node(3);
+    u64 par0 = alloc_node(2);
+    link(let0 + 2, take_arg(term, 1));
+    link(app0 + 1, Dp0(get_ext(arg0), let0));
+    link(app0 + 0, take_arg(arg0, 0));
+    link(app1 + 0, take_arg(arg0, 1));
+    link(app1 + 1, Dp1(get_ext(arg0), let0));
+    link(par0 + 0, App(app0));
+    link(par0 + 1, App(app1));
+    link(host, Sup(get_ext(arg0), par0));
+    return 0;
+  }
+
+  return 0;
+}
+
+static int dup_apply(u64 host, Ptr term) {
+  Ptr arg0 = load_arg(term, 2);
+  u64 tcol = get_ext(term);
+
+  // dup r s = λx(f)
+  // --------------- DUP-LAM
+  // dup f0 f1 = f
+  // r <- λx0(f0)
+  // s <- λx1(f1)
+  // x <- {x0 x1}
+  if (get_tag(arg0) == LAM) {
+    inc_cost();
+    u64 let0 = alloc_node(3);
+    u64 par0 = alloc_node(2);
+    u64 lam0 = alloc_node(2);
+    u64 lam1 = alloc_node(2);
+    link(let0 + 2, take_arg(arg0, 1));
+    link(par0 + 1, Var(lam1));
+    link(par0 + 0, Var(lam0));
+    link(lam0 + 1, Dp0(tcol, let0));
+    link(lam1 + 1, Dp1(tcol, let0));
+    subst(Var(get_loc(arg0, 0)), Sup(tcol, par0));
+    subst(Dp0(tcol, get_loc(term, 0)), Lam(lam0));
+    subst(Dp1(tcol, get_loc(term, 0)), Lam(lam1));
+    link(host, Lam(get_tag(term) == DP0 ? lam0 : lam1));
+    free_node(get_loc(term, 0), 3);
+    free_node(get_loc(arg0, 0), 2);
+    return 1;
+  }
+
+  // dup x y = {a b}
+  // --------------- DUP-SUP
+  // if equal: | else:
+  // x <- a    | x <- {xA xB}
+  // y <- b    | y <- {yA yB}
+  //           | dup xA yA = a
+  //           | dup xB yB = b
+  if (get_tag(arg0) == SUP) {
+    inc_cost();
+    if (tcol == get_ext(arg0)) {
+      subst(Dp0(tcol, get_loc(term, 0)), take_arg(arg0, 0));
+      subst(Dp1(tcol, get_loc(term, 0)), take_arg(arg0, 1));
+      free_node(get_loc(term, 0), 3);
+      free_node(get_loc(arg0, 0), 2);
+    } else {
+      u64 par0 = alloc_node(2);
+      u64 let0 = alloc_node(3);
+      u64 par1 = get_loc(arg0, 0);
+      u64 let1 = alloc_node(3);
+      link(let0 + 2, take_arg(arg0, 0));
+      link(let1 + 2, take_arg(arg0, 1));
+      link(par1 + 0, Dp1(tcol, let0));
+      link(par1 + 1, Dp1(tcol, let1));
+      link(par0 + 0, Dp0(tcol, let0));
+      link(par0 + 1, Dp0(tcol, let1));
+      subst(Dp0(tcol, get_loc(term, 0)), Sup(get_ext(arg0), par0));
+      subst(Dp1(tcol, get_loc(term, 0)), Sup(get_ext(arg0), par1));
+      free_node(get_loc(term, 0), 3);
+    }
+    return 1;
+  }
+
+  // dup x y = N
+  // ----------- DUP-U60, DUP-F60
+  // x <- N
+  // y <- N
+  if (get_tag(arg0) == U60 || get_tag(arg0) == F60) {
+    inc_cost();
+    subst(Dp0(tcol, get_loc(term, 0)), arg0);
+    subst(Dp1(tcol, get_loc(term, 0)), arg0);
+    free_node(get_loc(term, 0), 3);
+    return 1;
+  }
+
+  // dup x y = (K a b c ...)
+  // ----------------------- DUP-CTR
+  // dup a0 a1 = a
+  // dup b0 b1 = b
+  // dup c0 c1 = c
+  // ...
+  // x <- (K a0 b0 c0 ...)
+  // y <- (K a1 b1 c1 ...)
+  if (get_tag(arg0) == CTR) {
+    inc_cost();
+    u64 fnum = get_ext(arg0);
+    u64 fari = arity_of(arg0);
+    if (fari == 0) {
+      subst(Dp0(tcol, get_loc(term, 0)), Ctr(fnum, 0));
+      subst(Dp1(tcol, get_loc(term, 0)), Ctr(fnum, 0));
+      link(host, Ctr(fnum, 0));
+      free_node(get_loc(term, 0), 3);
+    } else {
+      u64 ctr0 = get_loc(arg0, 0);
+      u64 ctr1 = alloc_node(fari);
+      for (u64 i = 0; i < fari; ++i) {
+        u64 leti = alloc_node(3);
+        link(leti + 2, take_arg(arg0, i));
+        link(ctr0 + i, Dp0(tcol, leti));
+        link(ctr1 + i, Dp1(tcol, leti));
+      }
+      subst(Dp0(tcol, get_loc(term, 0)), Ctr(fnum, ctr0));
+      subst(Dp1(tcol, get_loc(term, 0)), Ctr(fnum, ctr1));
+      free_node(get_loc(term, 0), 3);
+    }
+    return 1;
+  }
+
+  // dup x y = *
+  // ----------- DUP-ERA
+  // x <- *
+  // y <- *
+  if (get_tag(arg0) == ERA) {
+    inc_cost();
+    subst(Dp0(tcol, get_loc(term, 0)), Era());
+    subst(Dp1(tcol, get_loc(term, 0)), Era());
+    link(host, Era());
+    free_node(get_loc(term, 0), 3);
+    return 1;
+  }
+
+  return 0;
+}
+
+static int op2_apply(u64 host, Ptr term) {
+  Ptr arg0 = load_arg(term, 0);
+  Ptr arg1 = load_arg(term, 1);
+
+  // (OP a b)
+  // -------- OP2-U60, OP2-F60
+  // op(a, b)
+  if (get_tag(arg0) == U60 && get_tag(arg1) == U60) {
+    inc_cost();
+    link(host, U6O(u60_op(get_ext(term), get_num(arg0), get_num(arg1))));
+    free_node(get_loc(term, 0), 2);
+    return 0;
+  }
+  if (get_tag(arg0) == F60 && get_tag(arg1) == F60) {
+    inc_cost();
+    link(host, F6O(f60_op(get_ext(term), get_num(arg0), get_num(arg1))));
+    free_node(get_loc(term, 0), 2);
+    return 0;
+  }
+
+  // (+ {a0 a1} b)
+  // --------------------- OP2-SUP-0
+  // dup b0 b1 = b
+  // {(+ a0 b0) (+ a1 b1)}
+  if (get_tag(arg0) == SUP) {
+    inc_cost();
+    u64 op20 = get_loc(term, 0);
+    u64 op21 = get_loc(arg0, 0);
+    u64 let0 = alloc_node(3);
+    u64 par0 = alloc_node(2);
+    link(let0 + 2, arg1);
+    link(op20 + 1, Dp0(get_ext(arg0), let0));
+    link(op20 + 0, take_arg(arg0, 0));
+    link(op21 + 0, take_arg(arg0, 1));
+    link(op21 + 1, Dp1(get_ext(arg0), let0));
+    link(par0 + 0, Op2(get_ext(term), op20));
+    link(par0 + 1, Op2(get_ext(term), op21));
+    link(host, Sup(get_ext(arg0), par0));
+    return 0;
+  }
+
+  // (+ a {b0 b1})
+  // --------------- OP2-SUP-1
+  // dup a0 a1 = a
+  // {(+ a0 b0) (+ a1 b1)}
+  if (get_tag(arg1) == SUP) {
+    inc_cost();
+    u64 op20 = get_loc(term, 0);
+    u64 op21 = get_loc(arg1, 0);
+    u64 let0 = alloc_node(3);
+    u64 par0 = alloc_node(2);
+    link(let0 + 2, arg0);
+    link(op20 + 0, Dp0(get_ext(arg1), let0));
+    link(op20 + 1, take_arg(arg1, 0));
+    link(op21 + 1, take_arg(arg1, 1));
+    link(op21 + 0, Dp1(get_ext(arg1), let0));
+    link(par0 + 0, Op2(get_ext(term), op20));
+    link(par0 + 1, Op2(get_ext(term), op21));
+    link(host, Sup(get_ext(arg1), par0));
+    return 0;
+  }
+
+  return 0;
+}
+
+// (F {a0 a1} b c ...)
+// ------------------- FUN-SUP
+// dup b0 b1 = b
+// dup c0 c1 = c
+// ...
+// {(F a0 b0 c0 ...) (F a1 b1 c1 ...)}
+static Ptr superpose(u64 host, Ptr term, Ptr argn, u64 n) {
+  inc_cost();
+  u64 arit = arity_of(term);
+  u64 func = get_ext(term);
+  u64 fun0 = get_loc(term, 0);
+  u64 fun1 = alloc_node(arit);
+  u64 par0 = get_loc(argn, 0);
+  for (u64 i = 0; i < arit; ++i) {
+    if (i != n) {
+      u64 leti = alloc_node(3);
+      Ptr argi = take_arg(term, i);
+      link(fun0 + i, Dp0(get_ext(argn), leti));
+      link(fun1 + i, Dp1(get_ext(argn), leti));
+      link(leti + 2, argi);
+    } else {
+      link(fun0 + i, take_arg(argn, 0));
+      link(fun1 + i, take_arg(argn, 1));
+    }
+  }
+  // Constructors are superposed too, when a call matches on their fields
+  Ptr (*make)(u64, u64) = get_tag(term) == CTR ? Ctr : Fun;
+  link(par0 + 0, make(func, fun0));
+  link(par0 + 1, make(func, fun1));
+  return link(host, Sup(get_ext(argn), par0));
+}
+
+// Lifts a superposition on a field of the matched constructors, following `path` from the argument
+// `n`, to the call, superposing each constructor on the way
+static Ptr superpose_field(u64 host, Ptr term, u64 n, const u64* path, u64 len) {
+  for (u64 depth = len; depth > 0; --depth) {
+    u64 ctr_loc = get_loc(term, n);
+    for (u64 i = 0; i < depth - 1; ++i) {
+      ctr_loc = get_loc(load_ptr(ctr_loc), path[i]);
+    }
+    Ptr ctr = load_ptr(ctr_loc);
+    superpose(ctr_loc, ctr, load_arg(ctr, path[depth - 1]), path[depth - 1]);
+  }
+  return superpose(host, term, load_arg(term, n), n);
+}
+
+// Reduces a field the call at `host` matches on, on the stack, applying the call again after it
+static int visit_field(u64 host, u64 loc) {
+  vec_push(&stack, host);
+  visit(loc);
+  return 0;
+}
+
+static Ptr reduce(u64 root);
+static Ptr normalize(u64 host);
+
+// Readback
+// --------
+//
+// Reads the normal form back as a tree, like `hvm run`'s default readback, and shows it the same
+// way, including the string and list sugars.
+
+typedef struct Term {
+  u64 tag; // LAM, APP, SUP, CTR, OP2, U60, F60 or VAR
+  u64 numb; // the number, or the operation
+  char* name; // the variable, or the constructor
+  u64 arity;
+  struct Term** args;
+} Term;
+
+typedef struct {
+  char* data;
+  u64 size;
+  u64 capacity;
+} Text;
+
+static void text_push(Text* text, const char* str) {
+  u64 len = strlen(str);
+  if (text->size + len + 1 > text->capacity) {
+    text->capacity = (text->size + len + 1) * 2;
+    text->data = (char*)realloc(text->data, text->capacity);
+    if (text->data == NULL) {
+      fail("Out of memory.");
+    }
+  }
+  memcpy(text->data + text->size, str, len + 1);
+  text->size += len;
+}
+
+static void text_push_char(Text* text, u32 chr) {
+  char buf[5] = {0};
+  if (chr < 0x80) {
+    buf[0] = (char)chr;
+  } else if (chr < 0x800) {
+    buf[0] = (char)(0xC0 | (chr >> 6));
+    buf[1] = (char)(0x80 | (chr & 0x3F));
+  } else if (chr < 0x10000) {
+    buf[0] = (char)(0xE0 | (chr >> 12));
+    buf[1] = (char)(0x80 | ((chr >> 6) & 0x3F));
+    buf[2] = (char)(0x80 | (chr & 0x3F));
+  } else {
+    buf[0] = (char)(0xF0 | (chr >> 18));
+    buf[1] = (char)(0x80 | ((chr >> 12) & 0x3F));
+    buf[2] = (char)(0x80 | ((chr >> 6) & 0x3F));
+    buf[3] = (char)(0x80 | (chr & 0x3F));
+  }
+  text_push(text, buf);
+}
+
+static char* copy_str(const char* str) {
+  char* copy = (char*)malloc(strlen(str) + 1);
+  strcpy(copy, str);
+  return copy;
+}
+
+static Term* new_term(u64 tag, u64 numb, char* name, u64 arity) {
+  Term* term = (Term*)calloc(1, sizeof(Term));
+  term->tag = tag;
+  term->numb = numb;
+  term->name = name;
+  term->arity = arity;
+  term->args = arity > 0 ? (Term**)calloc(arity, sizeof(Term*)) : NULL;
+  return term;
+}
+
+static void free_term(Term* term) {
+  for (u64 i = 0; i < term->arity; ++i) {
+    free_term(term->args[i]);
+  }
+  free(term->args);
+  free(term->name);
+  free(term);
+}
+
+// Names lambda-bound variables x0, x1, ..., in the order they are found
+static void gen_var_names(Map* names, Map* seen, Ptr term) {
+  if (map_get(seen, term) != NULL) {
+    return;
+  }
+  map_set(seen, term, 1);
+  switch (get_tag(term)) {
+    case LAM: {
+      if (get_tag(load_arg(term, 0)) != ERA) {
+        map_set(names, Var(get_loc(term, 0)), names->size);
+      }
+      gen_var_names(names, seen, load_arg(term, 1));
+      break;
+    }
+    case APP: case SUP: case OP2: {
+      gen_var_names(names, seen, load_arg(term, 0));
+      gen_var_names(names, seen, load_arg(term, 1));
+      break;
+    }
+    case DP0: case DP1: {
+      gen_var_names(names, seen, load_arg(term, 2));
+      break;
+    }
+    case CTR: case FUN: {
+      u64 arity = arity_of(term);
+      for (u64 i = 0; i < arity; ++i) {
+        gen_var_names(names, seen, load_arg(term, i));
+      }
+      break;
+    }
+  }
+}
+
+// The sides of the superpositions taken by the enclosing duplications, by label
+typedef struct {
+  Vec cols;
+  Vec vals; // 0 or 1, plus 2 while popped
+} Stacks;
+
+static u64 stacks_last(Stacks* stacks, u64 col) {
+  for (u64 i = stacks->cols.size; i > 0; --i) {
+    if (stacks->cols.data[i - 1] == col && stacks->vals.data[i - 1] < 2) {
+      return i - 1;
+    }
+  }
+  return (u64)-1;
+}
+
+static Term* readback(Map* names, Stacks* stacks, Ptr term) {
+  char buf[64];
+  switch (get_tag(term)) {
+    case LAM: {
+      Term* body = readback(names, stacks, load_arg(term, 1));
+      char* name;
+      if (get_tag(load_arg(term, 0)) == ERA) {
+        name = copy_str("*");
+      } else {
+        u64* index = map_get(names, Var(get_loc(term, 0)));
+        if (index != NULL) {
+          snprintf(buf, sizeof(buf), "x%llu", (unsigned long long)*index);
+          name = copy_str(buf);
+        } else {
+          name = copy_str("?");
+        }
+      }
+      Term* lam = new_term(LAM, 0, name, 1);
+      lam->args[0] = body;
+      return lam;
+    }
+    case APP: case SUP: case OP2: {
+      u64 last = get_tag(term) == SUP ? stacks_last(stacks, get_ext(term)) : (u64)-1;
+      if (last != (u64)-1) {
+        u64 side = stacks->vals.data[last];
+        stacks->vals.data[last] = 2;
+        Term* got = readback(names, stacks, load_arg(term, side));
+        stacks->vals.data[last] = side;
+        return got;
+      }
+      Term* node = new_term(get_tag(term), get_ext(term), NULL, 2);
+      node->args[0] = readback(names, stacks, load_arg(term, 0));
+      node->args[1] = readback(names, stacks, load_arg(term, 1));
+      return node;
+    }
+    case DP0: case DP1: {
+      vec_push(&stacks->cols, get_ext(term));
+      vec_push(&stacks->vals, get_tag(term) == DP0 ? 0 : 1);
+      Term* got = readback(names, stacks, load_arg(term, 2));
+      stacks->cols.size -= 1;
+      stacks->vals.size -= 1;
+      return got;
+    }
+    case U60: case F60: {
+      return new_term(get_tag(term), get_num(term), NULL, 0);
+    }
+    case CTR: case FUN: {
+      u64 fid = get_ext(term);
+      u64 arity = arity_of(term);
+      char* name;
+      if (fid < ID_COUNT && NAMES[fid] != NULL) {
+        name = copy_str(NAMES[fid]);
+      } else {
+        snprintf(buf, sizeof(buf), "$%llu", (unsigned long long)fid);
+        name = copy_str(buf);
+      }
+      Term* ctr = new_term(CTR, 0, name, arity);
+      for (u64 i = 0; i < arity; ++i) {
+        ctr->args[i] = readback(names, stacks, load_arg(term, i));
+      }
+      return ctr;
+    }
+    case VAR: {
+      u64* index = map_get(names, term);
+      if (index != NULL) {
+        snprintf(buf, sizeof(buf), "x%llu", (unsigned long long)*index);
+      } else {
+        snprintf(buf, sizeof(buf), "^%llu", (unsigned long long)get_loc(term, 0));
+      }
+      return new_term(VAR, 0, copy_str(buf), 0);
+    }
+    case ARG: {
+      return new_term(VAR, 0, copy_str("<arg>"), 0);
+    }
+    case ERA: {
+      return new_term(VAR, 0, copy_str("<era>"), 0);
+    }
+    default: {
+      snprintf(buf, sizeof(buf), "<unknown_tag_%llu>", (unsigned long long)get_tag(term));
+      return new_term(VAR, 0, copy_str(buf), 0);
+    }
+  }
+}
+
+// Shows a f60 like Rust does: the shortest digits that read back to it, without exponents
+static void show_f60(Text* text, u64 numb) {
+  double val = f60_val(numb);
+  char buf[64];
+  if (isnan(val)) {
+    text_push(text, "NaN");
+    return;
+  }
+  if (isinf(val)) {
+    text_push(text, val < 0 ? "-inf.0" : "inf.0");
+    return;
+  }
+  int prec = 1;
+  for (; prec < 17; ++prec) {
+    snprintf(buf, sizeof(buf), "%.*e", prec - 1, val);
+    if (strtod(buf, NULL) == val) {
+      break;
+    }
+  }
+  snprintf(buf, sizeof(buf), "%.*e", prec - 1, val);
+  char digits[32];
+  int ndigits = 0;
+  char* chr = buf;
+  if (*chr == '-') {
+    text_push(text, "-");
+    chr++;
+  }
+  for (; *chr != 'e'; ++chr) {
+    if (*chr != '.') {
+      digits[ndigits++] = *chr;
+    }
+  }
+  int exp = atoi(chr + 1);
+  while (ndigits > 1 && digits[ndigits - 1] == '0') {
+    ndigits--;
+  }
+  digits[ndigits] = 0;
+  if (exp < 0) {
+    text_push(text, "0.");
+    for (int i = -1; i > exp; --i) {
+      text_push(text, "0");
+    }
+    text_push(text, digits);
+  } else if (exp + 1 >= ndigits) {
+    text_push(text, digits);
+    for (int i = ndigits; i <= exp; ++i) {
+      text_push(text, "0");
+    }
+    text_push(text, ".0");
+  } else {
+    char head[32];
+    memcpy(head, digits, exp + 1);
+    head[exp + 1] = 0;
+    text_push(text, head);
+    text_push(text, ".");
+    text_push(text, digits + exp + 1);
+  }
+}
+
+static int is_ctr(Term* term, const char* name, u64 arity) {
+  return term->tag == CTR && term->arity == arity && strcmp(term->name, name) == 0;
+}
+
+static void show_term(Text* text, Term* term);
+
+static int show_string(Text* text, Term* term) {
+  Term* cur = term;
+  while (is_ctr(cur, "String.cons", 2)) {
+    if (cur->args[0]->tag != U60 || cur->args[0]->numb > 0x10FFFF || (cur->args[0]->numb >= 0xD800 && cur->args[0]->numb < 0xE000)) {
+      return 0;
+    }
+    cur = cur->args[1];
+  }
+  if (!is_ctr(cur, "String.nil", 0)) {
+    return 0;
+  }
+  text_push(text, "\"");
+  for (cur = term; cur->arity == 2; cur = cur->args[1]) {
+    text_push_char(text, (u32)cur->args[0]->numb);
+  }
+  text_push(text, "\"");
+  return 1;
+}
+
+static int show_list(Text* text, Term* term) {
+  Term* cur = term;
+  while (is_ctr(cur, "List.cons", 2)) {
+    cur = cur->args[1];
+  }
+  if (!is_ctr(cur, "List.nil", 0)) {
+    return 0;
+  }
+  text_push(text, "[");
+  for (cur = term; cur->arity == 2; cur = cur->args[1]) {
+    if (cur != term) {
+      text_push(text, ", ");
+    }
+    show_term(text, cur->args[0]);
+  }
+  text_push(text, "]");
+  return 1;
+}
+
+static void show_term(Text* text, Term* term) {
+  static const char* OPERS[] = {"+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "<", "<=", "==", ">=", ">", "!="};
+  char buf[64];
+  switch (term->tag) {
+    case VAR: {
+      text_push(text, term->name);
+      break;
+    }
+    case LAM: {
+      text_push(text, "λ");
+      text_push(text, term->name);
+      text_push(text, " ");
+      show_term(text, term->args[0]);
+      break;
+    }
+    case APP: {
+      Term* func = term;
+      u64 count = 0;
+      while (func->tag == APP) {
+        func = func->args[0];
+        count++;
+      }
+      text_push(text, "(");
+      show_term(text, func);
+      for (u64 i = count; i > 0; --i) {
+        Term* app = term;
+        for (u64 j = 1; j < i; ++j) {
+          app = app->args[0];
+        }
+        text_push(text, " ");
+        show_term(text, app->args[1]);
+      }
+      text_push(text, ")");
+      break;
+    }
+    case SUP: {
+      text_push(text, "{");
+      show_term(text, term->args[0]);
+      text_push(text, " ");
+      show_term(text, term->args[1]);
+      text_push(text, "}");
+      break;
+    }
+    case OP2: {
+      text_push(text, "(");
+      text_push(text, OPERS[term->numb & 0xF]);
+      text_push(text, " ");
+      show_term(text, term->args[0]);
+      text_push(text, " ");
+      show_term(text, term->args[1]);
+      text_push(text, ")");
+      break;
+    }
+    case U60: {
+      snprintf(buf, sizeof(buf), "%llu", (unsigned long long)term->numb);
+      text_push(text, buf);
+      break;
+    }
+    case F60: {
+      show_f60(text, term->numb);
+      break;
+    }
+    case CTR: {
+      if (show_string(text, term) || show_list(text, term)) {
+        break;
+      }
+      text_push(text, "(");
+      text_push(text, term->name);
+      for (u64 i = 0; i < term->arity; ++i) {
+        text_push(text, " ");
+        show_term(text, term->args[i]);
+      }
+      text_push(text, ")");
+      break;
+    }
+  }
+}
+
+// Shows the term at `host`, which must be normalized first
+static char* show_at(u64 host) {
+  Map names = {0};
+  Map seen = {0};
+  Stacks stacks = {{0}, {0}};
+  gen_var_names(&names, &seen, load_ptr(host));
+  Term* term = readback(&names, &stacks, load_ptr(host));
+  Text text = {0};
+  text_push(&text, "");
+  show_term(&text, term);
+  free_term(term);
+  map_free(&names);
+  map_free(&seen);
+  free(stacks.cols.data);
+  free(stacks.vals.data);
+  return text.data;
+}
+
+// Reads the string at `host`, normalizing it; returns NULL if it isn't one
+static char* read_string(u64 host) {
+  normalize(host);
+  Text text = {0};
+  text_push(&text, "");
+  for (;;) {
+    Ptr term = load_ptr(host);
+    if (get_tag(term) == CTR && get_ext(term) == _String_nil_) {
+      return text.data;
+    } else if (get_tag(term) == CTR && get_ext(term) == _String_cons_ && get_tag(load_arg(term, 0)) == U60) {
+      u64 chr = get_num(load_arg(term, 0));
+      text_push_char(&text, chr > 0x10FFFF ? '?' : (u32)chr);
+      host = get_loc(term, 1);
+    } else if (get_tag(term) == SUP) {
+      host = get_loc(term, 0);
+    } else {
+      free(text.data);
+      return NULL;
+    }
+  }
+}
+
+static Ptr make_string(const char* text) {
+  // Decodes the UTF-8 text to code points, then builds the list from its end
+  Vec chars = {0};
+  const unsigned char* chr = (const unsigned char*)text;
+  while (*chr) {
+    u32 code = *chr++;
+    int more = code >= 0xF0 ? 3 : code >= 0xE0 ? 2 : code >= 0xC0 ? 1 : 0;
+    code &= more == 3 ? 0x07 : more == 2 ? 0x0F : more == 1 ? 0x1F : 0x7F;
+    for (; more > 0 && (*chr & 0xC0) == 0x80; --more) {
+      code = (code << 6) | (*chr++ & 0x3F);
+    }
+    vec_push(&chars, code);
+  }
+  Ptr list = Ctr(_String_nil_, 0);
+  for (u64 i = chars.size; i > 0; --i) {
+    u64 node = alloc_node(2);
+    link(node + 0, U6O(chars.data[i - 1]));
+    link(node + 1, list);
+    list = Ctr(_String_cons_, node);
+  }
+  free(chars.data);
+  return list;
+}
+
+// Builtins
+// --------
+
+// U60.if (cond: Term) (if_t: Term) (if_f: Term)
+static void u60_if_visit(Ptr term) {
+  if (!is_whnf(load_arg(term, 0))) {
+    visit(get_loc(term, 0));
+  }
+}
+
+static int u60_if_apply(u64 host, Ptr term) {
+  Ptr arg0 = load_arg(term, 0);
+  Ptr arg1 = load_arg(term, 1);
+  Ptr arg2 = load_arg(term, 2);
+  if (get_tag(arg0) == SUP) {
+    superpose(host, term, arg0, 0);
+    return 1;
+  }
+  if (get_tag(arg0) == U60) {
+    inc_cost();
+    link(host, get_num(arg0) == 0 ? arg2 : arg1);
+    collect(get_num(arg0) == 0 ? arg1 : arg2);
+    free_node(get_loc(term, 0), 3);
+    return 1;
+  }
+  return 0;
+}
+
+// U60.swap (cond: Term) (pair: Term)
+static void u60_swap_visit(Ptr term) {
+  u60_if_visit(term);
+}
+
+static int u60_swap_apply(u64 host, Ptr term) {
+  Ptr arg0 = load_arg(term, 0);
+  Ptr arg1 = load_arg(term, 1);
+  Ptr arg2 = load_arg(term, 2);
+  if (get_tag(arg0) == SUP) {
+    superpose(host, term, arg0, 0);
+    return 1;
+  }
+  if (get_tag(arg0) == U60) {
+    inc_cost();
+    u64 both = alloc_node(2);
+    link(both + 0, get_num(arg0) == 0 ? arg1 : arg2);
+    link(both + 1, get_num(arg0) == 0 ? arg2 : arg1);
+    link(host, Ctr(_Both_, both));
+    free_node(get_loc(term, 0), 3);
+    return 1;
+  }
+  return 0;
+}
+
+// HVM.log (term: Term) (cont: Term)
+static int hvm_log_apply(u64 host, Ptr term) {
+  normalize(get_loc(term, 0));
+  char* code = show_at(get_loc(term, 0));
+  printf("%s\n", code);
+  free(code);
+  link(host, load_arg(term, 1));
+  collect(load_arg(term, 0));
+  free_node(get_loc(term, 0), 2);
+  return 1;
+}
+
+// HVM.print (text: String) (cont: Term), and HVM.put and HVM.eprint, which are alike
+static int hvm_print_with(u64 host, Ptr term, FILE* file, const char* end) {
+  char* text = read_string(get_loc(term, 0));
+  if (text != NULL) {
+    fprintf(file, "%s%s", text, end);
+    fflush(file);
+    free(text);
+  }
+  link(host, load_arg(term, 1));
+  collect(load_arg(term, 0));
+  free_node(get_loc(term, 0), 2);
+  return 1;
+}
+
+static int hvm_print_apply(u64 host, Ptr term) {
+  return hvm_print_with(host, term, stdout, "\n");
+}
+
+static int hvm_put_apply(u64 host, Ptr term) {
+  return hvm_print_with(host, term, stdout, "");
+}
+
+static int hvm_eprint_apply(u64 host, Ptr term) {
+  return hvm_print_with(host, term, stderr, "\n");
+}
+
+// HVM.exit (code: U60)
+static int hvm_exit_apply(u64 host, Ptr term) {
+  Ptr code = normalize(get_loc(term, 0));
+  if (get_tag(code) != U60) {
+    char* shown = show_at(host);
+    set_error("Runtime failure on: %s", shown);
+    free(shown);
+    longjmp(failure, HVM_ERROR);
+  }
+  exit_code = (int)get_num(code);
+  longjmp(failure, HVM_EXIT);
+}
+
+/*! GENERATED_FUNCTIONS !*/
+
+// Reducer
+// -------
+
+// Reduces the term at `root` to weak head normal form
+static Ptr reduce(u64 root) {
+  u64 base = stack.size;
+  visit(root);
+  while (stack.size > base) {
+    u64 item = stack.data[--stack.size];
+    u64 host = item & ~VISIT;
+    Ptr term = load_ptr(host);
+    if (item & VISIT) {
+      switch (get_tag(term)) {
+        case APP: {
+          vec_push(&stack, host);
+          visit(get_loc(term, 0));
+          break;
+        }
+        case DP0: case DP1: {
+          vec_push(&stack, host);
+          visit(get_loc(term, 2));
+          break;
+        }
+        case OP2: {
+          vec_push(&stack, host);
+          visit(get_loc(term, 1));
+          visit(get_loc(term, 0));
+          break;
+        }
+        case CTR: case FUN: {
+          u64 fid = get_ext(term);
+          if (fid < ID_COUNT && APPLY[fid] != NULL) {
+            vec_push(&stack, host);
+            if (VISIT_FN[fid] != NULL) {
+              VISIT_FN[fid](term);
+            }
+          }
+          break;
+        }
+      }
+    } else {
+      int again = 0;
+      switch (get_tag(term)) {
+        case APP: again = app_apply(host, term); break;
+        case DP0: case DP1: again = dup_apply(host, term); break;
+        case OP2: again = op2_apply(host, term); break;
+        case CTR: case FUN: again = APPLY[get_ext(term)](host, term); break;
+      }
+      if (again) {
+        visit(host);
+      }
+    }
+  }
+  return load_ptr(root);
+}
+
+// Reduces the term at `host` to normal form
+static Ptr normalize(u64 host) {
+  u64 last;
+  do {
+    last = cost;
+    Map seen = {0};
+    Vec locs = {0};
+    vec_push(&locs, host);
+    while (locs.size > 0) {
+      u64 loc = locs.data[--locs.size];
+      Ptr term = reduce(loc);
+      if (map_get(&seen, loc) != NULL) {
+        continue;
+      }
+      map_set(&seen, loc, 1);
+      switch (get_tag(term)) {
+        case LAM: vec_push(&locs, get_loc(term, 1)); break;
+        case APP: case SUP: case OP2: vec_push(&locs, get_loc(term, 1)); vec_push(&locs, get_loc(term, 0)); break;
+        case DP0: case DP1: vec_push(&locs, get_loc(term, 2)); break;
+        case CTR: case FUN: {
+          for (u64 i = arity_of(term); i > 0; --i) {
+            vec_push(&locs, get_loc(term, i - 1));
+          }
+          break;
+        }
+      }
+    }
+    map_free(&seen);
+    free(locs.data);
+  } while (cost != last);
+  return load_ptr(host);
+}
+
+// Command line
+// ------------
+
+static u64 find_id(const char* name, u64 len) {
+  for (u64 id = 0; id < ID_COUNT; ++id) {
+    if (NAMES[id] != NULL && strlen(NAMES[id]) == len && strncmp(NAMES[id], name, len) == 0) {
+      return id;
+    }
+  }
+  return (u64)-1;
+}
+
+static Ptr make_call(u64 fid, u64 loc) {
+  return APPLY[fid] != NULL ? Fun(fid, loc) : Ctr(fid, loc);
+}
+
+// Parses the expression to run: constructors and calls, numbers, strings and lists
+static Ptr parse_term(const char** code) {
+  while (**code == ' ' || **code == '\n' || **code == '\t' || **code == ',') {
+    (*code)++;
+  }
+  const char* init = *code;
+  if (**code == '(' || **code == '[') {
+    char close = **code == '(' ? ')' : ']';
+    (*code)++;
+    Vec args = {0};
+    const char* name = *code;
+    while (close == ')' && **code != 0 && strchr(" \n\t()[]\",", **code) == NULL) {
+      (*code)++;
+    }
+    u64 name_len = (u64)(*code - name);
+    for (;;) {
+      while (**code == ' ' || **code == '\n' || **code == '\t' || **code == ',') {
+        (*code)++;
+      }
+      if (**code == close) {
+        (*code)++;
+        break;
+      }
+      if (**code == 0) {
+        fail("Invalid expression: unexpected end.");
+      }
+      vec_push(&args, parse_term(code));
+    }
+    Ptr term;
+    if (close == ']') {
+      term = Ctr(_List_nil_, 0);
+      for (u64 i = args.size; i > 0; --i) {
+        u64 node = alloc_node(2);
+        link(node + 0, args.data[i - 1]);
+        link(node + 1, term);
+        term = Ctr(_List_cons_, node);
+      }
+    } else {
+      u64 fid = find_id(name, name_len);
+      if (fid == (u64)-1 || ARITY[fid] != args.size) {
+        fail("Invalid expression: unknown name, or wrong number of arguments: '%.*s'.", (int)name_len, name);
+      }
+      u64 node = alloc_node(args.size);
+      for (u64 i = 0; i < args.size; ++i) {
+        link(node + i, args.data[i]);
+      }
+      term = make_call(fid, node);
+    }
+    free(args.data);
+    return term;
+  }
+  if (**code == '"') {
+    (*code)++;
+    Text text = {0};
+    text_push(&text, "");
+    while (**code != '"') {
+      if (**code == 0) {
+        fail("Invalid expression: unterminated string.");
+      }
+      char chr[2] = {**code, 0};
+      text_push(&text, chr);
+      (*code)++;
+    }
+    (*code)++;
+    Ptr term = make_string(text.data);
+    free(text.data);
+    return term;
+  }
+  if (**code >= '0' && **code <= '9') {
+    char* end;
+    u64 numb = strtoull(*code, &end, 0);
+    if (*end == '.') {
+      double val = strtod(*code, &end);
+      *code = end;
+      return F6O(f60_new(val));
+    }
+    *code = end;
+    return U6O(u60_new(numb));
+  }
+  while (**code != 0 && strchr(" \n\t()[]\",", **code) == NULL) {
+    (*code)++;
+  }
+  u64 fid = find_id(init, (u64)(*code - init));
+  if (*code == init || fid == (u64)-1 || ARITY[fid] != 0) {
+    fail("Invalid expression: unknown name: '%.*s'.", (int)(*code - init + 1), init);
+  }
+  return make_call(fid, 0);
+}
+
+// Library
+// -------
+//
+// The functions the header declares, which `main` uses too. A failure, or `HVM.exit`, returns from
+// the evaluation with its status, instead of ending the process.
+
+static u64 eval_time = 0; // the milliseconds the last evaluation took
+
+// Frees the heap, and everything else the runtime allocated
+void hvm_free(void) {
+  free(heap);
+  heap = NULL;
+  heap_size = 0;
+  for (u64 i = 0; i < FREE_LISTS; ++i) {
+    free(free_list[i].data);
+    free_list[i] = (Vec){0};
+  }
+  free(stack.data);
+  stack = (Vec){0};
+  free(error);
+  error = NULL;
+}
+
+// Allocates a heap of `size` nodes, freeing the previous one. Returns HVM_OK, or HVM_ERROR.
+int hvm_init(u64 size) {
+  hvm_free();
+  heap = size >= 2 ? (Ptr*)malloc(size * sizeof(Ptr)) : NULL;
+  if (heap == NULL) {
+    set_error("Couldn't allocate a heap of %llu nodes.", (unsigned long long)size);
+    return HVM_ERROR;
+  }
+  heap_size = size;
+  return HVM_OK;
+}
+
+// Evaluates `expr`, or `Main` if it's NULL, which receives `args` if it takes an argument
+static int eval(const char* expr, int argc, char** args, char** norm) {
+  if (heap == NULL) {
+    set_error("The runtime isn't initialized. Call 'hvm_init' first.");
+    return HVM_ERROR;
+  }
+  heap_next = 1;
+  for (u64 i = 0; i < FREE_LISTS; ++i) {
+    free_list[i].size = 0;
+  }
+  stack.size = 0;
+  cost = 0;
+  int status = setjmp(failure);
+  if (status != 0) {
+    return status;
+  }
+
+  // A `Main` taking an argument receives the arguments, as a list of strings
+  u64 main_id = find_id("Main", 4);
+  if (expr == NULL && main_id != (u64)-1 && ARITY[main_id] == 1) {
+    Ptr list = Ctr(_List_nil_, 0);
+    for (int i = argc; i > 0; --i) {
+      u64 node = alloc_node(2);
+      link(node + 0, make_string(args[i - 1]));
+      link(node + 1, list);
+      list = Ctr(_List_cons_, node);
+    }
+    u64 call = alloc_node(1);
+    link(call, list);
+    link(0, make_call(main_id, call));
+  } else {
+    const char* code = expr == NULL ? "Main" : expr;
+    link(0, parse_term(&code));
+  }
+
+  clock_t init = clock();
+  normalize(0);
+  eval_time = (u64)((clock() - init) * 1000 / CLOCKS_PER_SEC);
+  *norm = show_at(0);
+  return HVM_OK;
+}
+
+// Normalizes `expr`, or `Main` if it's NULL, on an emptied heap. Returns HVM_OK, setting `*norm`
+// to the normal form, shown like `hvm run` does, which the caller must `free`. Otherwise, returns
+// HVM_ERROR, or HVM_EXIT if the program called `HVM.exit`.
+int hvm_eval(const char* expr, char** norm) {
+  return eval(expr, 0, NULL, norm);
+}
+
+// The message of the last HVM_ERROR
+const char* hvm_error(void) {
+  return error != NULL ? error : "";
+}
+
+// The code of the last HVM_EXIT
+int hvm_exit_code(void) {
+  return exit_code;
+}
+
+#ifndef HVM_NO_MAIN
+int main(int argc, char** argv) {
+  const char* expr = NULL;
+  int show_cost = 0;
+  int args_from = argc;
+  u64 size = (u64)1 << 28;
+  for (int i = 1; i < argc; ++i) {
+    if (strcmp(argv[i], "--") == 0) {
+      args_from = i + 1;
+      break;
+    } else if ((strcmp(argv[i], "-s") == 0 || strcmp(argv[i], "--size") == 0) && i + 1 < argc) {
+      size = strtoull(argv[++i], NULL, 10);
+    } else if ((strcmp(argv[i], "-t") == 0 || strcmp(argv[i], "--tids") == 0) && i + 1 < argc) {
+      ++i; // reduction is single-threaded
+    } else if (strcmp(argv[i], "-c") == 0 || strcmp(argv[i], "--cost") == 0) {
+      show_cost = 1;
+    } else if (strcmp(argv[i], "-h") == 0 || strcmp(argv[i], "--help") == 0) {
+      printf("Usage: %s [-s SIZE] [-c] [EXPR] [-- ARGS...]\n", argv[0]);
+      return 0;
+    } else if (expr == NULL) {
+      expr = argv[i];
+    } else {
+      fprintf(stderr, "Unexpected argument: '%s'.\n", argv[i]);
+      return 1;
+    }
+  }
+
+  if (hvm_init(size) != HVM_OK) {
+    fprintf(stderr, "Couldn't allocate the heap. Use '-s' to give a smaller one.\n");
+    return 1;
+  }
+  char* norm = NULL;
+  int status = eval(expr, argc - args_from, argv + args_from, &norm);
+  fflush(stdout);
+  if (status != HVM_OK) {
+    if (status == HVM_ERROR) {
+      fprintf(stderr, "%s\n", hvm_error());
+    }
+    hvm_free();
+    return status == HVM_EXIT ? hvm_exit_code() : 1;
+  }
+
+  printf("%s\n", norm);
+  free(norm);
+  if (show_cost) {
+    fprintf(stderr, "\n\x1b[32m[TIME: %.2fs | COST: %llu | RPS: %.2fm]\x1b[0m\n", (double)eval_time / 1000.0, (unsigned long long)cost, (double)cost / (double)eval_time / 1000.0);
+  }
+  hvm_free();
+  return 0;
+}
+#endif
@@ -0,0 +1,43 @@
+// The library interface of the C file `hvm compile --target c` writes next to this header. Compile
+// that file with `-DHVM_NO_MAIN`, which leaves `main` out, and link it to your C or C++ program:
+//
+//   cc -O2 -DHVM_NO_MAIN -c <name>.c -o <name>.o
+//   cc -O2 app.c <name>.o -o app -lm
+//
+// The runtime is global and single-threaded, so only one thread should call these at a time.
+
+#ifndef HVM_COMPILED_H
+#define HVM_COMPILED_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// What `hvm_init` and `hvm_eval` return
+#define HVM_OK 0    // it succeeded
+#define HVM_ERROR 1 // it failed, and `hvm_error` tells why
+#define HVM_EXIT 2  // the program called `HVM.exit`, with the code `hvm_exit_code` returns
+
+// Allocates a heap of `size` nodes, of 8 bytes each, freeing the previous one
+int hvm_init(uint64_t size);
+
+// Normalizes `expr`, or `Main` if it's NULL, on an emptied heap. On HVM_OK, sets `*norm` to the
+// normal form, shown like `hvm run` does, which the caller must `free`.
+int hvm_eval(const char* expr, char** norm);
+
+// The message of the last HVM_ERROR
+const char* hvm_error(void);
+
+// The code of the last HVM_EXIT
+int hvm_exit_code(void);
+
+// Frees the heap, and everything else the runtime allocated
+void hvm_free(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,261 @@
+// C interface
+// ===========
+//
+// Exposes the runtime to other languages, through the cdylib. The matching declarations are on
+// `include/hvm.h`, which is generated from this file by `cbindgen --config cbindgen.toml`, and
+// checked against it by `tests/ffi.rs`.
+//
+// Runtimes are opaque handles, fallible functions return an `HvmStatus`, and strings returned to
+// the caller are owned by it, and must be released with `hvm_string_free`.
+
+use crate::language;
+use crate::runtime;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// the result of a fallible call
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HvmStatus {
+  Ok = 0,
+  /// a required pointer was null
+  NullPointer = 1,
+  /// a string wasn't valid UTF-8
+  InvalidUtf8 = 2,
+  /// the code or expression failed to parse
+  ParseError = 3,
+  /// the evaluation took more rewrites than allowed
+  LimitExceeded = 4,
+  /// the evaluation failed, for example due to an unbound symbol
+  RuntimeError = 5,
+  /// the code parsed, but the runtime couldn't be built from it, for example due to inconsistent arities
+  BuildError = 6,
+  /// the evaluation called `HVM.exit`, whose code is returned by `hvm_runtime_exit_code`
+  Exited = 7,
+}
+
+/// a runtime, with the code added to it so far
+pub struct HvmRuntime {
+  heap_size: usize,
+  thread_count: usize,
+  code: String,
+  // Built on the first evaluation, and dropped when code is added
+  runtime: Option<runtime::Runtime>,
+  // Rewrites made by runtimes dropped before
+  cost: u64,
+  // Code given to the last `HVM.exit`
+  exit_code: i32,
+}
+
+impl HvmRuntime {
+  fn runtime(&mut self) -> Result<&mut runtime::Runtime, String> {
+    if self.runtime.is_none() {
+      // Reports invalid rules, such as inconsistent arities, instead of failing while building
+      language::rulebook::check_file(&language::syntax::read_file(&self.code)?)?;
+      let runtime = runtime::RuntimeBuilder::default()
+        .set_heap_size(self.heap_size)
+        .set_thread_count(self.thread_count)
+        .add_code(&self.code)?
+        .build();
+      self.runtime = Some(runtime);
+    }
+    Ok(self.runtime.as_mut().unwrap())
+  }
+
+  fn drop_runtime(&mut self) {
+    if let Some(runtime) = self.runtime.take() {
+      self.cost += runtime.get_rewrite_count() as u64;
+    }
+  }
+}
+
+// Reads a string given by the caller
+unsafe fn read_str<'a>(text: *const c_char) -> Result<&'a str, HvmStatus> {
+  if text.is_null() {
+    return Err(HvmStatus::NullPointer);
+  }
+  CStr::from_ptr(text).to_str().map_err(|_| HvmStatus::InvalidUtf8)
+}
+
+// Hands a string to the caller, if it asked for one
+unsafe fn write_str(out: *mut *mut c_char, text: &str) {
+  if !out.is_null() {
+    // Interior nul bytes can't be represented, so they're dropped
+    let text = CString::new(text.replace('\0', "")).unwrap();
+    *out = text.into_raw();
+  }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+  if let Some(message) = payload.downcast_ref::<&str>() {
+    message.to_string()
+  } else if let Some(message) = payload.downcast_ref::<String>() {
+    message.clone()
+  } else if let Some(failure) = payload.downcast_ref::<runtime::Failure>() {
+    failure.to_string()
+  } else {
+    "Runtime failure.".to_string()
+  }
+}
+
+/// creates a runtime with the given heap size (in 64-bit nodes) and number of threads,
+/// where 0 picks the same defaults as the `hvm` command.
+///
+/// it must be released with `hvm_runtime_free`.
+#[no_mangle]
+pub extern "C" fn hvm_runtime_new(heap_size: usize, thread_count: usize) -> *mut HvmRuntime {
+  let heap_size = if heap_size == 0 { runtime::default_heap_size() } else { heap_size };
+  let thread_count = if thread_count == 0 { runtime::default_heap_tids() } else { thread_count };
+  let runtime = HvmRuntime { heap_size, thread_count, code: String::new(), runtime: None, cost: 0, exit_code: 0 };
+  Box::into_raw(Box::new(runtime))
+}
+
+/// releases a runtime created by `hvm_runtime_new`.
+///
+/// # Safety
+///
+/// `runtime` must be null, or a runtime which wasn't released yet.
+#[no_mangle]
+pub unsafe extern "C" fn hvm_runtime_free(runtime: *mut HvmRuntime) {
+  if !runtime.is_null() {
+    drop(Box::from_raw(runtime));
+  }
+}
+
+/// adds the rules written in the given HVM source code.
+///
+/// on failure, if `error` isn't null, it receives the error message.
+///
+/// # Safety
+///
+/// `runtime` must be a live runtime, and `code` a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn hvm_runtime_add_code(runtime: *mut HvmRuntime, code: *const c_char, error: *mut *mut c_char) -> HvmStatus {
+  let runtime = match runtime.as_mut() {
+    Some(runtime) => runtime,
+    None => return HvmStatus::NullPointer,
+  };
+  let code = match read_str(code) {
+    Ok(code) => code,
+    Err(status) => return status,
+  };
+  if let Err(err) = language::syntax::read_file(code) {
+    write_str(error, &err);
+    return HvmStatus::ParseError;
+  }
+  runtime.drop_runtime();
+  runtime.code.push('\n');
+  runtime.code.push_str(code);
+  HvmStatus::Ok
+}
+
+/// evaluates the given expression to normal form.
+///
+/// if `output` isn't null, it receives the result on success, or the error message on failure.
+///
+/// # Safety
+///
+/// `runtime` must be a live runtime, and `expr` a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn hvm_runtime_eval(runtime: *mut HvmRuntime, expr: *const c_char, output: *mut *mut c_char) -> HvmStatus {
+  hvm_runtime_eval_limited(runtime, expr, u64::MAX, output)
+}
+
+/// evaluates the given expression to normal form, like `hvm_runtime_eval`,
+/// giving up with `LimitExceeded` once it takes more than `max_rewrites` graph rewrites.
+///
+/// # Safety
+///
+/// `runtime` must be a live runtime, and `expr` a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn hvm_runtime_eval_limited(runtime: *mut HvmRuntime, expr: *const c_char, max_rewrites: u64, output: *mut *mut c_char) -> HvmStatus {
+  let runtime = match runtime.as_mut() {
+    Some(runtime) => runtime,
+    None => return HvmStatus::NullPointer,
+  };
+  let expr = match read_str(expr) {
+    Ok(expr) => expr,
+    Err(status) => return status,
+  };
+  let term = match language::syntax::read_term(expr) {
+    Ok(term) => term,
+    Err(err) => {
+      write_str(output, &err);
+      return HvmStatus::ParseError;
+    }
+  };
+  let result = catch_unwind(AssertUnwindSafe(|| -> Result<Option<language::syntax::Term>, String> {
+    let runtime = runtime.runtime()?;
+    if max_rewrites == u64::MAX {
+      Ok(Some(runtime.normalize_term(&term)))
+    } else {
+      Ok(runtime.normalize_term_limited(&term, max_rewrites))
+    }
+  }));
+  match result {
+    Ok(Ok(Some(norm))) => {
+      write_str(output, &norm.to_string());
+      HvmStatus::Ok
+    }
+    Ok(Ok(None)) => {
+      write_str(output, &format!("Exceeded the limit of {} rewrites.", max_rewrites));
+      HvmStatus::LimitExceeded
+    }
+    Ok(Err(err)) => {
+      write_str(output, &err);
+      HvmStatus::BuildError
+    }
+    Err(payload) => {
+      // The heap may have been left in the middle of a reduction, so the runtime is rebuilt
+      runtime.drop_runtime();
+      write_str(output, &panic_message(&*payload));
+      match payload.downcast_ref::<runtime::Failure>() {
+        Some(runtime::Failure::Exit(code)) => {
+          runtime.exit_code = *code;
+          HvmStatus::Exited
+        }
+        _ => HvmStatus::RuntimeError,
+      }
+    }
+  }
+}
+
+/// returns the number of graph rewrites made by the runtime, since its creation.
+///
+/// # Safety
+///
+/// `runtime` must be null, or a live runtime.
+#[no_mangle]
+pub unsafe extern "C" fn hvm_runtime_cost(runtime: *const HvmRuntime) -> u64 {
+  match runtime.as_ref() {
+    Some(runtime) => runtime.cost + runtime.runtime.as_ref().map_or(0, |runtime| runtime.get_rewrite_count() as u64),
+    None => 0,
+  }
+}
+
+/// returns the code given to `HVM.exit` by the last evaluation which returned `Exited`,
+/// or 0 if there was none.
+///
+/// # Safety
+///
+/// `runtime` must be null, or a live runtime.
+#[no_mangle]
+pub unsafe extern "C" fn hvm_runtime_exit_code(runtime: *const HvmRuntime) -> i32 {
+  match runtime.as_ref() {
+    Some(runtime) => runtime.exit_code,
+    None => 0,
+  }
+}
+
+/// releases a string returned by the runtime.
+///
+/// # Safety
+///
+/// `text` must be null, or a string returned by the runtime which wasn't released yet.
+#[no_mangle]
+pub unsafe extern "C" fn hvm_string_free(text: *mut c_char) {
+  if !text.is_null() {
+    drop(CString::from_raw(text));
+  }
+}
@@ -1,3 +1,5 @@
 pub mod readback;
 pub mod rulebook;
+#[cfg(feature = "serde")]
+pub mod serde_term;
 pub mod syntax;
@@ -8,6 +8,72 @@ use crate::runtime as runtime;
 use crate::runtime::{Ptr, Heap, Program};
 use std::collections::{hash_map, HashMap, HashSet};
 
+// Tracks, for each dup label, which side of a superposition with that label must be taken
+struct Stacks {
+  stacks: HashMap<Ptr, Vec<bool>>,
+}
+
+impl Stacks {
+  fn new() -> Stacks {
+    Stacks { stacks: HashMap::new() }
+  }
+  fn get(&self, col: Ptr) -> Option<&Vec<bool>> {
+    self.stacks.get(&col)
+  }
+  fn pop(&mut self, col: Ptr) -> bool {
+    let stack = self.stacks.entry(col).or_insert_with(Vec::new);
+    stack.pop().unwrap_or(false)
+  }
+  fn push(&mut self, col: Ptr, val: bool) {
+    let stack = self.stacks.entry(col).or_insert_with(Vec::new);
+    stack.push(val);
+  }
+}
+
+// Tracks how much of a term can still be read back, see `ReadbackOptions`
+struct Budget {
+  max_depth: usize,
+  nodes_left: usize,
+}
+
+impl Budget {
+  fn new(options: &ReadbackOptions) -> Budget {
+    Budget {
+      max_depth: options.max_depth.unwrap_or(usize::MAX),
+      nodes_left: options.max_nodes.unwrap_or(usize::MAX),
+    }
+  }
+  fn unlimited() -> Budget {
+    Budget::new(&ReadbackOptions::default())
+  }
+  // The deepest a reader must walk, since a term of N nodes is never deeper than N
+  fn reach(&self) -> usize {
+    self.max_depth.min(self.nodes_left)
+  }
+  // Consumes one node at the given depth; returns false when the term must be cut there
+  fn take(&mut self, depth: usize) -> bool {
+    if depth > self.max_depth || self.nodes_left == 0 {
+      return false;
+    }
+    self.nodes_left -= 1;
+    true
+  }
+}
+
+// Dups and superpositions being resolved don't show up in the output, so they cost nothing
+fn is_hidden(stacks: &Stacks, term: Ptr) -> bool {
+  match runtime::get_tag(term) {
+    runtime::DP0 | runtime::DP1 => true,
+    runtime::SUP => stacks.get(runtime::get_ext(term)).is_some_and(|stack| !stack.is_empty()),
+    _ => false,
+  }
+}
+
+// Stands for the part of a term that was left out by the readback limits
+fn ellipsis() -> Box<language::syntax::Term> {
+  Box::new(language::syntax::Term::Var { name: "...".to_string() })
+}
+
 /// Reads back a term from Runtime's memory
 pub fn as_code(heap: &Heap, prog: &Program, host: u64) -> String {
   return format!("{}", as_term(heap, prog, host));
@@ -15,15 +81,20 @@ pub fn as_code(heap: &Heap, prog: &Program, host: u64) -> String {
 
 /// Reads back a term from Runtime's memory
 pub fn as_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::syntax::Term> {
+  read_tree(heap, prog, host, &mut Budget::unlimited())
+}
+
+fn read_tree(heap: &Heap, prog: &Program, host: u64, budget: &mut Budget) -> Box<language::syntax::Term> {
   struct CtxName<'a> {
     heap: &'a Heap,
     prog: &'a Program,
     names: &'a mut HashMap<Ptr, String>,
     seen: &'a mut HashSet<Ptr>,
+    reach: usize,
   }
 
-  fn gen_var_names(heap: &Heap, prog: &Program, ctx: &mut CtxName, term: Ptr, depth: u32) {
-    if ctx.seen.contains(&term) {
+  fn gen_var_names(heap: &Heap, prog: &Program, ctx: &mut CtxName, term: Ptr, depth: usize) {
+    if ctx.seen.contains(&term) || depth > ctx.reach {
       return;
     };
 
@@ -53,11 +124,11 @@ pub fn as_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::syntax::
       }
       runtime::DP0 => {
         let arg = runtime::load_arg(&ctx.heap, term, 2);
-        gen_var_names(heap, prog, ctx, arg, depth + 1);
+        gen_var_names(heap, prog, ctx, arg, depth);
       }
       runtime::DP1 => {
         let arg = runtime::load_arg(&ctx.heap, term, 2);
-        gen_var_names(heap, prog, ctx, arg, depth + 1);
+        gen_var_names(heap, prog, ctx, arg, depth);
       }
       runtime::OP2 => {
         let arg0 = runtime::load_arg(&ctx.heap, term, 0);
@@ -84,30 +155,13 @@ pub fn as_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::syntax::
     prog: &'a Program,
     names: &'a HashMap<Ptr, String>,
     seen: &'a HashSet<Ptr>,
+    budget: &'a mut Budget,
   }
 
-  struct Stacks {
-    stacks: HashMap<Ptr, Vec<bool>>,
-  }
-
-  impl Stacks {
-    fn new() -> Stacks {
-      Stacks { stacks: HashMap::new() }
+  fn readback(heap: &Heap, prog: &Program, ctx: &mut CtxGo, stacks: &mut Stacks, term: Ptr, depth: usize) -> Box<language::syntax::Term> {
+    if !is_hidden(stacks, term) && !ctx.budget.take(depth) {
+      return ellipsis();
     }
-    fn get(&self, col: Ptr) -> Option<&Vec<bool>> {
-      self.stacks.get(&col)
-    }
-    fn pop(&mut self, col: Ptr) -> bool {
-      let stack = self.stacks.entry(col).or_insert_with(Vec::new);
-      stack.pop().unwrap_or(false)
-    }
-    fn push(&mut self, col: Ptr, val: bool) {
-      let stack = self.stacks.entry(col).or_insert_with(Vec::new);
-      stack.push(val);
-    }
-  }
-
-  fn readback(heap: &Heap, prog: &Program, ctx: &mut CtxGo, stacks: &mut Stacks, term: Ptr, depth: u32) -> Box<language::syntax::Term> {
     match runtime::get_tag(term) {
       runtime::LAM => {
         let body = runtime::load_arg(&ctx.heap, term, 1);
@@ -136,7 +190,7 @@ pub fn as_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::syntax::
           let arg_idx = *val as u64;
           let val = runtime::load_arg(&ctx.heap, term, arg_idx);
           let old = stacks.pop(col);
-          let got = readback(heap, prog, ctx, stacks, val, depth + 1);
+          let got = readback(heap, prog, ctx, stacks, val, depth);
           stacks.push(col, old);
           got
         } else {
@@ -151,7 +205,7 @@ pub fn as_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::syntax::
         let col = runtime::get_ext(term);
         let val = runtime::load_arg(&ctx.heap, term, 2);
         stacks.push(col, false);
-        let result = readback(heap, prog, ctx, stacks, val, depth + 1);
+        let result = readback(heap, prog, ctx, stacks, val, depth);
         stacks.pop(col);
         result
       }
@@ -159,7 +213,7 @@ pub fn as_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::syntax::
         let col = runtime::get_ext(term);
         let val = runtime::load_arg(&ctx.heap, term, 2);
         stacks.push(col, true);
-        let result = readback(heap, prog, ctx, stacks, val, depth + 1);
+        let result = readback(heap, prog, ctx, stacks, val, depth);
         stacks.pop(col);
         result
       }
@@ -229,18 +283,22 @@ pub fn as_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::syntax::
   let mut names = HashMap::<Ptr, String>::new();
   let mut seen = HashSet::<Ptr>::new();
 
-  let ctx = &mut CtxName { heap, prog, names: &mut names, seen: &mut seen };
+  let ctx = &mut CtxName { heap, prog, names: &mut names, seen: &mut seen, reach: budget.reach() };
   gen_var_names(heap, prog, ctx, term, 0);
 
-  let ctx = &mut CtxGo { heap, prog, names: &names, seen: &seen };
+  let ctx = &mut CtxGo { heap, prog, names: &names, seen: &seen, budget };
   let mut stacks = Stacks::new();
   readback(heap, prog, ctx, &mut stacks, term, 0)
 }
 
 // Reads a term linearly, i.e., preserving dups
 pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::syntax::Term> {
+  read_linear(heap, prog, host, &mut Budget::unlimited())
+}
+
+fn read_linear(heap: &Heap, prog: &Program, host: u64, budget: &mut Budget) -> Box<language::syntax::Term> {
   enum StackItem {
-    Term(Ptr),
+    Term(Ptr, usize),
     Resolver(Ptr),
   }
 
@@ -252,7 +310,7 @@ pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::s
     }
   }
 
-  fn dups(heap: &Heap, prog: &Program, term: Ptr, names: &mut HashMap<u64, String>) -> language::syntax::Term {
+  fn dups(heap: &Heap, prog: &Program, term: Ptr, names: &mut HashMap<u64, String>, budget: &mut Budget) -> language::syntax::Term {
     let mut lets: HashMap<u64, u64> = HashMap::new();
     let mut kinds: HashMap<u64, u64> = HashMap::new();
     let mut stack = vec![term];
@@ -301,18 +359,22 @@ pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::s
       }
     }
 
-    let cont = expr(heap, prog, term, &names);
+    let cont = expr(heap, prog, term, &names, budget);
     if lets.is_empty() {
       cont
     } else {
       let mut output = language::syntax::Term::Var { name: "?".to_string() };
       for (i, (_key, pos)) in lets.iter().enumerate() {
+        // Nothing else fits, so the remaining dups are left out
+        if budget.nodes_left == 0 {
+          break;
+        }
         // todo: reverse
         let what = String::from("?h");
         let name = names.get(&pos).unwrap_or(&what);
         let nam0 = if runtime::load_ptr(heap, pos + 0) == runtime::Era() { String::from("*") } else { format!("a{}", name) };
         let nam1 = if runtime::load_ptr(heap, pos + 1) == runtime::Era() { String::from("*") } else { format!("b{}", name) };
-        let expr = expr(heap, prog, runtime::load_ptr(heap, pos + 2), &names);
+        let expr = expr(heap, prog, runtime::load_ptr(heap, pos + 2), &names, budget);
         if i == 0 {
           output = language::syntax::Term::Dup { nam0, nam1, expr: Box::new(expr), body: Box::new(cont.clone()) };
         } else {
@@ -323,8 +385,8 @@ pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::s
     }
   }
 
-  fn expr(heap: &Heap, prog: &Program, term: Ptr, names: &HashMap<u64, String>) -> language::syntax::Term {
-    let mut stack = vec![StackItem::Term(term)];
+  fn expr(heap: &Heap, prog: &Program, term: Ptr, names: &HashMap<u64, String>, budget: &mut Budget) -> language::syntax::Term {
+    let mut stack = vec![StackItem::Term(term, 0)];
     let mut output : Vec<language::syntax::Term> = Vec::new();
     while !stack.is_empty() {
       let item = stack.pop().unwrap();
@@ -338,6 +400,7 @@ pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::s
               for _ in 0..arit {
                 args.push(Box::new(output.pop().unwrap()));
               }
+              args.reverse();
               let name = ctr_name(prog, func);
               output.push(language::syntax::Term::Ctr { name, args });
             },
@@ -348,6 +411,7 @@ pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::s
               for _ in 0..arit {
                 args.push(Box::new(output.pop().unwrap()));
               }
+              args.reverse();
               let name = ctr_name(prog, func);
               output.push(language::syntax::Term::Ctr { name, args });
             }
@@ -389,7 +453,11 @@ pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::s
             _ => panic!("Term not valid in readback"),
           }
         },
-        StackItem::Term(term) => {
+        StackItem::Term(term, depth) => {
+          if !matches!(runtime::get_tag(term), runtime::SUP | runtime::ERA) && !budget.take(depth) {
+            output.push(*ellipsis());
+            continue;
+          }
           match runtime::get_tag(term) {
             runtime::DP0 => {
               let name = format!("a{}", names.get(&runtime::get_loc(term, 0)).unwrap_or(&String::from("?a")));
@@ -405,18 +473,18 @@ pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::s
             }
             runtime::LAM => {
               stack.push(StackItem::Resolver(term));
-              stack.push(StackItem::Term(runtime::load_arg(heap, term, 1)));
+              stack.push(StackItem::Term(runtime::load_arg(heap, term, 1), depth + 1));
             }
             runtime::APP => {
               stack.push(StackItem::Resolver(term));
-              stack.push(StackItem::Term(runtime::load_arg(heap, term, 1)));
-              stack.push(StackItem::Term(runtime::load_arg(heap, term, 0)));
+              stack.push(StackItem::Term(runtime::load_arg(heap, term, 1), depth + 1));
+              stack.push(StackItem::Term(runtime::load_arg(heap, term, 0), depth + 1));
             }
             runtime::SUP => {}
             runtime::OP2 => {
               stack.push(StackItem::Resolver(term));
-              stack.push(StackItem::Term(runtime::load_arg(heap, term, 1)));
-              stack.push(StackItem::Term(runtime::load_arg(heap, term, 0)));
+              stack.push(StackItem::Term(runtime::load_arg(heap, term, 1), depth + 1));
+              stack.push(StackItem::Term(runtime::load_arg(heap, term, 0), depth + 1));
             }
             runtime::U60 => {
               let numb = runtime::get_num(term);
@@ -429,15 +497,15 @@ pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::s
             runtime::CTR => {
               let arit = runtime::arity_of(&prog.aris, term);
               stack.push(StackItem::Resolver(term));
-              for i in 0..arit {
-                stack.push(StackItem::Term(runtime::load_arg(heap, term, i)));
+              for i in (0..arit).rev() {
+                stack.push(StackItem::Term(runtime::load_arg(heap, term, i), depth + 1));
               }
             }
             runtime::FUN => {
               let arit = runtime::arity_of(&prog.aris, term);
               stack.push(StackItem::Resolver(term));
-              for i in 0..arit {
-                stack.push(StackItem::Term(runtime::load_arg(heap, term, i)));
+              for i in (0..arit).rev() {
+                stack.push(StackItem::Term(runtime::load_arg(heap, term, i), depth + 1));
               }
             }
             runtime::ERA => {}
@@ -450,7 +518,7 @@ pub fn as_linear_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::s
   }
 
   let mut names: HashMap<u64, String> = HashMap::new();
-  Box::new(dups(heap, prog, runtime::load_ptr(heap, host), &mut names))
+  Box::new(dups(heap, prog, runtime::load_ptr(heap, host), &mut names, budget))
 }
 
 /// Reads back a term from Runtime's memory
@@ -459,11 +527,482 @@ pub fn as_linear_code(heap: &Heap, prog: &Program, host: u64) -> String {
 }
 
 
+// Reads a term preserving sharing, i.e., binding duplicated expressions with `let`
+//
+// A duplication can only be shared when both of its copies read back to the same term, which
+// holds when its expression has no superpositions, since those are the only nodes whose readback
+// depends on which copy is being read. Every other duplication is expanded, as in `as_term`.
+// Each `let` is placed right inside the innermost lambda binding one of its free variables, so
+// that, even for huge results, the output stays proportional to the size of the heap graph.
+pub fn as_shared_term(heap: &Heap, prog: &Program, host: u64) -> Box<language::syntax::Term> {
+  read_shared(heap, prog, host, &mut Budget::unlimited())
+}
+
+fn read_shared(heap: &Heap, prog: &Program, host: u64, budget: &mut Budget) -> Box<language::syntax::Term> {
+  struct CtxShared<'a> {
+    heap: &'a Heap,
+    prog: &'a Program,
+    names: HashMap<Ptr, String>,                    // lambda variable names
+    sup_free: HashMap<u64, bool>,                   // dup node -> expression has no superposition
+    shared: HashMap<u64, (String, Option<u64>)>,    // dup node -> (let name, scope of the let)
+    scopes: Vec<(u64, Vec<(String, language::syntax::Term)>)>, // lambda node -> pending lets
+    lets: u64,
+    budget: &'a mut Budget,
+  }
+
+  // The root scope, which isn't bound to any lambda
+  const ROOT: u64 = u64::MAX;
+
+  fn is_sup_free(ctx: &mut CtxShared, term: Ptr, depth: usize) -> bool {
+    // Too deep to be read back anyway, so it is safer to not share it
+    if depth > ctx.budget.reach() {
+      return false;
+    }
+    match runtime::get_tag(term) {
+      runtime::SUP => false,
+      runtime::DP0 | runtime::DP1 => {
+        let dup = runtime::get_loc(term, 0);
+        if let Some(free) = ctx.sup_free.get(&dup) {
+          return *free;
+        }
+        // Assumes it is, so that a dup reachable from its own expression doesn't loop forever
+        ctx.sup_free.insert(dup, true);
+        let free = is_sup_free(ctx, runtime::load_arg(ctx.heap, term, 2), depth);
+        ctx.sup_free.insert(dup, free);
+        free
+      }
+      runtime::LAM => is_sup_free(ctx, runtime::load_arg(ctx.heap, term, 1), depth + 1),
+      runtime::APP | runtime::OP2 => {
+        is_sup_free(ctx, runtime::load_arg(ctx.heap, term, 0), depth + 1) && is_sup_free(ctx, runtime::load_arg(ctx.heap, term, 1), depth + 1)
+      }
+      runtime::CTR | runtime::FUN => {
+        let arit = runtime::arity_of(&ctx.prog.aris, term);
+        (0 .. arit).all(|i| is_sup_free(ctx, runtime::load_arg(ctx.heap, term, i), depth + 1))
+      }
+      _ => true,
+    }
+  }
+
+  fn var_name(ctx: &mut CtxShared, var: Ptr) -> String {
+    let count = ctx.names.len();
+    ctx.names.entry(var).or_insert_with(|| format!("x{}", count)).clone()
+  }
+
+  // Reads a term back, adding the lambdas whose variables occur free on it to `frees`
+  fn readback(ctx: &mut CtxShared, stacks: &mut Stacks, frees: &mut HashSet<u64>, term: Ptr, depth: usize) -> Box<language::syntax::Term> {
+    if !is_hidden(stacks, term) && !ctx.budget.take(depth) {
+      // What was left out may use any variable in scope, so lets holding it can't float outwards
+      if let Some((lam, _)) = ctx.scopes.last() {
+        if *lam != ROOT {
+          frees.insert(*lam);
+        }
+      }
+      return ellipsis();
+    }
+    match runtime::get_tag(term) {
+      runtime::LAM => {
+        let lam = runtime::get_loc(term, 0);
+        let bind = runtime::load_arg(ctx.heap, term, 0);
+        let name = if runtime::get_tag(bind) == runtime::ERA {
+          "*".to_string()
+        } else {
+          var_name(ctx, runtime::Var(lam))
+        };
+        ctx.scopes.push((lam, Vec::new()));
+        let mut body = readback(ctx, stacks, frees, runtime::load_arg(ctx.heap, term, 1), depth + 1);
+        let (_, lets) = ctx.scopes.pop().unwrap();
+        for (name, expr) in lets.into_iter().rev() {
+          body = Box::new(language::syntax::Term::Let { name, expr: Box::new(expr), body });
+        }
+        frees.remove(&lam);
+        Box::new(language::syntax::Term::Lam { name, body })
+      }
+      runtime::APP => {
+        let func = readback(ctx, stacks, frees, runtime::load_arg(ctx.heap, term, 0), depth + 1);
+        let argm = readback(ctx, stacks, frees, runtime::load_arg(ctx.heap, term, 1), depth + 1);
+        Box::new(language::syntax::Term::App { func, argm })
+      }
+      runtime::SUP => {
+        let col = runtime::get_ext(term);
+        if let Some(val) = stacks.get(col).and_then(|stack| stack.last()) {
+          let val = runtime::load_arg(ctx.heap, term, *val as u64);
+          let old = stacks.pop(col);
+          let got = readback(ctx, stacks, frees, val, depth);
+          stacks.push(col, old);
+          got
+        } else {
+          let val0 = readback(ctx, stacks, frees, runtime::load_arg(ctx.heap, term, 0), depth + 1);
+          let val1 = readback(ctx, stacks, frees, runtime::load_arg(ctx.heap, term, 1), depth + 1);
+          Box::new(language::syntax::Term::Sup { val0, val1 })
+        }
+      }
+      runtime::DP0 | runtime::DP1 => {
+        let dup = runtime::get_loc(term, 0);
+        let used_twice = runtime::get_tag(runtime::load_ptr(ctx.heap, dup)) != runtime::ERA
+                      && runtime::get_tag(runtime::load_ptr(ctx.heap, dup + 1)) != runtime::ERA;
+        if used_twice && is_sup_free(ctx, term, 0) {
+          // Already bound, and still in scope: just refer to it
+          if let Some((name, scope)) = ctx.shared.get(&dup) {
+            let visible = match scope {
+              Some(scope) => ctx.scopes.iter().any(|(lam, _)| lam == scope),
+              None => true, // still being read, so this is a self-reference
+            };
+            if visible {
+              let name = name.clone();
+              if let Some(scope) = scope {
+                if *scope != ROOT {
+                  frees.insert(*scope);
+                }
+              }
+              return Box::new(language::syntax::Term::Var { name });
+            }
+          } else {
+            let name = format!("s{}", ctx.lets);
+            ctx.lets += 1;
+            ctx.shared.insert(dup, (name.clone(), None));
+            let mut expr_frees = HashSet::new();
+            let expr = readback(ctx, stacks, &mut expr_frees, runtime::load_arg(ctx.heap, term, 2), depth);
+            // Variables are cheap to repeat, so there is no point in binding them again
+            if let language::syntax::Term::Var { name } = &*expr {
+              let scope = ctx.scopes.iter().rev().map(|(lam, _)| *lam).find(|lam| *lam == ROOT || expr_frees.contains(lam));
+              ctx.shared.insert(dup, (name.clone(), scope));
+              frees.extend(expr_frees);
+              return expr;
+            }
+            // Binds it inside the innermost lambda it depends on
+            let idx = ctx.scopes.iter().rposition(|(lam, _)| *lam == ROOT || expr_frees.contains(lam)).unwrap_or(0);
+            let scope = ctx.scopes[idx].0;
+            ctx.scopes[idx].1.push((name.clone(), *expr));
+            ctx.shared.insert(dup, (name.clone(), Some(scope)));
+            if scope != ROOT {
+              frees.insert(scope);
+            }
+            return Box::new(language::syntax::Term::Var { name });
+          }
+        }
+        // Can't be shared, so it is expanded
+        let col = runtime::get_ext(term);
+        stacks.push(col, runtime::get_tag(term) == runtime::DP1);
+        let result = readback(ctx, stacks, frees, runtime::load_arg(ctx.heap, term, 2), depth);
+        stacks.pop(col);
+        result
+      }
+      runtime::OP2 => {
+        let oper = get_oper(runtime::get_ext(term));
+        let val0 = readback(ctx, stacks, frees, runtime::load_arg(ctx.heap, term, 0), depth + 1);
+        let val1 = readback(ctx, stacks, frees, runtime::load_arg(ctx.heap, term, 1), depth + 1);
+        Box::new(language::syntax::Term::Op2 { oper, val0, val1 })
+      }
+      runtime::U60 => {
+        Box::new(language::syntax::Term::U6O { numb: runtime::get_num(term) })
+      }
+      runtime::F60 => {
+        Box::new(language::syntax::Term::F6O { numb: runtime::get_num(term) })
+      }
+      runtime::CTR | runtime::FUN => {
+        let func = runtime::get_ext(term);
+        let arit = runtime::arity_of(&ctx.prog.aris, term);
+        let mut args = Vec::new();
+        for i in 0 .. arit {
+          args.push(readback(ctx, stacks, frees, runtime::load_arg(ctx.heap, term, i), depth + 1));
+        }
+        let name = ctx.prog.nams.get(&func).map(String::to_string).unwrap_or_else(|| format!("${}", func));
+        Box::new(language::syntax::Term::Ctr { name, args })
+      }
+      runtime::VAR => {
+        let lam = runtime::get_loc(term, 0);
+        frees.insert(lam);
+        Box::new(language::syntax::Term::Var { name: var_name(ctx, term) })
+      }
+      runtime::ARG => {
+        Box::new(language::syntax::Term::Var { name: "<arg>".to_string() })
+      }
+      runtime::ERA => {
+        Box::new(language::syntax::Term::Var { name: "<era>".to_string() })
+      }
+      _ => {
+        Box::new(language::syntax::Term::Var { name: format!("<unknown_tag_{}>", runtime::get_tag(term)) })
+      }
+    }
+  }
+
+  let ctx = &mut CtxShared {
+    heap,
+    prog,
+    names: HashMap::new(),
+    sup_free: HashMap::new(),
+    shared: HashMap::new(),
+    scopes: vec![(ROOT, Vec::new())],
+    lets: 0,
+    budget,
+  };
+  let mut term = readback(ctx, &mut Stacks::new(), &mut HashSet::new(), runtime::load_ptr(heap, host), 0);
+  let (_, lets) = ctx.scopes.pop().unwrap();
+  for (name, expr) in lets.into_iter().rev() {
+    term = Box::new(language::syntax::Term::Let { name, expr: Box::new(expr), body: term });
+  }
+  term
+}
+
+/// Reads back a term from Runtime's memory, preserving sharing
+pub fn as_shared_code(heap: &Heap, prog: &Program, host: u64) -> String {
+  format!("{}", as_shared_term(heap, prog, host))
+}
+
+/// The strategy used to read a normalized term back from Runtime's memory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadbackMode {
+  /// expands every duplication, see [`as_term`]
+  #[default]
+  Tree,
+  /// keeps every duplication as a `dup` expression, see [`as_linear_term`]
+  Linear,
+  /// binds duplicated expressions with `let`, see [`as_shared_term`]
+  Shared,
+}
+
+impl std::str::FromStr for ReadbackMode {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text {
+      "tree"   => Ok(ReadbackMode::Tree),
+      "linear" => Ok(ReadbackMode::Linear),
+      "shared" => Ok(ReadbackMode::Shared),
+      _        => Err(format!("unknown readback mode '{}', expected 'tree', 'linear' or 'shared'", text)),
+    }
+  }
+}
+
+/// How to read a normalized term back from Runtime's memory
+///
+/// Once one of the limits is reached, the rest of the term is replaced by `...`, so that huge
+/// (or infinite) results can still be inspected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReadbackOptions {
+  /// the strategy used to read the term back
+  pub mode: ReadbackMode,
+  /// the maximum nesting depth of the result, with the root at depth 0
+  pub max_depth: Option<usize>,
+  /// the maximum number of nodes in the result
+  pub max_nodes: Option<usize>,
+}
+
+/// Reads back a term from Runtime's memory, using the given options
+pub fn as_term_with(heap: &Heap, prog: &Program, host: u64, options: &ReadbackOptions) -> Box<language::syntax::Term> {
+  let budget = &mut Budget::new(options);
+  match options.mode {
+    ReadbackMode::Tree   => read_tree(heap, prog, host, budget),
+    ReadbackMode::Linear => read_linear(heap, prog, host, budget),
+    ReadbackMode::Shared => read_shared(heap, prog, host, budget),
+  }
+}
+
+// Picks a side for each superposition that isn't resolved by a dup, so that a superposed term
+// can be read one branch at a time. Superpositions with the same label always take the same side,
+// as they do when a dup with that label resolves them, and sides are picked in the order they are
+// met, so replaying a path always reaches the same superpositions.
+struct Choices {
+  path: Vec<bool>,          // side picked for each label, in the order they were met
+  next: usize,              // how many labels were met on this branch so far
+  sides: HashMap<u64, bool>, // label -> side picked on this branch
+}
+
+impl Choices {
+  fn new() -> Choices {
+    Choices { path: Vec::new(), next: 0, sides: HashMap::new() }
+  }
+  fn pick(&mut self, col: u64) -> bool {
+    if let Some(side) = self.sides.get(&col) {
+      return *side;
+    }
+    if self.next == self.path.len() {
+      self.path.push(false);
+    }
+    let side = self.path[self.next];
+    self.next += 1;
+    self.sides.insert(col, side);
+    side
+  }
+  // Moves to the next branch, depth-first; returns false once every branch was visited
+  fn advance(&mut self) -> bool {
+    self.next = 0;
+    self.sides.clear();
+    while let Some(side) = self.path.pop() {
+      if !side {
+        self.path.push(true);
+        return true;
+      }
+    }
+    false
+  }
+}
+
+/// Enumerates the branches of a superposed term in Runtime's memory, see [`collapse`]
+pub struct Branches<'a> {
+  heap: &'a Heap,
+  prog: &'a Program,
+  tid: usize,
+  host: u64,
+  choices: Option<Choices>,
+}
+
+impl<'a> Branches<'a> {
+  /// Allocates the next branches with the given thread, which the caller must own meanwhile
+  pub fn set_tid(&mut self, tid: usize) {
+    self.tid = tid;
+  }
+}
+
+impl<'a> Iterator for Branches<'a> {
+  type Item = Ptr;
+
+  fn next(&mut self) -> Option<Ptr> {
+    struct CtxCopy<'a> {
+      heap: &'a Heap,
+      prog: &'a Program,
+      tid: usize,
+      choices: &'a mut Choices,
+      lams: HashMap<u64, u64>, // lambda node -> its copy
+      dups: HashMap<u64, u64>, // dup node -> its copy
+      pending: Vec<u64>,       // labels of the dups whose expressions are being copied
+    }
+
+    // Copies the branch of a term selected by `choices`, keeping its dups, so the copy is still linear
+    fn copy(ctx: &mut CtxCopy, term: Ptr) -> Ptr {
+      match runtime::get_tag(term) {
+        runtime::LAM => {
+          let node = runtime::alloc(ctx.heap, ctx.tid, 2);
+          runtime::link(ctx.heap, node, runtime::Era());
+          ctx.lams.insert(runtime::get_loc(term, 0), node);
+          let body = copy(ctx, runtime::load_arg(ctx.heap, term, 1));
+          runtime::link(ctx.heap, node + 1, body);
+          runtime::Lam(node)
+        }
+        runtime::VAR => {
+          // A variable bound outside of the term can't be shared by every copy
+          match ctx.lams.get(&runtime::get_loc(term, 0)) {
+            Some(node) => runtime::Var(*node),
+            None => runtime::fail(runtime::Failure::Error("Can't collapse a term with variables bound outside of it.".to_string())),
+          }
+        }
+        runtime::DP0 | runtime::DP1 => {
+          let dup = runtime::get_loc(term, 0);
+          let col = runtime::get_ext(term);
+          let node = match ctx.dups.get(&dup) {
+            Some(node) => *node,
+            None => {
+              let node = runtime::alloc(ctx.heap, ctx.tid, 3);
+              runtime::link(ctx.heap, node, runtime::Era());
+              runtime::link(ctx.heap, node + 1, runtime::Era());
+              ctx.dups.insert(dup, node);
+              ctx.pending.push(col);
+              let expr = copy(ctx, runtime::load_arg(ctx.heap, term, 2));
+              ctx.pending.pop();
+              runtime::link(ctx.heap, node + 2, expr);
+              node
+            }
+          };
+          if runtime::get_tag(term) == runtime::DP0 { runtime::Dp0(col, node) } else { runtime::Dp1(col, node) }
+        }
+        runtime::SUP => {
+          let col = runtime::get_ext(term);
+          // Resolved by an enclosing dup with the same label, like `Stacks` do on readback
+          if ctx.pending.contains(&col) {
+            runtime::Sup(col, copy_args(ctx, term, 2))
+          } else {
+            let side = ctx.choices.pick(col);
+            copy(ctx, runtime::load_arg(ctx.heap, term, side as u64))
+          }
+        }
+        runtime::APP => runtime::App(copy_args(ctx, term, 2)),
+        runtime::OP2 => runtime::Op2(runtime::get_ext(term), copy_args(ctx, term, 2)),
+        runtime::CTR | runtime::FUN => {
+          let arit = runtime::arity_of(&ctx.prog.aris, term);
+          if arit == 0 {
+            return term;
+          }
+          let node = copy_args(ctx, term, arit);
+          if runtime::get_tag(term) == runtime::CTR {
+            runtime::Ctr(runtime::get_ext(term), node)
+          } else {
+            runtime::Fun(runtime::get_ext(term), node)
+          }
+        }
+        _ => term,
+      }
+    }
+
+    fn copy_args(ctx: &mut CtxCopy, term: Ptr, arit: u64) -> u64 {
+      let node = runtime::alloc(ctx.heap, ctx.tid, arit);
+      for i in 0 .. arit {
+        let arg = copy(ctx, runtime::load_arg(ctx.heap, term, i));
+        runtime::link(ctx.heap, node + i, arg);
+      }
+      node
+    }
+
+    let choices = self.choices.as_mut()?;
+    let ctx = &mut CtxCopy {
+      heap: self.heap,
+      prog: self.prog,
+      tid: self.tid,
+      choices,
+      lams: HashMap::new(),
+      dups: HashMap::new(),
+      pending: Vec::new(),
+    };
+    let branch = copy(ctx, runtime::load_ptr(self.heap, self.host));
+    if !ctx.choices.advance() {
+      self.choices = None;
+    }
+    Some(branch)
+  }
+}
+
+/// Enumerates the branches of a normalized term in Runtime's memory, lazily and depth-first
+///
+/// Each branch is a fresh copy of the term, allocated by the given thread, in which every
+/// superposition is replaced by one of its sides; the caller is responsible for collecting it.
+/// Superpositions with the same label are collapsed together, taking the first side before the
+/// second, so `(Pair {1 2} {3 4})` has four branches, unless both superpositions share a label.
+/// The term must be closed: copying a variable bound outside of it fails the evaluation.
+pub fn collapse<'a>(heap: &'a Heap, prog: &'a Program, tid: usize, host: u64) -> Branches<'a> {
+  Branches { heap, prog, tid, host, choices: Some(Choices::new()) }
+}
+
+fn get_oper(oper: u64) -> language::syntax::Oper {
+  match oper {
+    runtime::ADD => language::syntax::Oper::Add,
+    runtime::SUB => language::syntax::Oper::Sub,
+    runtime::MUL => language::syntax::Oper::Mul,
+    runtime::DIV => language::syntax::Oper::Div,
+    runtime::MOD => language::syntax::Oper::Mod,
+    runtime::AND => language::syntax::Oper::And,
+    runtime::OR  => language::syntax::Oper::Or,
+    runtime::XOR => language::syntax::Oper::Xor,
+    runtime::SHL => language::syntax::Oper::Shl,
+    runtime::SHR => language::syntax::Oper::Shr,
+    runtime::LTN => language::syntax::Oper::Ltn,
+    runtime::LTE => language::syntax::Oper::Lte,
+    runtime::EQL => language::syntax::Oper::Eql,
+    runtime::GTE => language::syntax::Oper::Gte,
+    runtime::GTN => language::syntax::Oper::Gtn,
+    runtime::NEQ => language::syntax::Oper::Neq,
+    _            => panic!("unknown operation"),
+  }
+}
+
 // This reads a term in the `(String.cons ... String.nil)` shape directly into a string.
 pub fn as_string(heap: &Heap, prog: &Program, tids: &[usize], host: u64) -> Option<String> {
+  runtime::reduce(heap, prog, tids, host, true, false);
+  as_normal_string(heap, host)
+}
+
+// Reads a string like `as_string`, without reducing it, as it is already in normal form.
+pub fn as_normal_string(heap: &Heap, host: u64) -> Option<String> {
   let mut host = host;
   let mut text = String::new();
-  runtime::reduce(heap, prog, tids, host, true, false);
   loop {
     let term = runtime::load_ptr(heap, host);
     if runtime::get_tag(term) == runtime::CTR {
@@ -10,6 +10,8 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 // - id_to_name: maps ctr ids to names
 // - name_to_id: maps ctr names to ids
 // - ctr_is_fun: true if a ctr is used as a function
+// - id_to_inferred: the strict arguments which strictness inference found, see `infer_strictness`
+// - lazy: the functions it was disabled on
 // A sanitized rule has all its variables renamed to have unique names.
 // Variables that are never used are renamed to "*".
 #[derive(Clone, Debug)]
@@ -20,6 +22,8 @@ pub struct RuleBook {
   pub id_to_smap: HashMap<u64, Vec<bool>>,
   pub id_to_name: HashMap<u64, String>,
   pub ctr_is_fun: HashMap<String, bool>,
+  pub id_to_inferred: HashMap<u64, Vec<bool>>,
+  pub lazy: HashSet<String>,
 }
 
 pub type RuleGroup = (usize, Vec<language::syntax::Rule>);
@@ -33,6 +37,8 @@ pub fn new_rulebook() -> RuleBook {
     id_to_smap: HashMap::new(),
     id_to_name: HashMap::new(),
     ctr_is_fun: HashMap::new(),
+    id_to_inferred: HashMap::new(),
+    lazy: HashSet::new(),
   };
   for precomp in runtime::PRECOMP {
     book.name_count = book.name_count + 1;
@@ -137,13 +143,25 @@ pub fn gen_rulebook(file: &language::syntax::File) -> RuleBook {
   // Creates an empty rulebook
   let mut book = new_rulebook();
 
-  // Flattens, sanitizes and groups this file's rules
-  let groups = group_rules(&sanitize_rules(&flatten(&file.rules)));
+  // Sanitizes and groups this file's rules
+  let rules = sanitize_rules(&file.rules);
+  let groups = group_rules(&rules);
 
-  // Adds each group
-  for (name, group) in groups.iter() {
+  // Adds each group in the order it first appears on the rules. So, the same code always
+  // gets the same ids, and adding more code after it doesn't change them, which compiled code
+  // relies on.
+  let mut names : Vec<&String> = Vec::new();
+  let mut seen : HashSet<&String> = HashSet::new();
+  for rule in &rules {
+    if let language::syntax::Term::Ctr { ref name, .. } = *rule.lhs {
+      if seen.insert(name) {
+        names.push(name);
+      }
+    }
+  }
+  for name in names {
     if book.name_to_id.get(name).unwrap_or(&u64::MAX) >= &runtime::PRECOMP_COUNT {
-      add_group(&mut book, name, group);
+      add_group(&mut book, name, &groups[name]);
     }
   }
 
@@ -161,9 +179,262 @@ pub fn gen_rulebook(file: &language::syntax::File) -> RuleBook {
     }
   }
 
+  // Makes the arguments every rule reduces anyway strict
+  book.lazy = file.lazy.iter().cloned().collect();
+  infer_strictness(&mut book);
+
   book
 }
 
+// Registers a function implemented by the host, which has no rules and whose arguments are strict
+pub fn add_native(book: &mut RuleBook, name: &str, arity: usize) -> Result<u64, String> {
+  if book.rule_group.contains_key(name) {
+    return Err(format!("native function '{}' is already defined by rules", name));
+  }
+  let id = match book.name_to_id.get(name) {
+    Some(id) if *id < runtime::PRECOMP_COUNT => {
+      return Err(format!("native function '{}' is already a builtin", name));
+    }
+    Some(id) => *id,
+    None => {
+      let id = book.name_count;
+      book.name_to_id.insert(name.to_string(), id);
+      book.id_to_name.insert(id, name.to_string());
+      book.name_count += 1;
+      id
+    }
+  };
+  if book.id_to_smap.get(&id).is_some_and(|smap| smap.len() != arity) {
+    return Err(format!("inconsistent arity on: '{}'", name));
+  }
+  book.id_to_smap.insert(id, vec![true; arity]);
+  book.ctr_is_fun.insert(name.to_string(), true);
+  Ok(id)
+}
+
+// Tree-shaking
+// ============
+
+// The functions and constructors tree-shaking removed from a rulebook, sorted by name
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Shaken {
+  pub functions: Vec<String>,
+  pub constructors: Vec<String>,
+}
+
+// Removes the functions and constructors which the rules of the entries never lead to, so they
+// aren't compiled. Builtins are always kept. The ids of the rest don't change, so code compiled
+// from the shaken rulebook still matches the full one.
+pub fn tree_shake(book: &mut RuleBook, entries: &[String]) -> Result<Shaken, String> {
+  fn collect<'a>(term: &'a language::syntax::Term, found: &mut Vec<&'a String>) {
+    use language::syntax::Term;
+    match term {
+      Term::Var { .. } | Term::U6O { .. } | Term::F6O { .. } => {}
+      Term::Dup { expr, body, .. } | Term::Let { expr, body, .. } => {
+        collect(expr, found);
+        collect(body, found);
+      }
+      Term::Sup { val0, val1 } | Term::Op2 { val0, val1, .. } => {
+        collect(val0, found);
+        collect(val1, found);
+      }
+      Term::App { func, argm } => {
+        collect(func, found);
+        collect(argm, found);
+      }
+      Term::Lam { body, .. } => {
+        collect(body, found);
+      }
+      Term::Ctr { name, args } => {
+        found.push(name);
+        for arg in args {
+          collect(arg, found);
+        }
+      }
+    }
+  }
+
+  let mut reached : HashSet<String> = HashSet::new();
+  let mut next : Vec<String> = Vec::new();
+  for entry in entries {
+    if !book.name_to_id.contains_key(entry) {
+      return Err(format!("Unknown entry point: '{}'.", entry));
+    }
+    next.push(entry.clone());
+  }
+  while let Some(name) = next.pop() {
+    if !reached.insert(name.clone()) {
+      continue;
+    }
+    if let Some((_, rules)) = book.rule_group.get(&name) {
+      let mut found = Vec::new();
+      for rule in rules {
+        collect(&rule.lhs, &mut found);
+        collect(&rule.rhs, &mut found);
+      }
+      next.extend(found.into_iter().filter(|name| !reached.contains(*name)).cloned());
+    }
+  }
+
+  let mut shaken = Shaken::default();
+  let unreached : Vec<(u64, String)> = book.id_to_name.iter()
+    .filter(|(id, name)| **id >= runtime::PRECOMP_COUNT && !reached.contains(*name))
+    .map(|(id, name)| (*id, name.clone()))
+    .collect();
+  for (id, name) in unreached {
+    if book.rule_group.remove(&name).is_some() {
+      shaken.functions.push(name.clone());
+    } else {
+      shaken.constructors.push(name.clone());
+    }
+    book.name_to_id.remove(&name);
+    book.id_to_name.remove(&id);
+    book.id_to_smap.remove(&id);
+    book.id_to_inferred.remove(&id);
+    book.ctr_is_fun.remove(&name);
+  }
+  shaken.functions.sort();
+  shaken.constructors.sort();
+  Ok(shaken)
+}
+
+// Strictness inference
+// ====================
+
+// Finds the variables of a term which are reduced whenever it is: the ones it returns, operates on,
+// applies, or passes to a strict argument of a function. Each comes with why, `why` being the
+// reason for the term itself.
+fn find_demanded(book: &RuleBook, term: &language::syntax::Term, why: &str, found: &mut HashMap<String, String>) {
+  match term {
+    language::syntax::Term::Var { name } => {
+      found.entry(name.clone()).or_insert_with(|| why.to_string());
+    }
+    language::syntax::Term::Op2 { oper, val0, val1 } => {
+      let why = format!("used by `{}`", oper);
+      find_demanded(book, val0, &why, found);
+      find_demanded(book, val1, &why, found);
+    }
+    language::syntax::Term::App { func, .. } => {
+      find_demanded(book, func, "applied", found);
+    }
+    language::syntax::Term::Ctr { name, args } if book.ctr_is_fun.get(name) == Some(&true) => {
+      let smap = &book.id_to_smap[&book.name_to_id[name]];
+      let why = format!("passed to `{}`", name);
+      for (arg, is_strict) in args.iter().zip(smap) {
+        if *is_strict {
+          find_demanded(book, arg, &why, found);
+        }
+      }
+    }
+    // A variable bound by a let or a dup is reduced by reducing its expression
+    language::syntax::Term::Let { name, expr, body } => {
+      let mut inner = HashMap::new();
+      find_demanded(book, body, why, &mut inner);
+      if let Some(why) = inner.remove(name) {
+        find_demanded(book, expr, &why, found);
+      }
+      for (name, why) in inner {
+        found.entry(name).or_insert(why);
+      }
+    }
+    language::syntax::Term::Dup { nam0, nam1, expr, body } => {
+      let mut inner = HashMap::new();
+      find_demanded(book, body, why, &mut inner);
+      let why0 = inner.remove(nam0);
+      let why1 = inner.remove(nam1);
+      if let Some(why) = why0.or(why1) {
+        find_demanded(book, expr, &why, found);
+      }
+      for (name, why) in inner {
+        found.entry(name).or_insert(why);
+      }
+    }
+    // Lambda bodies, superpositions and constructor fields are only reduced when asked for
+    _ => {}
+  }
+}
+
+// Why a rule reduces the given argument, if it does. Only arguments bound to a variable count:
+// pattern-matched ones are strict already.
+fn find_demand(book: &RuleBook, rule: &language::syntax::Rule, arg: usize) -> Option<String> {
+  if let language::syntax::Term::Ctr { args, .. } = &*rule.lhs {
+    if let language::syntax::Term::Var { name } = &*args[arg] {
+      let mut found = HashMap::new();
+      find_demanded(book, &rule.rhs, "returned", &mut found);
+      return found.remove(name);
+    }
+  }
+  None
+}
+
+// Makes the arguments which every rule of a function reduces strict, recording them on
+// `id_to_inferred`. Reducing them before the call doesn't change the result, and saves allocating
+// them as thunks. They are only forced, though: match trees still see them as lazy arguments.
+// Starting from all arguments of all functions, but the lazy and HOAS ones, it drops those some
+// rule doesn't reduce, until none is, so that recursive calls count as reducing their arguments.
+pub fn infer_strictness(book: &mut RuleBook) {
+  let mut candidates = Vec::new();
+  for name in book.rule_group.keys() {
+    if name.starts_with("F$") || book.lazy.contains(name) {
+      continue;
+    }
+    let id = book.name_to_id[name];
+    for (arg, is_strict) in book.id_to_smap[&id].iter().enumerate() {
+      if !is_strict {
+        candidates.push((name.clone(), id, arg));
+      }
+    }
+  }
+  for (_, id, arg) in &candidates {
+    book.id_to_smap.get_mut(id).unwrap()[*arg] = true;
+  }
+  loop {
+    let (kept, dropped) : (Vec<_>, Vec<_>) = candidates.into_iter().partition(|(name, _, arg)| {
+      book.rule_group[name].1.iter().all(|rule| find_demand(book, rule, *arg).is_some())
+    });
+    if dropped.is_empty() {
+      candidates = kept;
+      break;
+    }
+    for (_, id, arg) in dropped {
+      book.id_to_smap.get_mut(&id).unwrap()[arg] = false;
+    }
+    candidates = kept;
+  }
+  for (_, id, arg) in candidates {
+    let arity = book.id_to_smap[&id].len();
+    book.id_to_inferred.entry(id).or_insert_with(|| vec![false; arity])[arg] = true;
+  }
+}
+
+// Explains which arguments of each function are strict: the ones it matches on, the ones annotated
+// with `!`, and the inferred ones, with why.
+pub fn explain_strictness(book: &RuleBook) -> String {
+  let mut text = String::new();
+  for name in itertools::sorted(book.rule_group.keys()) {
+    let rules = &book.rule_group[name].1;
+    let id = book.name_to_id[name];
+    let inferred = book.id_to_inferred.get(&id);
+    let mut args = Vec::new();
+    for (arg, is_strict) in book.id_to_smap[&id].iter().enumerate() {
+      if !is_strict {
+        continue;
+      }
+      if inferred.is_some_and(|inferred| inferred[arg]) {
+        args.push(format!("{} inferred ({})", arg, find_demand(book, &rules[0], arg).unwrap_or_default()));
+      } else if rules.iter().any(|rule| matches!(&*rule.lhs, language::syntax::Term::Ctr { args, .. } if !matches!(*args[arg], language::syntax::Term::Var { .. }))) {
+        args.push(format!("{} matched", arg));
+      } else {
+        args.push(format!("{} annotated", arg));
+      }
+    }
+    let args = if args.is_empty() { String::from("lazy") } else { args.join(", ") };
+    let note = if book.lazy.contains(name) { " (inference disabled)" } else { "" };
+    text.push_str(&format!("{}: {}{}\n", name, args, note));
+  }
+  text
+}
+
 // Groups rules by name. For example:
 //   (add (succ a) (succ b)) = (succ (succ (add a b)))
 //   (add (succ a) (zero)  ) = (succ a)
@@ -222,26 +493,30 @@ pub fn sanitize_rule(rule: &language::syntax::Rule) -> Result<language::syntax::
   ) -> Result<NameTable, String> {
     let mut table = BTreeMap::new();
 
+    // Patterns can have nested constructors, with variables at any depth
+    fn go(arg: &language::syntax::Term, table: &mut NameTable, fresh: &mut dyn FnMut() -> String) -> Result<(), String> {
+      match arg {
+        language::syntax::Term::Var { name, .. } => {
+          table.insert(name.clone(), fresh());
+        }
+        language::syntax::Term::Ctr { args, .. } => {
+          for arg in args {
+            go(arg, table, fresh)?;
+          }
+        }
+        language::syntax::Term::U6O { .. } => {}
+        language::syntax::Term::F6O { .. } => {}
+        _ => {
+          return Err("Invalid left-hand side".to_owned());
+        }
+      }
+      Ok(())
+    }
+
     let lhs = &rule.lhs;
     if let language::syntax::Term::Ctr { name: _, ref args } = **lhs {
       for arg in args {
-        match &**arg {
-          language::syntax::Term::Var { name, .. } => {
-            table.insert(name.clone(), fresh());
-          }
-          language::syntax::Term::Ctr { args, .. } => {
-            for arg in args {
-              if let language::syntax::Term::Var { name } = &**arg {
-                table.insert(name.clone(), fresh());
-              }
-            }
-          }
-          language::syntax::Term::U6O { .. } => {}
-          language::syntax::Term::F6O { .. } => {}
-          _ => {
-            return Err("Invalid left-hand side".to_owned());
-          }
-        }
+        go(arg, &mut table, fresh)?;
       }
     } else {
       return Err("Invalid left-hand side".to_owned());
@@ -275,7 +550,7 @@ pub fn sanitize_rule(rule: &language::syntax::Rule) -> Result<language::syntax::
           Box::new(language::syntax::Term::Var { name })
         } else if runtime::get_global_name_misc(name).is_some() {
           if tbl.get(name).is_some() {
-            panic!("Using a global variable more than once isn't supported yet. Use an explicit 'let' to clone it. {} {:?}", name, tbl.get(name));
+            return Err(format!("Using a global variable more than once isn't supported yet. Use an explicit 'let' to clone it: `{}`.", name));
           } else {
             tbl.insert(name.clone(), String::new());
             Box::new(language::syntax::Term::Var { name: name.clone() })
@@ -299,16 +574,16 @@ pub fn sanitize_rule(rule: &language::syntax::Rule) -> Result<language::syntax::
         let is_global_0 = runtime::get_global_name_misc(nam0).is_some();
         let is_global_1 = runtime::get_global_name_misc(nam1).is_some();
         if is_global_0 && runtime::get_global_name_misc(nam0) != Some(runtime::DP0) {
-          panic!("The name of the global dup var '{}' must start with '$0'.", nam0);
+          return Err(format!("The name of the global dup var '{}' must start with '$0'.", nam0));
         }
         if is_global_1 && runtime::get_global_name_misc(nam1) != Some(runtime::DP1) {
-          panic!("The name of the global dup var '{}' must start with '$1'.", nam1);
+          return Err(format!("The name of the global dup var '{}' must start with '$1'.", nam1));
         }
         if is_global_0 != is_global_1 {
-          panic!("Both variables must be global: '{}' and '{}'.", nam0, nam1);
+          return Err(format!("Both variables must be global: '{}' and '{}'.", nam0, nam1));
         }
         if is_global_0 && &nam0[2..] != &nam1[2..] {
-          panic!("Global dup names must be identical: '{}' and '{}'.", nam0, nam1);
+          return Err(format!("Global dup names must be identical: '{}' and '{}'.", nam0, nam1));
         }
         let new_nam0 = if is_global_0 { nam0.clone() } else { (ctx.fresh)() };
         let new_nam1 = if is_global_1 { nam1.clone() } else { (ctx.fresh)() };
@@ -347,7 +622,7 @@ pub fn sanitize_rule(rule: &language::syntax::Rule) -> Result<language::syntax::
       }
       language::syntax::Term::Let { name, expr, body } => {
         if runtime::get_global_name_misc(name).is_some() {
-          panic!("Global variable '{}' not allowed on let. Use dup instead.", name);
+          return Err(format!("Global variable '{}' not allowed on let. Use dup instead.", name));
         }
         let new_name = (ctx.fresh)();
         let expr = sanitize_term(expr, lhs, tbl, ctx)?;
@@ -544,6 +819,51 @@ pub fn sanitize_rules(rules: &[language::syntax::Rule]) -> Vec<language::syntax:
     .collect()
 }
 
+// Checks that a file can be converted to a rulebook, returning the first problem found otherwise.
+// `gen_rulebook` assumes the file is valid, and gives up on the process if it isn't.
+pub fn check_file(file: &language::syntax::File) -> Result<(), String> {
+  fn check_arity(term: &language::syntax::Term, arities: &mut HashMap<String, usize>) -> Result<(), String> {
+    match term {
+      language::syntax::Term::Var { .. } | language::syntax::Term::U6O { .. } | language::syntax::Term::F6O { .. } => Ok(()),
+      language::syntax::Term::Dup { expr, body, .. } | language::syntax::Term::Let { expr, body, .. } => {
+        check_arity(expr, arities)?;
+        check_arity(body, arities)
+      }
+      language::syntax::Term::Sup { val0, val1 } | language::syntax::Term::Op2 { val0, val1, .. } => {
+        check_arity(val0, arities)?;
+        check_arity(val1, arities)
+      }
+      language::syntax::Term::Lam { body, .. } => check_arity(body, arities),
+      language::syntax::Term::App { func, argm } => {
+        check_arity(func, arities)?;
+        check_arity(argm, arities)
+      }
+      language::syntax::Term::Ctr { name, args } => {
+        if *arities.entry(name.clone()).or_insert(args.len()) != args.len() {
+          return Err(format!("Inconsistent arity on: `{}`.", term));
+        }
+        for arg in args {
+          check_arity(arg, arities)?;
+        }
+        Ok(())
+      }
+    }
+  }
+  let mut arities : HashMap<String, usize> = runtime::PRECOMP.iter().map(|precomp| (precomp.name.to_string(), precomp.smap.len())).collect();
+  for rule in &file.rules {
+    check_arity(&rule.lhs, &mut arities).and_then(|_| check_arity(&rule.rhs, &mut arities)).map_err(|err| format!("{}\nOn rule: `{}`.", err, rule))?;
+  }
+  for rule in &file.rules {
+    sanitize_rule(rule).map_err(|err| format!("{}\nOn rule: `{}`.", err, rule))?;
+  }
+  for (name, smap) in &file.smaps {
+    if arities.get(name).map(|arity| *arity != smap.len()).unwrap_or(true) {
+      return Err(format!("Strictness annotation on unknown function, or of the wrong arity: `{}`.", name));
+    }
+  }
+  Ok(())
+}
+
 #[cfg(test)]
 mod tests {
   use core::panic;
@@ -571,6 +891,10 @@ mod tests {
       (
         "(Double (Succ a)) = (Double (Succ (Succ a)))",
         "(Double (Succ x0)) = let x0.0 = x0; (Double (Succ (Succ x0.0)))"
+      ),
+      (
+        "(Foo (Bar (Zaz x) y)) = (x)",
+        "(Foo (Bar (Zaz x0) *)) = let x0.0 = x0; x0.0"
       )
     ];
 
@@ -595,9 +919,7 @@ mod tests {
   #[test]
   fn test_sanitize_fail_code() {
     // code that has to fail
-    const FAILS: [&str; 2] = [
-      // more than one nesting in constructors
-      "(Foo (Bar (Zaz x))) = (x)",
+    const FAILS: [&str; 1] = [
       // variable not declared in lhs
       "(Succ x) = (j)",
     ];
@@ -707,324 +1029,6 @@ pub fn subst(term: &mut language::syntax::Term, sub_name: &str, value: &language
   }
 }
 
-// Split rules that have nested cases, flattening them.
-// I'm not proud of this code. Must improve considerably.
-pub fn flatten(rules: &[language::syntax::Rule]) -> Vec<language::syntax::Rule> {
-  // Unique name generator
-  let mut name_count = 0;
-  fn fresh(name_count: &mut u64) -> u64 {
-    let name = *name_count;
-    *name_count += 1;
-    name
-  }
-
-  // Checks if this rule has nested patterns, and must be splitted
-  #[rustfmt::skip]
-  fn must_split(lhs: &language::syntax::Term) -> bool {
-/**/if let language::syntax::Term::Ctr { ref args, .. } = *lhs {
-/*  */for arg in args {
-/*  H */if let language::syntax::Term::Ctr { args: ref arg_args, .. } = **arg {
-/*   A  */for field in arg_args {
-/*    D   */if is_matchable(field) {
-/* ─=≡ΣO)   */return true;
-/*    U   */}
-/*   K  */}
-/*  E */}
-/* N*/}
-/**/} false
-  }
-
-  // Returns true if a rule is a global default case (all args are vars)
-  //fn is_default(lhs: &language::syntax::Term) -> bool {
-///**/if let language::syntax::Term::Ctr { ref args, .. } = *lhs {
-///*  */for arg in args {
-///*  H */if let language::syntax::Term::Ctr { args: ref arg_args, .. } = **arg {
-///*   A  */for field in arg_args {
-///*    D   */if !is_variable(field) {
-///* ─=≡ΣO)   */return false;
-///*    U   */}
-///*   K  */}
-///*  E */}
-///* N*/}
-///**/} true
-  //}
-
-  fn is_matchable(term: &language::syntax::Term) -> bool {
-    matches!(term,
-        language::syntax::Term::Ctr { .. }
-      | language::syntax::Term::U6O { .. }
-      | language::syntax::Term::F6O { .. })
-  }
-
-  //fn is_variable(term: &language::syntax::Term) -> bool {
-    //matches!(term, language::syntax::Term::Var { .. })
-  //}
-
-
-  // Checks true if every time that `a` matches, `b` will match too
-  fn matches_together(a: &language::syntax::Rule, b: &language::syntax::Rule) -> (bool, bool) {
-    let mut same_shape = true;
-    if let (
-      language::syntax::Term::Ctr { name: ref _a_name, args: ref a_args },
-      language::syntax::Term::Ctr { name: ref _b_name, args: ref b_args },
-    ) = (&*a.lhs, &*b.lhs) {
-      for (a_arg, b_arg) in a_args.iter().zip(b_args) {
-        match **a_arg {
-          language::syntax::Term::Ctr { name: ref a_arg_name, args: ref a_arg_args } => match **b_arg {
-            language::syntax::Term::Ctr { name: ref b_arg_name, args: ref b_arg_args } => {
-              if a_arg_name != b_arg_name || a_arg_args.len() != b_arg_args.len() {
-                return (false, false);
-              }
-            }
-            language::syntax::Term::U6O { .. } => {
-              return (false, false);
-            }
-            language::syntax::Term::F6O { .. } => {
-              return (false, false);
-            }
-            language::syntax::Term::Var { .. } => {
-              same_shape = false;
-            }
-            _ => {}
-          },
-          language::syntax::Term::U6O { numb: a_arg_numb } => match **b_arg {
-            language::syntax::Term::U6O { numb: b_arg_numb } => {
-              if a_arg_numb != b_arg_numb {
-                return (false, false);
-              }
-            }
-            language::syntax::Term::Ctr { .. } => {
-              return (false, false);
-            }
-            language::syntax::Term::Var { .. } => {
-              same_shape = false;
-            }
-            _ => {}
-          },
-          language::syntax::Term::F6O { numb: a_arg_numb } => match **b_arg {
-            language::syntax::Term::F6O { numb: b_arg_numb } => {
-              if a_arg_numb != b_arg_numb {
-                return (false, false);
-              }
-            }
-            language::syntax::Term::Ctr { .. } => {
-              return (false, false);
-            }
-            language::syntax::Term::Var { .. } => {
-              same_shape = false;
-            }
-            _ => {}
-          },
-          _ => {}
-        }
-      }
-    }
-    (true, same_shape)
-  }
-
-  fn split_group(rules: &[language::syntax::Rule], name_count: &mut u64) -> Vec<language::syntax::Rule> {
-    // println!("\n[split_group]");
-    // for rule in rules {
-    //   println!("{}", rule);
-    // }
-    let mut skip: HashSet<usize> = HashSet::new();
-    let mut new_rules: Vec<language::syntax::Rule> = Vec::new();
-    for i in 0..rules.len() {
-      if !skip.contains(&i) {
-        let rule = &rules[i];
-        if must_split(&rule.lhs) {
-          if let language::syntax::Term::Ctr { ref name, ref args } = *rule.lhs {
-            let mut new_group: Vec<language::syntax::Rule> = Vec::new();
-            let new_lhs_name: String = name.clone();
-            let new_rhs_name: String = format!("{}.{}", name, fresh(name_count));
-            let mut new_lhs_args: Vec<Box<language::syntax::Term>> = Vec::new();
-            let mut new_rhs_args: Vec<Box<language::syntax::Term>> = Vec::new();
-            for arg in args {
-              match &**arg {
-                language::syntax::Term::Ctr { name: ref arg_name, args: ref arg_args } => {
-                  let new_arg_name = arg_name.clone();
-                  let mut new_arg_args = Vec::new();
-                  for field in arg_args {
-                    match &**field {
-                      language::syntax::Term::Ctr { .. } => {
-                        let var_name = format!(".{}", fresh(name_count));
-                        new_arg_args.push(Box::new(language::syntax::Term::Var { name: var_name.clone() }));
-                        new_rhs_args.push(Box::new(language::syntax::Term::Var { name: var_name.clone() }));
-                      }
-                      language::syntax::Term::U6O { .. } => {
-                        let var_name = format!(".{}", fresh(name_count));
-                        new_arg_args.push(Box::new(language::syntax::Term::Var { name: var_name.clone() }));
-                        new_rhs_args.push(Box::new(language::syntax::Term::Var { name: var_name.clone() }));
-                      }
-                      language::syntax::Term::F6O { .. } => {
-                        let var_name = format!(".{}", fresh(name_count));
-                        new_arg_args.push(Box::new(language::syntax::Term::Var { name: var_name.clone() }));
-                        new_rhs_args.push(Box::new(language::syntax::Term::Var { name: var_name.clone() }));
-                      }
-                      language::syntax::Term::Var { .. } => {
-                        new_arg_args.push(field.clone());
-                        new_rhs_args.push(field.clone());
-                      }
-                      _ => {
-                        panic!("?");
-                      }
-                    }
-                  }
-                  new_lhs_args.push(Box::new(language::syntax::Term::Ctr { name: new_arg_name, args: new_arg_args }));
-                }
-                language::syntax::Term::Var { .. } => {
-                  new_lhs_args.push(Box::new(*arg.clone()));
-                  new_rhs_args.push(Box::new(*arg.clone()));
-                }
-                _ => {}
-              }
-            }
-            //(Foo Tic (Bar a b) (Haz c d)) = A
-            //(Foo Tic x         y)         = B
-            //---------------------------------
-            //(Foo Tic (Bar a b) (Haz c d)) = B[x <- (Bar a b), y <- (Haz c d)]
-            //
-            //(Foo.0 a b c d) = ...
-            let new_lhs = Box::new(language::syntax::Term::Ctr { name: new_lhs_name, args: new_lhs_args.clone() });
-            let new_rhs = Box::new(language::syntax::Term::Ctr { name: new_rhs_name.clone(), args: new_rhs_args });
-            let new_rule = language::syntax::Rule { lhs: new_lhs, rhs: new_rhs };
-            new_group.push(new_rule);
-            for (j, other) in rules.iter().enumerate().skip(i) {
-              let (compatible, same_shape) = matches_together(&rule, &other);
-              if compatible {
-                if let (
-                  language::syntax::Term::Ctr { name: ref _rule_name, args: ref rule_args },
-                  language::syntax::Term::Ctr { name: ref _other_name, args: ref other_args }, 
-                ) = (&*rule.lhs, &*other.lhs) {
-                  // (Foo a     (B x P) (C y0 y1)) = F
-                  // (Foo (A k) (B x Q) y        ) = G
-                  // -----------------------------
-                  // (Foo a (B x u) (C y0 y1)) = (Foo.0 a x u y0 y1)
-                  //   (Foo.0 a     x P y0 y1) = F
-                  //   (Foo.0 (A k) x Q f0 f1) = G [y <- (C f0 f1)] // f0 and f1 are fresh
-                  if same_shape {
-                    skip.insert(j); // avoids identical, duplicated clauses
-                  }
-                  let other_new_lhs_name = new_rhs_name.clone();
-                  let mut other_new_lhs_args = Vec::new();
-                  let mut other_new_rhs = other.rhs.clone();
-                  for (rule_arg, other_arg) in rule_args.iter().zip(other_args) {
-                    match &**rule_arg {
-                      language::syntax::Term::Ctr { name: ref rule_arg_name, args: ref rule_arg_args } => {
-                        match &**other_arg {
-                          language::syntax::Term::Ctr { name: ref _other_arg_name, args: ref other_arg_args } => {
-                            for other_field in other_arg_args {
-                              other_new_lhs_args.push(other_field.clone());
-                            }
-                          }
-                          language::syntax::Term::Var { name: ref other_arg_name } => {
-                            let mut new_ctr_args = vec![];
-                            for _ in 0 .. rule_arg_args.len() {
-                              let new_arg = language::syntax::Term::Var { name: format!(".{}", fresh(name_count)) };
-                              new_ctr_args.push(Box::new(new_arg.clone()));
-                              other_new_lhs_args.push(Box::new(new_arg));
-                            }
-                            let new_ctr = language::syntax::Term::Ctr { name: rule_arg_name.clone(), args: new_ctr_args };
-                            subst(&mut other_new_rhs, other_arg_name, &new_ctr);
-                          }
-                          _ => {
-                            panic!("Internal error. Please report."); // not possible since it matches
-                          }
-                        }
-                      }
-                      language::syntax::Term::Var { .. } => {
-                        other_new_lhs_args.push(other_arg.clone());
-                      }
-                      language::syntax::Term::U6O { numb: rule_arg_numb } => {
-                        match &**other_arg {
-                          language::syntax::Term::U6O { numb: other_arg_numb } => {
-                            if rule_arg_numb == other_arg_numb {
-                              other_new_lhs_args.push(Box::new(*other_arg.clone()));
-                            } else {
-                              panic!("Internal error. Please report."); // not possible since it matches
-                            }
-                          }
-                          language::syntax::Term::Var { name: ref other_arg_name } => {
-                            subst(&mut other_new_rhs, other_arg_name, &rule_arg);
-                          }
-                          _ => {
-                            panic!("Internal error. Please report."); // not possible since it matches
-                          }
-                        }
-                      }
-                      language::syntax::Term::F6O { numb: rule_arg_numb } => {
-                        match &**other_arg {
-                          language::syntax::Term::F6O { numb: other_arg_numb } => {
-                            if rule_arg_numb == other_arg_numb {
-                              other_new_lhs_args.push(Box::new(*other_arg.clone()));
-                            } else {
-                              panic!("Internal error. Please report."); // not possible since it matches
-                            }
-                          }
-                          language::syntax::Term::Var { name: ref other_arg_name } => {
-                            subst(&mut other_new_rhs, other_arg_name, &rule_arg);
-                          }
-                          _ => {
-                            panic!("Internal error. Please report."); // not possible since it matches
-                          }
-                        }
-                      }
-                      _ => {
-                        panic!("Internal error. Please report."); // not possible since it matches
-                      }
-                    }
-                  }
-                  let other_new_lhs = Box::new(language::syntax::Term::Ctr {
-                    name: other_new_lhs_name,
-                    args: other_new_lhs_args,
-                  });
-                  let new_rule = language::syntax::Rule { lhs: other_new_lhs, rhs: other_new_rhs };
-                  new_group.push(new_rule);
-                }
-              }
-            }
-            for rule in split_group(&new_group, name_count) {
-              new_rules.push(rule);
-            }
-          } else {
-            panic!("Invalid left-hand side.");
-          }
-        } else {
-          new_rules.push(rules[i].clone());
-        }
-      }
-    }
-    new_rules
-  }
-
-  // Groups rules by function name
-  let mut groups: HashMap<String, Vec<language::syntax::Rule>> = HashMap::new();
-  for rule in rules {
-    if let language::syntax::Term::Ctr { ref name, .. } = *rule.lhs {
-      if let Some(group) = groups.get_mut(name) {
-        group.push(rule.clone());
-      } else {
-        groups.insert(name.clone(), vec![rule.clone()]);
-      }
-    }
-  }
-
-  // For each group, split its internal rules
-  let mut new_rules = Vec::new();
-  for (_name, rules) in &groups {
-    for rule in split_group(rules, &mut name_count) {
-      new_rules.push(rule);
-    }
-  }
-
-  // println!("\nresult:");
-  // for rule in &new_rules {
-  //   println!("{}", rule);
-  // }
-
-  new_rules
-}
-
 // notes
 // -----
 
@@ -1034,4 +1038,4 @@ pub fn flatten(rules: &[language::syntax::Rule]) -> Vec<language::syntax::Rule>
 // constructors with 0-arity, which are used by kind2's hoas functions, unless it is the last
 // (default) clause, which kind2 uses to quote a call back to low-order. this is an internal
 // feature that won't affect programs other than kind2. we can remove this in a future, but that
-// would require kind2 to replicate hvm's flattener algorithm, so we just use it instead.
+// would require kind2 to replicate hvm's pattern matching algorithm, so we just use it instead.
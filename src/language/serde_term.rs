@@ -0,0 +1,617 @@
+// A serde data format whose values are HVM terms. It allows converting any `Serialize` value to a
+// constructor term, and deserializing terms back, following the same conventions as the derived
+// `IntoTerm` and `FromTerm`:
+//
+// - integers, booleans and chars become U60 numbers, and floats become F60 numbers
+// - strings become `String.cons` chains, and sequences, bytes and maps become `List.cons` chains
+// - `None` and `Some(x)` become `Option.None` and `(Option.Some x)`
+// - `()` becomes `Unit`, and tuples become `(Tuple2 a b)`, `(Tuple3 a b c)`, and so on
+// - map entries become `(Tuple2 key value)`
+// - structs become constructors named after the type, such as `(Point x y)`, with positional
+//   fields, and enum variants become constructors named `Type.Variant`, such as `(Shape.Circle r)`
+
+use super::syntax::Term;
+use serde::{de, ser};
+use serde::de::IntoDeserializer;
+
+// Error
+// =====
+
+/// an error converting between a serde value and a term
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error(String);
+
+impl std::fmt::Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+  fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    Error(msg.to_string())
+  }
+}
+
+impl de::Error for Error {
+  fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    Error(msg.to_string())
+  }
+}
+
+fn unexpected(expected: &str, term: &Term) -> Error {
+  Error(format!("Expected {}, found `{}`.", expected, term))
+}
+
+// Entry points
+// ============
+
+/// converts a `Serialize` value to a term.
+pub fn to_term<T: ser::Serialize + ?Sized>(value: &T) -> Result<Term, Error> {
+  value.serialize(Serializer)
+}
+
+/// converts a term back to a `Deserialize` value, as produced by [`to_term`].
+pub fn from_term<T: de::DeserializeOwned>(term: Term) -> Result<T, Error> {
+  T::deserialize(Deserializer(term))
+}
+
+// Serializer
+// ==========
+
+pub struct Serializer;
+
+fn variant_name(name: &str, variant: &str) -> String {
+  format!("{}.{}", name, variant)
+}
+
+impl ser::Serializer for Serializer {
+  type Ok = Term;
+  type Error = Error;
+  type SerializeSeq = Compound;
+  type SerializeTuple = Compound;
+  type SerializeTupleStruct = Compound;
+  type SerializeTupleVariant = Compound;
+  type SerializeMap = Compound;
+  type SerializeStruct = Compound;
+  type SerializeStructVariant = Compound;
+
+  fn serialize_bool(self, v: bool) -> Result<Term, Error> {
+    Ok(Term::integer(v as u64))
+  }
+
+  fn serialize_i8(self, v: i8) -> Result<Term, Error> {
+    self.serialize_i64(v as i64)
+  }
+
+  fn serialize_i16(self, v: i16) -> Result<Term, Error> {
+    self.serialize_i64(v as i64)
+  }
+
+  fn serialize_i32(self, v: i32) -> Result<Term, Error> {
+    self.serialize_i64(v as i64)
+  }
+
+  fn serialize_i64(self, v: i64) -> Result<Term, Error> {
+    match u64::try_from(v) {
+      Ok(v) => self.serialize_u64(v),
+      Err(_) => Err(Error(format!("Negative number can't be represented: {}.", v))),
+    }
+  }
+
+  fn serialize_u8(self, v: u8) -> Result<Term, Error> {
+    self.serialize_u64(v as u64)
+  }
+
+  fn serialize_u16(self, v: u16) -> Result<Term, Error> {
+    self.serialize_u64(v as u64)
+  }
+
+  fn serialize_u32(self, v: u32) -> Result<Term, Error> {
+    self.serialize_u64(v as u64)
+  }
+
+  fn serialize_u64(self, v: u64) -> Result<Term, Error> {
+    if v >> 60 != 0 {
+      return Err(Error(format!("Number doesn't fit in 60 bits: {}.", v)));
+    }
+    Ok(Term::integer(v))
+  }
+
+  fn serialize_f32(self, v: f32) -> Result<Term, Error> {
+    self.serialize_f64(v as f64)
+  }
+
+  fn serialize_f64(self, v: f64) -> Result<Term, Error> {
+    Ok(Term::float(v))
+  }
+
+  fn serialize_char(self, v: char) -> Result<Term, Error> {
+    Ok(Term::from(v))
+  }
+
+  fn serialize_str(self, v: &str) -> Result<Term, Error> {
+    Ok(Term::string(v))
+  }
+
+  fn serialize_bytes(self, v: &[u8]) -> Result<Term, Error> {
+    Ok(Term::list(v.iter().map(|byte| Term::integer(*byte as u64))))
+  }
+
+  fn serialize_none(self) -> Result<Term, Error> {
+    Ok(Term::constructor("Option.None", []))
+  }
+
+  fn serialize_some<T: ser::Serialize + ?Sized>(self, value: &T) -> Result<Term, Error> {
+    Ok(Term::constructor("Option.Some", [value.serialize(self)?]))
+  }
+
+  fn serialize_unit(self) -> Result<Term, Error> {
+    Ok(Term::constructor("Unit", []))
+  }
+
+  fn serialize_unit_struct(self, name: &'static str) -> Result<Term, Error> {
+    Ok(Term::constructor(name, []))
+  }
+
+  fn serialize_unit_variant(self, name: &'static str, _index: u32, variant: &'static str) -> Result<Term, Error> {
+    Ok(Term::constructor(variant_name(name, variant), []))
+  }
+
+  fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(self, name: &'static str, value: &T) -> Result<Term, Error> {
+    Ok(Term::constructor(name, [value.serialize(self)?]))
+  }
+
+  fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(self, name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<Term, Error> {
+    Ok(Term::constructor(variant_name(name, variant), [value.serialize(self)?]))
+  }
+
+  fn serialize_seq(self, len: Option<usize>) -> Result<Compound, Error> {
+    Ok(Compound::new(None, len.unwrap_or(0)))
+  }
+
+  fn serialize_tuple(self, len: usize) -> Result<Compound, Error> {
+    Ok(Compound::new(Some(format!("Tuple{}", len)), len))
+  }
+
+  fn serialize_tuple_struct(self, name: &'static str, len: usize) -> Result<Compound, Error> {
+    Ok(Compound::new(Some(name.to_string()), len))
+  }
+
+  fn serialize_tuple_variant(self, name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Compound, Error> {
+    Ok(Compound::new(Some(variant_name(name, variant)), len))
+  }
+
+  fn serialize_map(self, len: Option<usize>) -> Result<Compound, Error> {
+    Ok(Compound::new(None, len.unwrap_or(0)))
+  }
+
+  fn serialize_struct(self, name: &'static str, len: usize) -> Result<Compound, Error> {
+    Ok(Compound::new(Some(name.to_string()), len))
+  }
+
+  fn serialize_struct_variant(self, name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Compound, Error> {
+    Ok(Compound::new(Some(variant_name(name, variant)), len))
+  }
+}
+
+// Collects the fields of a constructor, or, when it has no name, the elements of a list
+pub struct Compound {
+  name: Option<String>,
+  args: Vec<Term>,
+  key: Option<Term>,
+}
+
+impl Compound {
+  fn new(name: Option<String>, len: usize) -> Self {
+    Compound { name, args: Vec::with_capacity(len), key: None }
+  }
+
+  fn push<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+    self.args.push(value.serialize(Serializer)?);
+    Ok(())
+  }
+
+  fn done(self) -> Result<Term, Error> {
+    match self.name {
+      None => Ok(Term::list(self.args)),
+      Some(name) => Ok(Term::constructor(name, self.args)),
+    }
+  }
+}
+
+impl ser::SerializeSeq for Compound {
+  type Ok = Term;
+  type Error = Error;
+
+  fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<Term, Error> {
+    self.done()
+  }
+}
+
+impl ser::SerializeTuple for Compound {
+  type Ok = Term;
+  type Error = Error;
+
+  fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<Term, Error> {
+    self.done()
+  }
+}
+
+impl ser::SerializeTupleStruct for Compound {
+  type Ok = Term;
+  type Error = Error;
+
+  fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<Term, Error> {
+    self.done()
+  }
+}
+
+impl ser::SerializeTupleVariant for Compound {
+  type Ok = Term;
+  type Error = Error;
+
+  fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<Term, Error> {
+    self.done()
+  }
+}
+
+impl ser::SerializeMap for Compound {
+  type Ok = Term;
+  type Error = Error;
+
+  fn serialize_key<T: ser::Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+    self.key = Some(key.serialize(Serializer)?);
+    Ok(())
+  }
+
+  fn serialize_value<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+    let key = self.key.take().ok_or_else(|| Error("Map value serialized before its key.".to_string()))?;
+    self.args.push(Term::constructor("Tuple2", [key, value.serialize(Serializer)?]));
+    Ok(())
+  }
+
+  fn end(self) -> Result<Term, Error> {
+    self.done()
+  }
+}
+
+impl ser::SerializeStruct for Compound {
+  type Ok = Term;
+  type Error = Error;
+
+  fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), Error> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<Term, Error> {
+    self.done()
+  }
+}
+
+impl ser::SerializeStructVariant for Compound {
+  type Ok = Term;
+  type Error = Error;
+
+  fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, _key: &'static str, value: &T) -> Result<(), Error> {
+    self.push(value)
+  }
+
+  fn end(self) -> Result<Term, Error> {
+    self.done()
+  }
+}
+
+// Deserializer
+// ============
+
+pub struct Deserializer(pub Term);
+
+// Returns the fields of a constructor with the given name and arity
+fn constructor(term: Term, name: &str, arity: usize) -> Result<Vec<Term>, Error> {
+  match term {
+    Term::Ctr { name: ctr_name, args } if ctr_name == name && args.len() == arity => {
+      Ok(args.into_iter().map(|arg| *arg).collect())
+    }
+    term => Err(unexpected(&format!("a `{}` constructor with {} fields", name, arity), &term)),
+  }
+}
+
+// Returns the elements of a list
+fn elements(term: Term) -> Result<Vec<Term>, Error> {
+  let mut values = Vec::new();
+  let mut term = term;
+  loop {
+    match term {
+      Term::Ctr { name, args } if name == "List.cons" && args.len() == 2 => {
+        let [head, tail]: [Box<Term>; 2] = args.try_into().unwrap();
+        values.push(*head);
+        term = *tail;
+      }
+      Term::Ctr { name, args } if name == "List.nil" && args.is_empty() => return Ok(values),
+      term => return Err(unexpected("a list", &term)),
+    }
+  }
+}
+
+impl Deserializer {
+  fn integer(&self) -> Result<u64, Error> {
+    self.0.as_integer().ok_or_else(|| unexpected("a number", &self.0))
+  }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+  type Error = Error;
+
+  fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    if let Some(string) = self.0.as_string() {
+      return visitor.visit_string(string);
+    }
+    match self.0 {
+      Term::U6O { numb } => visitor.visit_u64(numb),
+      term @ Term::F6O { .. } => visitor.visit_f64(term.as_float().unwrap()),
+      Term::Ctr { ref name, .. } if name.starts_with("List.") => self.deserialize_seq(visitor),
+      Term::Ctr { ref name, .. } if name.starts_with("Option.") => self.deserialize_option(visitor),
+      Term::Ctr { args, .. } if args.is_empty() => visitor.visit_unit(),
+      Term::Ctr { args, .. } => visitor.visit_seq(Elements(args.into_iter().map(|arg| *arg).collect::<Vec<_>>().into_iter())),
+      term => Err(unexpected("a constructor or a number", &term)),
+    }
+  }
+
+  fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.integer()? {
+      0 => visitor.visit_bool(false),
+      1 => visitor.visit_bool(true),
+      _ => Err(unexpected("0 or 1", &self.0)),
+    }
+  }
+
+  fn deserialize_i8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_u64(self.integer()?)
+  }
+
+  fn deserialize_i16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_u64(self.integer()?)
+  }
+
+  fn deserialize_i32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_u64(self.integer()?)
+  }
+
+  fn deserialize_i64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_u64(self.integer()?)
+  }
+
+  fn deserialize_u8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_u64(self.integer()?)
+  }
+
+  fn deserialize_u16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_u64(self.integer()?)
+  }
+
+  fn deserialize_u32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_u64(self.integer()?)
+  }
+
+  fn deserialize_u64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_u64(self.integer()?)
+  }
+
+  fn deserialize_f32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_f64(visitor)
+  }
+
+  fn deserialize_f64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.0.as_float() {
+      Some(numb) => visitor.visit_f64(numb),
+      None => Err(unexpected("a floating point number", &self.0)),
+    }
+  }
+
+  fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.0.as_char() {
+      Some(chr) => visitor.visit_char(chr),
+      None => Err(unexpected("a character", &self.0)),
+    }
+  }
+
+  fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_string(visitor)
+  }
+
+  fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.0.as_string() {
+      Some(string) => visitor.visit_string(string),
+      None => Err(unexpected("a string", &self.0)),
+    }
+  }
+
+  fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_byte_buf(visitor)
+  }
+
+  fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    let mut bytes = Vec::new();
+    for elem in elements(self.0)? {
+      match elem.as_integer().and_then(|byte| u8::try_from(byte).ok()) {
+        Some(byte) => bytes.push(byte),
+        None => return Err(unexpected("a byte", &elem)),
+      }
+    }
+    visitor.visit_byte_buf(bytes)
+  }
+
+  fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    match self.0 {
+      Term::Ctr { ref name, ref args } if name == "Option.None" && args.is_empty() => visitor.visit_none(),
+      term => {
+        let [value]: [Term; 1] = constructor(term, "Option.Some", 1)?.try_into().unwrap();
+        visitor.visit_some(Deserializer(value))
+      }
+    }
+  }
+
+  fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    constructor(self.0, "Unit", 0)?;
+    visitor.visit_unit()
+  }
+
+  fn deserialize_unit_struct<V: de::Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    constructor(self.0, name, 0)?;
+    visitor.visit_unit()
+  }
+
+  fn deserialize_newtype_struct<V: de::Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value, Error> {
+    let [value]: [Term; 1] = constructor(self.0, name, 1)?.try_into().unwrap();
+    visitor.visit_newtype_struct(Deserializer(value))
+  }
+
+  fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(Elements(elements(self.0)?.into_iter()))
+  }
+
+  fn deserialize_tuple<V: de::Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(Elements(constructor(self.0, &format!("Tuple{}", len), len)?.into_iter()))
+  }
+
+  fn deserialize_tuple_struct<V: de::Visitor<'de>>(self, name: &'static str, len: usize, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(Elements(constructor(self.0, name, len)?.into_iter()))
+  }
+
+  fn deserialize_map<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_map(Entries { entries: elements(self.0)?.into_iter(), value: None })
+  }
+
+  fn deserialize_struct<V: de::Visitor<'de>>(self, name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(Elements(constructor(self.0, name, fields.len())?.into_iter()))
+  }
+
+  fn deserialize_enum<V: de::Visitor<'de>>(self, name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+    match self.0 {
+      Term::Ctr { name: ctr_name, args } if ctr_name.strip_prefix(name).is_some_and(|rest| rest.starts_with('.')) => {
+        let variant = ctr_name[name.len() + 1 ..].to_string();
+        visitor.visit_enum(Variant { variant, args: args.into_iter().map(|arg| *arg).collect() })
+      }
+      term => Err(unexpected(&format!("a `{}` variant", name), &term)),
+    }
+  }
+
+  fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    self.deserialize_string(visitor)
+  }
+
+  fn deserialize_ignored_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_unit()
+  }
+}
+
+// The elements of a list, or the fields of a constructor
+struct Elements(std::vec::IntoIter<Term>);
+
+impl<'de> de::SeqAccess<'de> for Elements {
+  type Error = Error;
+
+  fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+    self.0.next().map(|elem| seed.deserialize(Deserializer(elem))).transpose()
+  }
+
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.0.len())
+  }
+}
+
+// The `(Tuple2 key value)` entries of a map
+struct Entries {
+  entries: std::vec::IntoIter<Term>,
+  value: Option<Term>,
+}
+
+impl<'de> de::MapAccess<'de> for Entries {
+  type Error = Error;
+
+  fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+    match self.entries.next() {
+      Some(entry) => {
+        let [key, value]: [Term; 2] = constructor(entry, "Tuple2", 2)?.try_into().unwrap();
+        self.value = Some(value);
+        seed.deserialize(Deserializer(key)).map(Some)
+      }
+      None => Ok(None),
+    }
+  }
+
+  fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+    let value = self.value.take().ok_or_else(|| Error("Map value deserialized before its key.".to_string()))?;
+    seed.deserialize(Deserializer(value))
+  }
+
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.entries.len())
+  }
+}
+
+// An enum variant, with the fields of its constructor
+struct Variant {
+  variant: String,
+  args: Vec<Term>,
+}
+
+impl<'de> de::EnumAccess<'de> for Variant {
+  type Error = Error;
+  type Variant = Self;
+
+  fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+    let variant: de::value::StringDeserializer<Error> = self.variant.clone().into_deserializer();
+    Ok((seed.deserialize(variant)?, self))
+  }
+}
+
+impl Variant {
+  fn fields(self, arity: usize) -> Result<Vec<Term>, Error> {
+    if self.args.len() != arity {
+      return Err(Error(format!("Expected {} fields on variant `{}`, found {}.", arity, self.variant, self.args.len())));
+    }
+    Ok(self.args)
+  }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant {
+  type Error = Error;
+
+  fn unit_variant(self) -> Result<(), Error> {
+    self.fields(0)?;
+    Ok(())
+  }
+
+  fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+    let [value]: [Term; 1] = self.fields(1)?.try_into().unwrap();
+    seed.deserialize(Deserializer(value))
+  }
+
+  fn tuple_variant<V: de::Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(Elements(self.fields(len)?.into_iter()))
+  }
+
+  fn struct_variant<V: de::Visitor<'de>>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+    visitor.visit_seq(Elements(self.fields(fields.len())?.into_iter()))
+  }
+}
@@ -10,6 +10,7 @@ use std::convert::{TryFrom, TryInto};
 // ----
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Term {
   Var { name: String }, // TODO: add `global: bool`
   Dup { nam0: String, nam1: String, expr: Box<Term>, body: Box<Term> },
@@ -24,6 +25,7 @@ pub enum Term {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Oper {
   Add, Sub, Mul, Div,
   Mod, And, Or,  Xor,
@@ -35,6 +37,7 @@ pub enum Oper {
 // ----
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rule {
   pub lhs: Box<Term>,
   pub rhs: Box<Term>,
@@ -48,9 +51,11 @@ type SMap = (String, Vec<bool>);
 // File
 // ----
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct File {
   pub rules: Vec<Rule>,
   pub smaps: Vec<SMap>,
+  pub lazy: Vec<String>,
 }
 
 // Stringifier
@@ -418,6 +423,120 @@ impl TryFrom<Term> for String {
     }
 }
 
+// Unused by the binary, which shares this module
+#[cfg(feature = "derive")]
+#[allow(unused_imports)]
+pub use hvm_derive::{FromTerm, IntoTerm};
+
+/// a type which can be converted to a term,
+/// usually implemented with `#[derive(IntoTerm)]`, from the `derive` feature.
+///
+/// structs become constructors named after the type, such as `(Point x y)`,
+/// and enum variants become constructors named `Type.Variant`, such as `(Shape.Circle r)`.
+pub trait IntoTerm {
+    fn into_term(self) -> Term;
+}
+
+/// a type which can be converted from a term,
+/// usually implemented with `#[derive(FromTerm)]`, from the `derive` feature.
+///
+/// this is the inverse of [`IntoTerm`];
+/// on failure, it returns the part of the term which couldn't be converted.
+pub trait FromTerm: Sized {
+    fn from_term(term: Term) -> Result<Self, Term>;
+}
+
+impl IntoTerm for Term {
+    fn into_term(self) -> Term {
+        self
+    }
+}
+
+impl FromTerm for Term {
+    fn from_term(term: Term) -> Result<Self, Term> {
+        Ok(term)
+    }
+}
+
+macro_rules! impl_term_conversions {
+    ($($type:ty),*) => {
+        $(
+            impl IntoTerm for $type {
+                fn into_term(self) -> Term {
+                    self.into()
+                }
+            }
+
+            impl FromTerm for $type {
+                fn from_term(term: Term) -> Result<Self, Term> {
+                    term.try_into()
+                }
+            }
+        )*
+    };
+}
+
+impl_term_conversions!(u64, f64, char, String);
+
+impl<T: IntoTerm> IntoTerm for Box<T> {
+    fn into_term(self) -> Term {
+        (*self).into_term()
+    }
+}
+
+impl<T: FromTerm> FromTerm for Box<T> {
+    fn from_term(term: Term) -> Result<Self, Term> {
+        T::from_term(term).map(Box::new)
+    }
+}
+
+/// vectors are converted to lists, see [`Term::list`]
+impl<T: IntoTerm> IntoTerm for Vec<T> {
+    fn into_term(self) -> Term {
+        Term::list(self.into_iter().map(T::into_term))
+    }
+}
+
+impl<T: FromTerm> FromTerm for Vec<T> {
+    fn from_term(term: Term) -> Result<Self, Term> {
+        let mut values = Vec::new();
+        let mut term = term;
+        loop {
+            match term {
+                Term::Ctr { name, args } if name == "List.cons" && args.len() == 2 => {
+                    let [head, tail]: [Box<Term>; 2] = args.try_into().unwrap();
+                    values.push(T::from_term(*head)?);
+                    term = *tail;
+                }
+                Term::Ctr { name, args } if name == "List.nil" && args.is_empty() => return Ok(values),
+                term => return Err(term),
+            }
+        }
+    }
+}
+
+/// options are converted as an enum would be, to `(Option.Some value)` or `(Option.None)`
+impl<T: IntoTerm> IntoTerm for Option<T> {
+    fn into_term(self) -> Term {
+        match self {
+            Some(value) => Term::constructor("Option.Some", [value.into_term()]),
+            None => Term::constructor("Option.None", []),
+        }
+    }
+}
+
+impl<T: FromTerm> FromTerm for Option<T> {
+    fn from_term(term: Term) -> Result<Self, Term> {
+        match term {
+            Term::Ctr { name, mut args } if name == "Option.Some" && args.len() == 1 => {
+                T::from_term(*args.pop().unwrap()).map(Some)
+            }
+            Term::Ctr { name, args } if name == "Option.None" && args.is_empty() => Ok(None),
+            term => Err(term),
+        }
+    }
+}
+
 // Rule
 // ----
 
@@ -869,25 +988,33 @@ pub fn parse_smap(state: HOPA::State) -> HOPA::Answer<Option<SMap>> {
 pub fn parse_file(state: HOPA::State) -> HOPA::Answer<File> {
   let mut rules = Vec::new();
   let mut smaps = Vec::new();
+  let mut lazy = Vec::new();
   let mut state = state;
   loop {
     let (new_state, done) = HOPA::there_end(state)?;
     if done {
       break;
     }
+    // A `~` before a rule disables strictness inference on its function
+    let (new_state, is_lazy) = HOPA::there_take_exact("~", new_state)?;
     let (_, smap) = parse_smap(new_state)?;
     if let Some(smap) = smap {
       smaps.push(smap);
     }
     let (new_state, rule) = parse_rule(new_state)?;
     if let Some(rule) = rule {
+      if let (true, Term::Ctr { name, .. }) = (is_lazy, &*rule.lhs) {
+        if !lazy.contains(name) {
+          lazy.push(name.clone());
+        }
+      }
       rules.push(rule);
       state = new_state;
       continue;
     }
     return HOPA::expected("declaration", 1, state);
   }
-  Ok((state, File { rules, smaps }))
+  Ok((state, File { rules, smaps, lazy }))
 }
 
 pub fn read_term(code: &str) -> Result<Box<Term>, String> {
@@ -27,6 +27,9 @@
 
 pub mod language;
 pub mod runtime;
+pub mod compiler;
+pub mod api;
+pub mod ffi;
 
 pub use language::{*};
 pub use runtime::{*};
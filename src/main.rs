@@ -45,6 +45,26 @@ enum Command {
     #[clap(short = 'd', long, default_value = "false", default_missing_value = "true", parse(try_from_str=parse_bool))]
     debug: bool,
 
+    /// Runs the expression as an IO action, performing its effects in order.
+    #[clap(long, default_value = "false", default_missing_value = "true", parse(try_from_str=parse_bool))]
+    io: bool,
+
+    /// How to read the result back: "tree", "linear" or "shared".
+    #[clap(long, default_value = "tree", parse(try_from_str=parse_readback))]
+    readback: language::readback::ReadbackMode,
+
+    /// Limits the number of nodes shown, replacing the rest of the result by "...".
+    #[clap(long)]
+    max_output: Option<usize>,
+
+    /// The optimizations to run on the rules: "0" (none), "1" or "2" (which also inlines functions).
+    #[clap(short = 'O', long = "opt-level", default_value = "0", parse(try_from_str=parse_opt_level))]
+    opt: runtime::Optimizations,
+
+    /// Shows which arguments of each function are strict, and why, including the inferred ones.
+    #[clap(long)]
+    explain_strictness: bool,
+
     /// A "file.hvm" to load.
     #[clap(short = 'f', long, default_value = "")]
     file: String,
@@ -52,47 +72,131 @@ enum Command {
     /// The expression to run.
     #[clap(default_value = "Main")]
     expr: String,
+
+    /// Arguments given to `Main`, as a list of strings, when it takes one.
+    #[clap(last = true)]
+    args: Vec<String>,
   },
 
-  /// Compile a file to Rust
+  /// Compile a file to Rust, or to C
   #[clap(aliases = &["c"])]
   Compile {
     /// A "file.hvm" to load.
-    file: String
+    file: String,
+
+    /// The directory to write the crate to. Defaults to "./<name>".
+    #[clap(short = 'o', long)]
+    out: Option<String>,
+
+    /// The name of the crate, and of its binary. Defaults to the file's name.
+    #[clap(short = 'n', long)]
+    name: Option<String>,
+
+    /// Overwrites the directory, if it isn't empty.
+    #[clap(long)]
+    force: bool,
+
+    /// Builds the crate with `cargo build --release`, or the C file with `$CC`.
+    #[clap(short = 'b', long)]
+    build: bool,
+
+    /// The language to compile to: "rust" or "c".
+    #[clap(long, default_value = "rust", parse(try_from_str=parse_target))]
+    target: compiler::Target,
+
+    /// The optimizations to run on the rules: "0" (none), "1" or "2" (which also inlines functions).
+    #[clap(short = 'O', long = "opt-level", default_value = "0", parse(try_from_str=parse_opt_level))]
+    opt: runtime::Optimizations,
+
+    /// Only compiles the functions and constructors `Main` leads to, showing what was removed.
+    #[clap(long)]
+    shake: bool,
+
+    /// A function to tree-shake from, instead of `Main`. Can be given more than once. Implies `--shake`.
+    #[clap(long = "entry")]
+    entries: Vec<String>,
+
+    /// Shows which arguments of each function are strict, and why, including the inferred ones.
+    #[clap(long)]
+    explain_strictness: bool,
+
+    /// Makes the crate depend on the `hvm` source at this directory, instead of on crates.io.
+    #[clap(long)]
+    hvm_path: Option<String>,
   },
 }
 
 fn main() {
-  if let Err(err) = run_cli() {
-    eprintln!("{}", err);
-    std::process::exit(1);
-  };
+  match runtime::catch_failure(run_cli) {
+    Ok(Ok(())) => {}
+    Ok(Err(err)) => api::exit_with(runtime::Failure::Error(err)),
+    Err(failure) => api::exit_with(failure),
+  }
 }
 
 fn run_cli() -> Result<(), String> {
   let cli = Cli::parse();
 
   match cli.command {
-    Command::Run { size, tids, cost: show_cost, debug, file, expr } => {
+    Command::Run { size, tids, cost: show_cost, debug, io, readback, max_output, opt, explain_strictness, file, expr, args } => {
       let tids = if debug { 1 } else { tids };
-      let (norm, cost, time) = api::eval(&load_code(&file)?, &expr, Vec::new(), size, tids, debug)?;
-      println!("{}", norm);
+      let options = language::readback::ReadbackOptions { mode: readback, max_depth: None, max_nodes: max_output };
+      let code = load_code(&file)?;
+      if explain_strictness {
+        print!("{}", api::explain_strictness(&code)?);
+      }
+      let (norm, cost, time) = api::eval(&code, &expr, &args, &[], size, tids, debug, io, &opt, options)?;
+      // IO programs show their output themselves
+      if !io {
+        println!("{}", norm);
+      }
       if show_cost {
-        eprintln!();
-        eprintln!("\x1b[32m[TIME: {:.2}s | COST: {} | RPS: {:.2}m]\x1b[0m", ((time as f64)/1000.0), cost - 1, (cost as f64) / (time as f64) / 1000.0);
+        api::print_cost(cost, time);
       }
       Ok(())
     }
-    Command::Compile { file } => {
+    Command::Compile { file, out, name, force, build, target, opt, shake, mut entries, explain_strictness, hvm_path } => {
       let code = load_code(&file)?;
-      let name = file.replace(".hvm", "");
-      compiler::compile(&code, &name).map_err(|x| x.to_string())?;
-      println!("Compiled definitions to '/{}'.", name);
+      if explain_strictness {
+        print!("{}", api::explain_strictness(&code)?);
+      }
+      let name = name.unwrap_or_else(|| {
+        std::path::Path::new(&file).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default()
+      });
+      let dir = std::path::PathBuf::from(out.unwrap_or_else(|| name.clone()));
+      if shake && entries.is_empty() {
+        entries.push(String::from("Main"));
+      }
+      let entries = if entries.is_empty() { None } else { Some(&entries[..]) };
+      let hvm_path = hvm_path.as_ref().map(std::path::Path::new);
+      let shaken = compiler::compile(&code, &name, &dir, force, target, &opt, entries, hvm_path)?;
+      if let (Some(shaken), Some(entries)) = (shaken, entries) {
+        print_shaken(&shaken, entries);
+      }
+      println!("Compiled definitions to '{}'.", dir.display());
+      if build {
+        let binary = compiler::build(&dir, &name, target)?;
+        println!("Built '{}'.", binary.display());
+      }
       Ok(())
     }
   }
 }
 
+// Reports what tree-shaking removed
+fn print_shaken(shaken: &language::rulebook::Shaken, entries: &[String]) {
+  let entries = entries.join(", ");
+  if shaken.functions.is_empty() && shaken.constructors.is_empty() {
+    println!("Everything is reachable from {}.", entries);
+  }
+  for (kind, names) in [("function", &shaken.functions), ("constructor", &shaken.constructors)] {
+    if !names.is_empty() {
+      let plural = if names.len() == 1 { "" } else { "s" };
+      println!("Removed {} {}{} unreachable from {}: {}.", names.len(), kind, plural, entries, names.join(", "));
+    }
+  }
+}
+
 fn parse_size(text: &str) -> Result<usize, String> {
   if text == "auto" {
     return Ok(runtime::default_heap_size());
@@ -113,6 +217,18 @@ fn parse_bool(text: &str) -> Result<bool, String> {
   return text.parse::<bool>().map_err(|x| format!("{}", x));
 }
 
+fn parse_readback(text: &str) -> Result<language::readback::ReadbackMode, String> {
+  text.parse::<language::readback::ReadbackMode>()
+}
+
+fn parse_target(text: &str) -> Result<compiler::Target, String> {
+  text.parse::<compiler::Target>()
+}
+
+fn parse_opt_level(text: &str) -> Result<runtime::Optimizations, String> {
+  text.parse::<runtime::Optimizations>()
+}
+
 fn load_code(file: &str) -> Result<String, String> {
   if file.is_empty() {
     return Ok(String::new());
@@ -1,142 +1,84 @@
-//#[cfg(not(target_arch = "wasm32"))]
-//pub fn run_io(heap: &Heap, prog: &Program, tids: &[usize], host: u64) {
-  //fn read_input() -> String {
-    //let mut input = String::new();
-    //stdin().read_line(&mut input).expect("string");
-    //if let Some('\n') = input.chars().next_back() { input.pop(); }
-    //if let Some('\r') = input.chars().next_back() { input.pop(); }
-    //return input;
-  //}
-  //use std::io::{stdin,stdout,Write};
-  //loop {
-    //let term = reduce(heap, prog, tids, host); // FIXME: add parallelism
-    //match get_tag(term) {
-      //CTR => {
-        //let fid = get_ext(term);
-        //// IO.done a : (IO a)
-        //if fid == IO_DONE {
-          //let done = load_arg(heap, term, 0);
-          //free(heap, 0, get_loc(term, 0), 1);
-          //link(heap, host, done);
-          //println!("");
-          //println!("");
-          //break;
-        //}
-        //// IO.do_input (String -> IO a) : (IO a)
-        //if fid == IO_DO_INPUT {
-          //let cont = load_arg(heap, term, 0);
-          //let text = make_string(heap, tids[0], &read_input());
-          //let app0 = alloc(heap, tids[0], 2);
-          //link(heap, app0 + 0, cont);
-          //link(heap, app0 + 1, text);
-          //free(heap, 0, get_loc(term, 0), 1);
-          //let done = App(app0);
-          //link(heap, host, done);
-        //}
-        //// IO.do_output String (Wrd -> IO a) : (IO a)
-        //if fid == IO_DO_OUTPUT {
-          //if let Some(show) = readback_string(heap, prog, tids, get_loc(term, 0)) {
-            //print!("{}", show);
-            //stdout().flush().ok();
-            //let cont = load_arg(heap, term, 1);
-            //let app0 = alloc(heap, tids[0], 2);
-            //link(heap, app0 + 0, cont);
-            //link(heap, app0 + 1, Wrd(0));
-            //free(heap, 0, get_loc(term, 0), 2);
-            //let text = load_arg(heap, term, 0);
-            //collect(heap, prog, 0, text);
-            //let done = App(app0);
-            //link(heap, host, done);
-          //} else {
-            //println!("Runtime type error: attempted to print a non-string.");
-            //println!("{}", crate::language::readback::as_code(heap, prog, get_loc(term, 0)));
-            //std::process::exit(0);
-          //}
-        //}
-        //// IO.do_fetch String (String -> IO a) : (IO a)
-        //if fid == IO_DO_FETCH {
-          //if let Some(url) = readback_string(heap, prog, tids, get_loc(term, 0)) {
-            //let body = reqwest::blocking::get(url).unwrap().text().unwrap(); // FIXME: treat
-            //let cont = load_arg(heap, term, 2);
-            //let app0 = alloc(heap, tids[0], 2);
-            //let text = make_string(heap, tids[0], &body);
-            //link(heap, app0 + 0, cont);
-            //link(heap, app0 + 1, text);
-            //free(heap, 0, get_loc(term, 0), 3);
-            //let opts = load_arg(heap, term, 1); // FIXME: use options
-            //collect(heap, prog, 0, opts);
-            //let done = App(app0);
-            //link(heap, host, done);
-          //} else {
-            //println!("Runtime type error: attempted to print a non-string.");
-            //println!("{}", crate::language::readback::as_code(heap, prog, get_loc(term, 0)));
-            //std::process::exit(0);
-          //}
-        //}
-        //// IO.do_store String String (Wrd -> IO a) : (IO a)
-        //if fid == IO_DO_STORE {
-          //if let Some(key) = readback_string(heap, prog, tids, get_loc(term, 0)) {
-            //if let Some(val) = readback_string(heap, prog, tids, get_loc(term, 1)) {
-              //std::fs::write(key, val).ok(); // TODO: Handle errors
-              //let cont = load_arg(heap, term, 2);
-              //let app0 = alloc(heap, tids[0], 2);
-              //link(heap, app0 + 0, cont);
-              //link(heap, app0 + 1, Wrd(0));
-              //free(heap, 0, get_loc(term, 0), 2);
-              //let key = load_arg(heap, term, 0);
-              //collect(heap, prog, 0, key);
-              //free(heap, 0, get_loc(term, 1), 2);
-              //let val = load_arg(heap, term, 1);
-              //collect(heap, prog, 0, val);
-              //let done = App(app0);
-              //link(heap, host, done);
-            //} else {
-              //println!("Runtime type error: attempted to store a non-string.");
-              //println!("{}", crate::language::readback::as_code(heap, prog, get_loc(term, 1)));
-              //std::process::exit(0);
-            //}
-          //} else {
-            //println!("Runtime type error: attempted to store to a non-string key.");
-            //println!("{}", crate::language::readback::as_code(heap, prog, get_loc(term, 0)));
-            //std::process::exit(0);
-          //}
-        //}
-        //// IO.do_load String (String -> IO a) : (IO a)
-        //if fid == IO_DO_LOAD {
-          //if let Some(key) = readback_string(heap, prog, tids, get_loc(term, 0)) {
-            //let file = std::fs::read(key).unwrap(); // TODO: Handle errors
-            //let file = std::str::from_utf8(&file).unwrap();
-            //let cont = load_arg(heap, term, 1); 
-            //let text = make_string(heap, tids[0], file);
-            //let app0 = alloc(heap, tids[0], 2);
-            //link(heap, app0 + 0, cont);
-            //link(heap, app0 + 1, text);
-            //free(heap, 0, get_loc(term, 0), 2);
-            //let done = App(app0);
-            //link(heap, host, done);
-          //} else {
-            //println!("Runtime type error: attempted to read from a non-string key.");
-            //println!("{}", crate::language::readback::as_code(heap, prog, get_loc(term, 0)));
-            //std::process::exit(0);
-          //}
-        //}
-        //break;
-      //}
-      //_ => {
-        //break;
-      //}
-    //}
-  //}
-//}
+// IO runner
+// ---------
+//
+// Runs a program whose result is an IO action, one action at a time:
+//
+//   IO.done      (val: a)                                  : (IO a)
+//   IO.do_input  (cont: String -> IO a)                    : (IO a)
+//   IO.do_output (text: String) (cont: U60 -> IO a)        : (IO a)
+//   IO.do_store  (key: String) (val: String) (cont: U60 -> IO a) : (IO a)
+//   IO.do_load   (key: String) (cont: String -> IO a)      : (IO a)
+//
+// Each step reduces the term to weak head normal form, on all the given threads, performs the
+// action it found, and replaces the term by the continuation applied to the action's result. So,
+// unlike the `HVM.print`-like builtins, which run whenever reduction happens to reach them, the
+// effects take place in the order the program sequences them.
 
+use crate::runtime::{*};
+use std::io::{BufRead, Write};
 
-//pub fn make_string(heap: &Heap, tid: usize, text: &str) -> Ptr {
-  //let mut term = Ctr(STRING_NIL, 0);
-  //for chr in text.chars().rev() { // TODO: reverse
-    //let ctr0 = alloc(heap, tid, 2);
-    //link(heap, ctr0 + 0, Wrd(chr as u64));
-    //link(heap, ctr0 + 1, term);
-    //term = Ctr(STRING_CONS, ctr0);
-  //}
-  //return term;
-//}
+// Runs the IO action at `host`, leaving the normalized value of its `IO.done` there
+pub fn run_io(heap: &Heap, prog: &Program, tids: &[usize], host: u64, input: &mut dyn BufRead, output: &mut dyn Write, debug: bool) -> Result<Ptr, String> {
+  let tid = tids[0];
+  loop {
+    let term = reduce(heap, prog, tids, host, false, debug);
+    let fid = if get_tag(term) == CTR { get_ext(term) } else { u64::MAX };
+    match fid {
+      IO_DONE => {
+        link(heap, host, load_arg(heap, term, 0));
+        free(heap, tid, get_loc(term, 0), 1);
+        return Ok(normalize(heap, prog, tids, host, debug));
+      }
+      IO_DO_INPUT => {
+        let mut line = String::new();
+        input.read_line(&mut line).map_err(|err| err.to_string())?;
+        if line.ends_with('\n') { line.pop(); }
+        if line.ends_with('\r') { line.pop(); }
+        let text = make_string(heap, tid, &line);
+        apply_cont(heap, tid, host, load_arg(heap, term, 0), text);
+        free(heap, tid, get_loc(term, 0), 1);
+      }
+      IO_DO_OUTPUT => {
+        let text = read_string(heap, prog, tids, term, 0)?;
+        write!(output, "{}", text).and_then(|_| output.flush()).map_err(|err| err.to_string())?;
+        collect(heap, &prog.aris, tid, load_arg(heap, term, 0));
+        apply_cont(heap, tid, host, load_arg(heap, term, 1), U6O(0));
+        free(heap, tid, get_loc(term, 0), 2);
+      }
+      IO_DO_STORE => {
+        let key = read_string(heap, prog, tids, term, 0)?;
+        let val = read_string(heap, prog, tids, term, 1)?;
+        std::fs::write(&key, val).map_err(|err| format!("Couldn't store '{}': {}", key, err))?;
+        collect(heap, &prog.aris, tid, load_arg(heap, term, 0));
+        collect(heap, &prog.aris, tid, load_arg(heap, term, 1));
+        apply_cont(heap, tid, host, load_arg(heap, term, 2), U6O(0));
+        free(heap, tid, get_loc(term, 0), 3);
+      }
+      IO_DO_LOAD => {
+        let key = read_string(heap, prog, tids, term, 0)?;
+        let file = std::fs::read_to_string(&key).map_err(|err| format!("Couldn't load '{}': {}", key, err))?;
+        collect(heap, &prog.aris, tid, load_arg(heap, term, 0));
+        let text = make_string(heap, tid, &file);
+        apply_cont(heap, tid, host, load_arg(heap, term, 1), text);
+        free(heap, tid, get_loc(term, 0), 2);
+      }
+      _ => {
+        return Err(format!("Runtime type error: expected an IO action, found: {}", show_at(heap, prog, host, &[])));
+      }
+    }
+  }
+}
+
+// Replaces the action at `host` by `(cont value)`
+fn apply_cont(heap: &Heap, tid: usize, host: u64, cont: Ptr, value: Ptr) {
+  let app0 = alloc(heap, tid, 2);
+  link(heap, app0 + 0, cont);
+  link(heap, app0 + 1, value);
+  link(heap, host, App(app0));
+}
+
+fn read_string(heap: &Heap, prog: &Program, tids: &[usize], term: Ptr, n: u64) -> Result<String, String> {
+  crate::language::readback::as_string(heap, prog, tids, get_loc(term, n)).ok_or_else(|| {
+    format!("Runtime type error: expected a string, found: {}", crate::language::readback::as_code(heap, prog, get_loc(term, n)))
+  })
+}
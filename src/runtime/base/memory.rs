@@ -191,6 +191,9 @@ pub struct LocalVars {
   pub amax: AtomicU64, // max alloc index
   pub dups: AtomicU64, // next dup label to be created
   pub cost: AtomicU64, // total number of rewrite rules
+  pub limit: AtomicU64, // cost past which reduction halts
+  #[cfg(feature = "observer")]
+  pub redex: AtomicU64, // redex being rewritten, for the observer
 }
 
 // Global memory buffer
@@ -203,6 +206,8 @@ pub struct Heap {
   pub aloc: Box<[Box<[AtomicU64]>]>,
   pub vbuf: Box<[Box<[AtomicU64]>]>,
   pub rbag: RedexBag,
+  #[cfg(feature = "observer")]
+  pub observer: Option<std::sync::Arc<dyn super::observer::ReductionObserver>>,
 }
 
 // Pointer Constructors
@@ -247,54 +252,70 @@ pub const NEQ: u64 = 0xF;
 // Pointer Constructors
 // --------------------
 
+// These and the other helpers compiled functions call are `#[inline]`, as they live in the crates
+// `hvm compile` writes, and couldn't inline them across crates otherwise
+
+#[inline]
 pub fn Var(pos: u64) -> Ptr {
   (VAR * TAG) | pos
 }
 
+#[inline]
 pub fn Dp0(col: u64, pos: u64) -> Ptr {
   (DP0 * TAG) | (col * EXT) | pos
 }
 
+#[inline]
 pub fn Dp1(col: u64, pos: u64) -> Ptr {
   (DP1 * TAG) | (col * EXT) | pos
 }
 
+#[inline]
 pub fn Arg(pos: u64) -> Ptr {
   (ARG * TAG) | pos
 }
 
+#[inline]
 pub fn Era() -> Ptr {
   ERA * TAG
 }
 
+#[inline]
 pub fn Lam(pos: u64) -> Ptr {
   (LAM * TAG) | pos
 }
 
+#[inline]
 pub fn App(pos: u64) -> Ptr {
   (APP * TAG) | pos
 }
 
+#[inline]
 pub fn Sup(col: u64, pos: u64) -> Ptr {
   (SUP * TAG) | (col * EXT) | pos
 }
 
+#[inline]
 pub fn Op2(ope: u64, pos: u64) -> Ptr {
   (OP2 * TAG) | (ope * EXT) | pos
 }
 
+#[inline]
 pub fn U6O(val: u64) -> Ptr {
   (U60 * TAG) | val
 }
 
+#[inline]
 pub fn F6O(val: u64) -> Ptr {
   (F60 * TAG) | val
 }
 
+#[inline]
 pub fn Ctr(fun: u64, pos: u64) -> Ptr {
   (CTR * TAG) | (fun * EXT) | pos
 }
 
+#[inline]
 pub fn Fun(fun: u64, pos: u64) -> Ptr {
   (FUN * TAG) | (fun * EXT) | pos
 }
@@ -302,22 +323,27 @@ pub fn Fun(fun: u64, pos: u64) -> Ptr {
 // Pointer Getters
 // ---------------
 
+#[inline]
 pub fn get_tag(lnk: Ptr) -> u64 {
   lnk / TAG
 }
 
+#[inline]
 pub fn get_ext(lnk: Ptr) -> u64 {
   (lnk / EXT) & 0xFFF_FFFF
 }
 
+#[inline]
 pub fn get_val(lnk: Ptr) -> u64 {
   lnk & 0xFFFF_FFFF
 }
 
+#[inline]
 pub fn get_num(lnk: Ptr) -> u64 {
   lnk & 0xFFF_FFFF_FFFF_FFFF
 }
 
+#[inline]
 pub fn get_loc(lnk: Ptr, arg: u64) -> u64 {
   get_val(lnk) + arg
 }
@@ -326,14 +352,52 @@ pub fn get_cost(heap: &Heap) -> u64 {
   heap.lvar.iter().map(|x| x.cost.load(Ordering::Relaxed)).sum()
 }
 
+pub fn get_tids_cost(heap: &Heap, tids: &[usize]) -> u64 {
+  tids.iter().map(|tid| heap.lvar[*tid].cost.load(Ordering::Relaxed)).sum()
+}
+
+// Reductions halt early once the rewrites made by their threads exceed the limit set on them,
+// leaving the term half-reduced
+pub fn exceeded_limit(heap: &Heap, tid: usize, tids: &[usize]) -> bool {
+  let limit = heap.lvar[tid].limit.load(Ordering::Relaxed);
+  limit != u64::MAX && get_tids_cost(heap, tids) > limit
+}
+
+// Whether the thread's reductions have a limit, which code that makes many rewrites at once must
+// leave to the reducer to check
+#[inline]
+pub fn has_limit(heap: &Heap, tid: usize) -> bool {
+  heap.lvar[tid].limit.load(Ordering::Relaxed) != u64::MAX
+}
+
+pub fn set_limit(heap: &Heap, tids: &[usize], limit: u64) {
+  for tid in tids {
+    heap.lvar[*tid].limit.store(limit, Ordering::Relaxed);
+  }
+}
+
 pub fn get_used(heap: &Heap) -> i64 {
   heap.lvar.iter().map(|x| x.used.load(Ordering::Relaxed)).sum()
 }
 
+#[inline]
 pub fn inc_cost(heap: &Heap, tid: usize) {
   unsafe { heap.lvar.get_unchecked(tid) }.cost.fetch_add(1, Ordering::Relaxed);
+  #[cfg(feature = "observer")]
+  super::observer::observe_rewrite(heap, tid);
 }
 
+// Counts many rewrites at once, like calling `inc_cost` once for each
+#[inline]
+pub fn add_cost(heap: &Heap, tid: usize, cost: u64) {
+  unsafe { heap.lvar.get_unchecked(tid) }.cost.fetch_add(cost, Ordering::Relaxed);
+  #[cfg(feature = "observer")]
+  for _ in 0 .. cost {
+    super::observer::observe_rewrite(heap, tid);
+  }
+}
+
+#[inline]
 pub fn gen_dup(heap: &Heap, tid: usize) -> u64 {
   return unsafe { heap.lvar.get_unchecked(tid) }.dups.fetch_add(1, Ordering::Relaxed) & 0xFFF_FFFF;
 }
@@ -346,31 +410,37 @@ pub fn arity_of(arit: &ArityMap, lnk: Ptr) -> u64 {
 // --------
 
 // Given a location, loads the ptr stored on it
+#[inline]
 pub fn load_ptr(heap: &Heap, loc: u64) -> Ptr {
   unsafe { heap.node.get_unchecked(loc as usize).load(Ordering::Relaxed) }
 }
 
 // Moves a pointer to another location
+#[inline]
 pub fn move_ptr(heap: &Heap, old_loc: u64, new_loc: u64) -> Ptr {
   link(heap, new_loc, take_ptr(heap, old_loc))
 }
 
 // Given a pointer to a node, loads its nth arg
+#[inline]
 pub fn load_arg(heap: &Heap, term: Ptr, arg: u64) -> Ptr {
   load_ptr(heap, get_loc(term, arg))
 }
 
 // Given a location, takes the ptr stored on it
+#[inline]
 pub fn take_ptr(heap: &Heap, loc: u64) -> Ptr {
   unsafe { heap.node.get_unchecked(loc as usize).swap(0, Ordering::Relaxed) }
 }
 
 // Given a pointer to a node, takes its nth arg
+#[inline]
 pub fn take_arg(heap: &Heap, term: Ptr, arg: u64) -> Ptr {
   take_ptr(heap, get_loc(term, arg))
 }
 
 // Writes a ptr to memory. Updates binders.
+#[inline]
 pub fn link(heap: &Heap, loc: u64, ptr: Ptr) -> Ptr {
   unsafe {
     heap.node.get_unchecked(loc as usize).store(ptr, Ordering::Relaxed);
@@ -412,6 +482,9 @@ pub fn new_heap(size: usize, tids: usize) -> Heap {
       amax: AtomicU64::new((size / tids * (tid + 1)) as u64),
       dups: AtomicU64::new(((1 << 28) / tids * tid) as u64),
       cost: AtomicU64::new(0),
+      limit: AtomicU64::new(u64::MAX),
+      #[cfg(feature = "observer")]
+      redex: AtomicU64::new(0),
     }))
   }
   let node = new_atomic_u64_array(size);
@@ -427,12 +500,24 @@ pub fn new_heap(size: usize, tids: usize) -> Heap {
     .collect::<Vec<Box<[AtomicU64]>>>()
     .into_boxed_slice();
   let vstk = (0..tids).map(|x| VisitQueue::new()).collect::<Vec<VisitQueue>>().into_boxed_slice();
-  return Heap { tids, node, lock, lvar, rbag, aloc, vbuf, vstk };
+  return Heap {
+    tids,
+    node,
+    lock,
+    lvar,
+    rbag,
+    aloc,
+    vbuf,
+    vstk,
+    #[cfg(feature = "observer")]
+    observer: None,
+  };
 }
 
 // Allocator
 // ---------
 
+#[inline]
 pub fn alloc(heap: &Heap, tid: usize, arity: u64) -> u64 {
   unsafe {
     let lvar = &heap.lvar.get_unchecked(tid);
@@ -470,14 +555,20 @@ pub fn alloc(heap: &Heap, tid: usize, arity: u64) -> u64 {
           //if tid == 9 && count > 50000 {
           //println!("[{}] allocated {}! {}", 9, length, *lvar.next.as_ptr() - length);
           //}
-          return *lvar.next.as_ptr() - length;
+          let loc = *lvar.next.as_ptr() - length;
+          #[cfg(feature = "observer")]
+          super::observer::observe_alloc(heap, tid, loc, arity);
+          return loc;
         }
       }
     }
   }
 }
 
+#[inline]
 pub fn free(heap: &Heap, tid: usize, loc: u64, arity: u64) {
+  #[cfg(feature = "observer")]
+  super::observer::observe_free(heap, tid, loc, arity);
   for i in 0..arity {
     unsafe { heap.node.get_unchecked((loc + i) as usize) }.store(0, Ordering::Relaxed);
   }
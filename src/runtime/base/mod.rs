@@ -1,11 +1,19 @@
 pub mod debug;
+pub mod io;
 pub mod memory;
+#[cfg(feature = "observer")]
+pub mod observer;
+pub mod optimize;
 pub mod precomp;
 pub mod program;
 pub mod reducer;
 
 pub use debug::{*};
+pub use io::{*};
 pub use memory::{*};
+#[cfg(feature = "observer")]
+pub use observer::{*};
+pub use optimize::{*};
 pub use precomp::{*};
 pub use program::{*};
 pub use reducer::{*};
@@ -0,0 +1,84 @@
+// Reduction observers
+// -------------------
+//
+// With the `observer` feature, a `ReductionObserver` registered on the `RuntimeBuilder` is told
+// about each rewrite, allocation, free and steal, as it happens, on the thread that does it. This
+// is meant for profilers, such as rewrite counts per function, or allocation histograms. Without
+// the feature, none of the hooks is compiled.
+//
+// Callbacks run in the middle of the reduction, so they should be quick, and must not reduce on
+// the same heap. The allocations made by a rewrite are reported right after it.
+
+use crate::runtime::{*};
+use std::sync::atomic::Ordering;
+
+/// the kind of rule applied by a rewrite
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RewriteKind {
+  /// a lambda application, or an application of a superposition
+  App,
+  /// a duplication
+  Dup,
+  /// a numeric operation, or an operation on a superposition
+  Op2,
+  /// a call of the function with the given id, including natives
+  Fun(u64),
+}
+
+impl RewriteKind {
+  // Classifies a rewrite by the redex it was made on
+  fn of(redex: Ptr) -> Self {
+    match get_tag(redex) {
+      APP => RewriteKind::App,
+      DP0 | DP1 => RewriteKind::Dup,
+      OP2 => RewriteKind::Op2,
+      _ => RewriteKind::Fun(get_ext(redex)),
+    }
+  }
+}
+
+/// receives reduction events; every callback does nothing by default.
+pub trait ReductionObserver: Send + Sync {
+  /// called when thread `tid` applies a rewrite rule.
+  fn on_rewrite(&self, _heap: &Heap, _tid: usize, _kind: RewriteKind) {}
+
+  /// called when thread `tid` allocates `arity` nodes at `loc`.
+  fn on_alloc(&self, _heap: &Heap, _tid: usize, _loc: u64, _arity: u64) {}
+
+  /// called when thread `tid` frees `arity` nodes at `loc`.
+  fn on_free(&self, _heap: &Heap, _tid: usize, _loc: u64, _arity: u64) {}
+
+  /// called when thread `tid` steals a task queued by thread `victim`.
+  fn on_steal(&self, _heap: &Heap, _tid: usize, _victim: usize) {}
+}
+
+// Remembers the redex that thread `tid` is about to rewrite, so `observe_rewrite` can classify it
+pub fn observe_redex(heap: &Heap, tid: usize, redex: Ptr) {
+  if heap.observer.is_some() {
+    heap.lvar[tid].redex.store(redex, Ordering::Relaxed);
+  }
+}
+
+pub fn observe_rewrite(heap: &Heap, tid: usize) {
+  if let Some(observer) = &heap.observer {
+    observer.on_rewrite(heap, tid, RewriteKind::of(heap.lvar[tid].redex.load(Ordering::Relaxed)));
+  }
+}
+
+pub fn observe_alloc(heap: &Heap, tid: usize, loc: u64, arity: u64) {
+  if let Some(observer) = &heap.observer {
+    observer.on_alloc(heap, tid, loc, arity);
+  }
+}
+
+pub fn observe_free(heap: &Heap, tid: usize, loc: u64, arity: u64) {
+  if let Some(observer) = &heap.observer {
+    observer.on_free(heap, tid, loc, arity);
+  }
+}
+
+pub fn observe_steal(heap: &Heap, tid: usize, victim: usize) {
+  if let Some(observer) = &heap.observer {
+    observer.on_steal(heap, tid, victim);
+  }
+}
@@ -0,0 +1,428 @@
+// Optimizations on the rules of a program, after they're built and before they're evaluated or
+// compiled. They work on each rule's `Core`, whose variables are numbered by the depth of their
+// binders, and rely on the sanitizer having made every variable used at most once.
+
+use crate::runtime::{*};
+
+// The biggest body, in `Core` nodes, of a function that can be inlined
+const INLINE_SIZE: usize = 16;
+
+// Which optimizations to run. `level` gives the ones of `-O0`, `-O1` and `-O2`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Optimizations {
+  // Folds operations on number literals, and copies of them
+  pub fold: bool,
+  // Reduces applications of lambdas written in place, and lets of variables and numbers
+  pub beta: bool,
+  // Inlines calls to small, non-recursive functions which don't pattern-match
+  pub inline: bool,
+  // Removes rules that can never be selected, because earlier rules match first
+  pub dead_rules: bool,
+}
+
+impl Optimizations {
+  // `-O0` runs nothing, `-O1` only runs the passes that keep each rule on its own, and `-O2`
+  // also inlines functions
+  pub fn level(level: u8) -> Optimizations {
+    Optimizations {
+      fold: level >= 1,
+      beta: level >= 1,
+      inline: level >= 2,
+      dead_rules: level >= 1,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.fold || self.beta || self.inline || self.dead_rules
+  }
+}
+
+impl std::str::FromStr for Optimizations {
+  type Err = String;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    match text {
+      "0" => Ok(Optimizations::level(0)),
+      "1" => Ok(Optimizations::level(1)),
+      "2" => Ok(Optimizations::level(2)),
+      _   => Err(format!("unknown optimization level '{}', expected 0, 1 or 2", text)),
+    }
+  }
+}
+
+// Runs the optimizations on the interpreted functions, rebuilding the bodies of their rules
+pub fn optimize(funs: &mut Funs, opts: &Optimizations) {
+  if !opts.is_enabled() {
+    return;
+  }
+  let inlinable = if opts.inline { find_inlinable(funs, opts) } else { U64Map::new() };
+  for fun in funs.data.iter_mut().flatten() {
+    if let Function::Interpreted { apply, .. } = fun {
+      if opts.dead_rules {
+        remove_dead_rules(apply);
+      }
+      for rule in &mut apply.rules {
+        let depth = rule.vars.len() as u64;
+        rule.core = optimize_core(&rule.core, depth, opts, &inlinable);
+        rule.body = build_body(&rule.core, depth);
+      }
+    }
+  }
+}
+
+fn optimize_core(core: &Core, depth: u64, opts: &Optimizations, inlinable: &U64Map<Inlinable>) -> Core {
+  let core = if opts.inline { inline(core, depth, inlinable) } else { core.clone() };
+  if opts.fold || opts.beta { simplify(&core, depth, opts) } else { core }
+}
+
+// Dead rules
+// ----------
+
+// The match tree already skips the rules earlier ones shadow. Removing them, and renumbering the
+// tree's leaves, keeps the tree, so which fields are reduced doesn't change.
+fn remove_dead_rules(apply: &mut ApplyObj) {
+  fn find_used(tree: &MatchTree, used: &mut [bool]) {
+    match tree {
+      MatchTree::Rule { index } => {
+        used[*index] = true;
+      }
+      MatchTree::Fail => {}
+      MatchTree::Switch { ctrs, u60s, f60s, value, other, .. } => {
+        for (_, tree) in ctrs.iter().chain(u60s).chain(f60s) {
+          find_used(tree, used);
+        }
+        find_used(value, used);
+        find_used(other, used);
+      }
+      MatchTree::Hoas { then, other, .. } => {
+        find_used(then, used);
+        find_used(other, used);
+      }
+    }
+  }
+  fn renumber(tree: &mut MatchTree, index_of: &[usize]) {
+    match tree {
+      MatchTree::Rule { index } => {
+        *index = index_of[*index];
+      }
+      MatchTree::Fail => {}
+      MatchTree::Switch { ctrs, u60s, f60s, value, other, .. } => {
+        for (_, tree) in ctrs.iter_mut().chain(u60s.iter_mut()).chain(f60s.iter_mut()) {
+          renumber(tree, index_of);
+        }
+        renumber(value, index_of);
+        renumber(other, index_of);
+      }
+      MatchTree::Hoas { then, other, .. } => {
+        renumber(then, index_of);
+        renumber(other, index_of);
+      }
+    }
+  }
+  let mut used = vec![false; apply.rules.len()];
+  find_used(&apply.tree, &mut used);
+  if used.iter().all(|used| *used) {
+    return;
+  }
+  let mut index_of = vec![0; used.len()];
+  let mut count = 0;
+  for (index, used) in used.iter().enumerate() {
+    index_of[index] = count;
+    if *used {
+      count += 1;
+    }
+  }
+  let mut index = 0;
+  apply.rules.retain(|_| {
+    index += 1;
+    used[index - 1]
+  });
+  renumber(&mut apply.tree, &index_of);
+}
+
+// Inlining
+// --------
+
+// A function that can be inlined: the body of its only rule, and which of its params it erases
+struct Inlinable {
+  core: Core,
+  erase: Vec<bool>,
+}
+
+// Finds the functions made of a single rule which doesn't match on, nor force, its arguments, and
+// doesn't lead back to the function, whose body is small once optimized and has no scopeless
+// variables. Arguments that strictness inference made strict are fine, as the body reduces them
+// anyway. They're optimized after the ones they call, so the bodies inlined are optimized too.
+fn find_inlinable(funs: &Funs, opts: &Optimizations) -> U64Map<Inlinable> {
+  fn calls(core: &Core, found: &mut Vec<u64>) {
+    if let Core::Fun { func, .. } = core {
+      found.push(*func);
+    }
+    for child in children(core) {
+      calls(child, found);
+    }
+  }
+  fn is_recursive(funs: &Funs, fid: u64) -> bool {
+    let mut seen = std::collections::HashSet::new();
+    let mut next = vec![fid];
+    while let Some(func) = next.pop() {
+      if let Some(Function::Interpreted { apply, .. }) = funs.get(&func) {
+        let mut found = Vec::new();
+        for rule in &apply.rules {
+          calls(&rule.core, &mut found);
+        }
+        for callee in found {
+          if callee == fid {
+            return true;
+          }
+          if seen.insert(callee) {
+            next.push(callee);
+          }
+        }
+      }
+    }
+    false
+  }
+  let mut pending = Vec::new();
+  for (fid, fun) in funs.data.iter().enumerate() {
+    if let Some(Function::Interpreted { apply, .. }) = fun {
+      if let [rule] = &apply.rules[..] {
+        let plain = !rule.hoas
+          && rule.cond.iter().all(|cond| matches!(cond, RuleCond::Var))
+          && matches!(apply.tree, MatchTree::Rule { .. });
+        if plain && !is_recursive(funs, fid as u64) {
+          let mut found = Vec::new();
+          calls(&rule.core, &mut found);
+          pending.push((fid as u64, rule, found));
+        }
+      }
+    }
+  }
+  // As none of them is recursive, there's always one which doesn't call the others left
+  let mut inlinable = U64Map::new();
+  while !pending.is_empty() {
+    let waiting : Vec<u64> = pending.iter().map(|(fid, _, _)| *fid).collect();
+    let (ready, rest) : (Vec<_>, Vec<_>) = pending.into_iter().partition(|(_, _, found)| {
+      found.iter().all(|func| !waiting.contains(func))
+    });
+    if ready.is_empty() {
+      break;
+    }
+    for (fid, rule, _) in ready {
+      let core = optimize_core(&rule.core, rule.vars.len() as u64, opts, &inlinable);
+      if size(&core) <= INLINE_SIZE && !has_globals(&core) {
+        let erase = rule.vars.iter().map(|var| var.erase).collect();
+        inlinable.insert(fid, Inlinable { core, erase });
+      }
+    }
+    pending = rest;
+  }
+  inlinable
+}
+
+// Replaces calls to inlinable functions by lets of their arguments, on their bodies
+fn inline(core: &Core, depth: u64, inlinable: &U64Map<Inlinable>) -> Core {
+  match core {
+    Core::Fun { func, args } => {
+      let args : Vec<Core> = args.iter().map(|arg| inline(arg, depth, inlinable)).collect();
+      match inlinable.get(func) {
+        // Erased arguments are dropped, so they must not hold anything to collect
+        Some(callee) if args.iter().zip(&callee.erase).all(|(arg, erase)| !erase || is_droppable(arg)) => {
+          // Each argument is moved under the lets of the ones before it
+          let mut body = shift(&callee.core, 0, depth);
+          for (i, arg) in args.iter().enumerate().rev() {
+            body = Core::Let { expr: Box::new(shift(arg, depth, i as u64)), body: Box::new(body) };
+          }
+          body
+        }
+        _ => Core::Fun { func: *func, args },
+      }
+    }
+    _ => map_children(core, depth, &mut |child, depth| inline(child, depth, inlinable)),
+  }
+}
+
+// Folding and beta-reduction
+// --------------------------
+
+fn simplify(core: &Core, depth: u64, opts: &Optimizations) -> Core {
+  match core {
+    Core::Op2 { oper, val0, val1 } => {
+      let val0 = simplify(val0, depth, opts);
+      let val1 = simplify(val1, depth, opts);
+      if opts.fold {
+        if let Some(done) = fold(*oper, &val0, &val1) {
+          return done;
+        }
+      }
+      Core::Op2 { oper: *oper, val0: Box::new(val0), val1: Box::new(val1) }
+    }
+    // (λx body arg) becomes `let x = arg; body`. An erased argument is only dropped if there's
+    // nothing to collect in it.
+    Core::App { func, argm } => {
+      let func = simplify(func, depth, opts);
+      let argm = simplify(argm, depth, opts);
+      match func {
+        Core::Lam { glob: 0, body, .. } if opts.beta && (uses(&body, depth) == 1 || is_droppable(&argm)) => {
+          simplify(&Core::Let { expr: Box::new(argm), body }, depth, opts)
+        }
+        func => Core::App { func: Box::new(func), argm: Box::new(argm) },
+      }
+    }
+    // Lets of a variable, used once, or of a number are substituted, which can make more folds
+    Core::Let { expr, body } => {
+      let expr = simplify(expr, depth, opts);
+      let substitute = match expr {
+        Core::Var { .. } => uses(body, depth) == 1,
+        _ => is_droppable(&expr),
+      };
+      if opts.beta && substitute {
+        simplify(&subst(body, depth, &expr), depth, opts)
+      } else {
+        Core::Let { expr: Box::new(expr), body: Box::new(simplify(body, depth + 1, opts)) }
+      }
+    }
+    // Copying a number gives it to both variables
+    Core::Dup { eras, glob, expr, body } => {
+      let expr = simplify(expr, depth, opts);
+      if opts.fold && *glob == 0 && matches!(expr, Core::U6O { .. } | Core::F6O { .. }) {
+        simplify(&subst(&subst(body, depth + 1, &expr), depth, &expr), depth, opts)
+      } else {
+        Core::Dup { eras: *eras, glob: *glob, expr: Box::new(expr), body: Box::new(simplify(body, depth + 2, opts)) }
+      }
+    }
+    _ => map_children(core, depth, &mut |child, depth| simplify(child, depth, opts)),
+  }
+}
+
+// Computes an operation on two numbers the way the runtime does, unless it would fail
+fn fold(oper: u64, val0: &Core, val1: &Core) -> Option<Core> {
+  match (val0, val1) {
+    (Core::U6O { numb: a }, Core::U6O { numb: b }) => {
+      let (a, b) = (u60::new(*a), u60::new(*b));
+      let numb = match oper {
+        ADD => u60::add(a, b),
+        SUB => u60::sub(a, b),
+        MUL => u60::mul(a, b),
+        DIV if b != 0 => u60::div(a, b),
+        MOD if b != 0 => u60::mdl(a, b),
+        AND => u60::and(a, b),
+        OR  => u60::or(a, b),
+        XOR => u60::xor(a, b),
+        SHL if b < 64 => u60::shl(a, b),
+        SHR if b < 64 => u60::shr(a, b),
+        LTN => u60::ltn(a, b),
+        LTE => u60::lte(a, b),
+        EQL => u60::eql(a, b),
+        GTE => u60::gte(a, b),
+        GTN => u60::gtn(a, b),
+        NEQ => u60::neq(a, b),
+        _   => return None,
+      };
+      Some(Core::U6O { numb })
+    }
+    (Core::F6O { numb: a }, Core::F6O { numb: b }) => {
+      let (a, b) = (*a, *b);
+      let numb = match oper {
+        ADD => f60::add(a, b),
+        SUB => f60::sub(a, b),
+        MUL => f60::mul(a, b),
+        DIV => f60::div(a, b),
+        MOD => f60::mdl(a, b),
+        AND => f60::and(a, b),
+        OR  => f60::or(a, b),
+        XOR => f60::xor(a, b),
+        SHL => f60::shl(a, b),
+        SHR => f60::shr(a, b),
+        LTN => f60::ltn(a, b),
+        LTE => f60::lte(a, b),
+        EQL => f60::eql(a, b),
+        GTE => f60::gte(a, b),
+        GTN => f60::gtn(a, b),
+        NEQ => f60::neq(a, b),
+        _   => return None,
+      };
+      Some(Core::F6O { numb })
+    }
+    _ => None,
+  }
+}
+
+// Helpers
+// -------
+
+// Terms that allocate nothing, so they can be dropped, or copied, freely
+fn is_droppable(core: &Core) -> bool {
+  match core {
+    Core::U6O { .. } | Core::F6O { .. } => true,
+    Core::Ctr { args, .. } | Core::Fun { args, .. } => args.is_empty(),
+    _ => false,
+  }
+}
+
+fn children(core: &Core) -> Vec<&Core> {
+  match core {
+    Core::Var { .. } | Core::Glo { .. } | Core::U6O { .. } | Core::F6O { .. } => vec![],
+    Core::Dup { expr, body, .. } => vec![expr, body],
+    Core::Sup { val0, val1 } => vec![val0, val1],
+    Core::Let { expr, body } => vec![expr, body],
+    Core::Lam { body, .. } => vec![body],
+    Core::App { func, argm } => vec![func, argm],
+    Core::Fun { args, .. } | Core::Ctr { args, .. } => args.iter().collect(),
+    Core::Op2 { val0, val1, .. } => vec![val0, val1],
+  }
+}
+
+// Rebuilds a term with `f` applied to its children, given the depth each one is at
+fn map_children(core: &Core, depth: u64, f: &mut dyn FnMut(&Core, u64) -> Core) -> Core {
+  match core {
+    Core::Var { .. } | Core::Glo { .. } | Core::U6O { .. } | Core::F6O { .. } => core.clone(),
+    Core::Dup { eras, glob, expr, body } => Core::Dup { eras: *eras, glob: *glob, expr: Box::new(f(expr, depth)), body: Box::new(f(body, depth + 2)) },
+    Core::Sup { val0, val1 } => Core::Sup { val0: Box::new(f(val0, depth)), val1: Box::new(f(val1, depth)) },
+    Core::Let { expr, body } => Core::Let { expr: Box::new(f(expr, depth)), body: Box::new(f(body, depth + 1)) },
+    Core::Lam { eras, glob, body } => Core::Lam { eras: *eras, glob: *glob, body: Box::new(f(body, depth + 1)) },
+    Core::App { func, argm } => Core::App { func: Box::new(f(func, depth)), argm: Box::new(f(argm, depth)) },
+    Core::Fun { func, args } => Core::Fun { func: *func, args: args.iter().map(|arg| f(arg, depth)).collect() },
+    Core::Ctr { func, args } => Core::Ctr { func: *func, args: args.iter().map(|arg| f(arg, depth)).collect() },
+    Core::Op2 { oper, val0, val1 } => Core::Op2 { oper: *oper, val0: Box::new(f(val0, depth)), val1: Box::new(f(val1, depth)) },
+  }
+}
+
+fn size(core: &Core) -> usize {
+  1 + children(core).into_iter().map(size).sum::<usize>()
+}
+
+// Scopeless variables are named by a hash, which could clash with the caller's when inlined
+fn has_globals(core: &Core) -> bool {
+  match core {
+    Core::Glo { .. } => true,
+    Core::Dup { glob, .. } | Core::Lam { glob, .. } if *glob != 0 => true,
+    _ => children(core).into_iter().any(has_globals),
+  }
+}
+
+// How many times the variable bound at `bidx` occurs
+fn uses(core: &Core, bidx: u64) -> usize {
+  match core {
+    Core::Var { bidx: var } => (*var == bidx) as usize,
+    _ => children(core).into_iter().map(|child| uses(child, bidx)).sum(),
+  }
+}
+
+// Adds `delta` to the variables bound at `from` or deeper
+fn shift(core: &Core, from: u64, delta: u64) -> Core {
+  match core {
+    Core::Var { bidx } if *bidx >= from => Core::Var { bidx: bidx + delta },
+    _ => map_children(core, 0, &mut |child, _| shift(child, from, delta)),
+  }
+}
+
+// Replaces the variable bound at `bidx` by `value`, removing its binder. `value` can only refer to
+// variables bound before it.
+fn subst(core: &Core, bidx: u64, value: &Core) -> Core {
+  match core {
+    Core::Var { bidx: var } if *var == bidx => value.clone(),
+    Core::Var { bidx: var } if *var > bidx => Core::Var { bidx: var - 1 },
+    _ => map_children(core, 0, &mut |child, _| subst(child, bidx, value)),
+  }
+}
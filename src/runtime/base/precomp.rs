@@ -46,7 +46,26 @@ pub const HVM_PRINT : u64 = 26;
 pub const HVM_SLEEP : u64 = 27;
 pub const HVM_STORE : u64 = 28;
 pub const HVM_LOAD : u64 = 29;
-//[[CODEGEN:PRECOMP-IDS]]//
+pub const LIST_NIL : u64 = 30;
+pub const LIST_CONS : u64 = 31;
+pub const HVM_COLLAPSE : u64 = 32;
+pub const IO_DONE : u64 = 33;
+pub const IO_DO_INPUT : u64 = 34;
+pub const IO_DO_OUTPUT : u64 = 35;
+pub const IO_DO_STORE : u64 = 36;
+pub const IO_DO_LOAD : u64 = 37;
+pub const HVM_ENV : u64 = 38;
+pub const HVM_EXIT : u64 = 39;
+pub const HVM_EPRINT : u64 = 40;
+pub const HVM_PUT : u64 = 41;
+pub const RESULT_OK : u64 = 42;
+pub const RESULT_ERR : u64 = 43;
+pub const HVM_LOAD_BYTES : u64 = 44;
+pub const HVM_STORE_BYTES : u64 = 45;
+pub const HVM_APPEND : u64 = 46;
+pub const HVM_EXISTS : u64 = 47;
+pub const HVM_REMOVE : u64 = 48;
+pub const HVM_LIST_DIR : u64 = 49;
 
 pub const PRECOMP : &[Precomp] = &[
   Precomp {
@@ -253,7 +272,159 @@ pub const PRECOMP : &[Precomp] = &[
       apply: hvm_load_apply,
     }),
   },
-//[[CODEGEN:PRECOMP-ELS]]//
+  Precomp {
+    id: LIST_NIL,
+    name: "List.nil",
+    smap: &[false; 0],
+    funs: None,
+  },
+  Precomp {
+    id: LIST_CONS,
+    name: "List.cons",
+    smap: &[false; 2],
+    funs: None,
+  },
+  Precomp {
+    id: HVM_COLLAPSE,
+    name: "HVM.collapse",
+    smap: &[false; 1],
+    funs: Some(PrecompFuns {
+      visit: hvm_collapse_visit,
+      apply: hvm_collapse_apply,
+    }),
+  },
+  Precomp {
+    id: IO_DONE,
+    name: "IO.done",
+    smap: &[false; 1],
+    funs: None,
+  },
+  Precomp {
+    id: IO_DO_INPUT,
+    name: "IO.do_input",
+    smap: &[false; 1],
+    funs: None,
+  },
+  Precomp {
+    id: IO_DO_OUTPUT,
+    name: "IO.do_output",
+    smap: &[false; 2],
+    funs: None,
+  },
+  Precomp {
+    id: IO_DO_STORE,
+    name: "IO.do_store",
+    smap: &[false; 3],
+    funs: None,
+  },
+  Precomp {
+    id: IO_DO_LOAD,
+    name: "IO.do_load",
+    smap: &[false; 2],
+    funs: None,
+  },
+  Precomp {
+    id: HVM_ENV,
+    name: "HVM.env",
+    smap: &[false; 2],
+    funs: Some(PrecompFuns {
+      visit: hvm_env_visit,
+      apply: hvm_env_apply,
+    }),
+  },
+  Precomp {
+    id: HVM_EXIT,
+    name: "HVM.exit",
+    smap: &[false; 1],
+    funs: Some(PrecompFuns {
+      visit: hvm_exit_visit,
+      apply: hvm_exit_apply,
+    }),
+  },
+  Precomp {
+    id: HVM_EPRINT,
+    name: "HVM.eprint",
+    smap: &[false; 2],
+    funs: Some(PrecompFuns {
+      visit: hvm_eprint_visit,
+      apply: hvm_eprint_apply,
+    }),
+  },
+  Precomp {
+    id: HVM_PUT,
+    name: "HVM.put",
+    smap: &[false; 2],
+    funs: Some(PrecompFuns {
+      visit: hvm_put_visit,
+      apply: hvm_put_apply,
+    }),
+  },
+  Precomp {
+    id: RESULT_OK,
+    name: "Result.ok",
+    smap: &[false; 1],
+    funs: None,
+  },
+  Precomp {
+    id: RESULT_ERR,
+    name: "Result.err",
+    smap: &[false; 1],
+    funs: None,
+  },
+  Precomp {
+    id: HVM_LOAD_BYTES,
+    name: "HVM.load_bytes",
+    smap: &[false; 2],
+    funs: Some(PrecompFuns {
+      visit: hvm_load_bytes_visit,
+      apply: hvm_load_bytes_apply,
+    }),
+  },
+  Precomp {
+    id: HVM_STORE_BYTES,
+    name: "HVM.store_bytes",
+    smap: &[false; 3],
+    funs: Some(PrecompFuns {
+      visit: hvm_store_bytes_visit,
+      apply: hvm_store_bytes_apply,
+    }),
+  },
+  Precomp {
+    id: HVM_APPEND,
+    name: "HVM.append",
+    smap: &[false; 3],
+    funs: Some(PrecompFuns {
+      visit: hvm_append_visit,
+      apply: hvm_append_apply,
+    }),
+  },
+  Precomp {
+    id: HVM_EXISTS,
+    name: "HVM.exists",
+    smap: &[false; 2],
+    funs: Some(PrecompFuns {
+      visit: hvm_exists_visit,
+      apply: hvm_exists_apply,
+    }),
+  },
+  Precomp {
+    id: HVM_REMOVE,
+    name: "HVM.remove",
+    smap: &[false; 2],
+    funs: Some(PrecompFuns {
+      visit: hvm_remove_visit,
+      apply: hvm_remove_apply,
+    }),
+  },
+  Precomp {
+    id: HVM_LIST_DIR,
+    name: "HVM.list_dir",
+    smap: &[false; 2],
+    funs: Some(PrecompFuns {
+      visit: hvm_list_dir_visit,
+      apply: hvm_list_dir_apply,
+    }),
+  },
 ];
 
 pub const PRECOMP_COUNT : u64 = PRECOMP.len() as u64;
@@ -401,7 +572,7 @@ fn hvm_print_visit(ctx: ReduceCtx) -> bool {
 
 fn hvm_print_apply(ctx: ReduceCtx) -> bool {
   //normalize(ctx.heap, ctx.prog, &[ctx.tid], get_loc(ctx.term, 0), false);
-  if let Some(text) = crate::language::readback::as_string(ctx.heap, ctx.prog, &[ctx.tid], get_loc(ctx.term, 0)) {
+  if let Some(text) = read_string(&ctx, 0) {
     println!("{}", text);
   }
   link(ctx.heap, *ctx.host, load_arg(ctx.heap, ctx.term, 1));
@@ -433,8 +604,8 @@ fn hvm_store_visit(ctx: ReduceCtx) -> bool {
 }
 
 fn hvm_store_apply(ctx: ReduceCtx) -> bool {
-  if let Some(key) = crate::language::readback::as_string(ctx.heap, ctx.prog, &[ctx.tid], get_loc(ctx.term, 0)) {
-    if let Some(val) = crate::language::readback::as_string(ctx.heap, ctx.prog, &[ctx.tid], get_loc(ctx.term, 1)) {
+  if let Some(key) = read_string(&ctx, 0) {
+    if let Some(val) = read_string(&ctx, 1) {
       if std::fs::write(key, val).is_ok() {
         //let app0 = alloc(ctx.heap, ctx.tid, 2);
         //link(ctx.heap, app0 + 0, cont);
@@ -449,8 +620,7 @@ fn hvm_store_apply(ctx: ReduceCtx) -> bool {
       }
     }
   }
-  println!("Runtime failure on: {}", show_at(ctx.heap, ctx.prog, *ctx.host, &[]));
-  std::process::exit(0);
+  runtime_failure(ctx.heap, ctx.prog, *ctx.host);
 }
 
 // HVM.load (key: String) (cont: String -> Term)
@@ -461,7 +631,7 @@ fn hvm_load_visit(ctx: ReduceCtx) -> bool {
 }
 
 fn hvm_load_apply(ctx: ReduceCtx) -> bool {
-  if let Some(key) = crate::language::readback::as_string(ctx.heap, ctx.prog, &[ctx.tid], get_loc(ctx.term, 0)) {
+  if let Some(key) = read_string(&ctx, 0) {
     if let Ok(file) = std::fs::read(key) {
       if let Ok(file) = std::str::from_utf8(&file) {
         let cont = load_arg(ctx.heap, ctx.term, 1); 
@@ -476,8 +646,245 @@ fn hvm_load_apply(ctx: ReduceCtx) -> bool {
       }
     }
   }
-  println!("Runtime failure on: {}", show_at(ctx.heap, ctx.prog, *ctx.host, &[]));
-  std::process::exit(0);
+  runtime_failure(ctx.heap, ctx.prog, *ctx.host);
+}
+
+// HVM.collapse (term: Term)
+// -------------------------
+
+// Collects every branch of the term into a list, eagerly, so a term with infinitely many branches
+// never returns. Its variables must be bound inside it, as branches can't share them.
+
+fn hvm_collapse_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_collapse_apply(ctx: ReduceCtx) -> bool {
+  normalize_nested(ctx.heap, ctx.prog, ctx.tid, get_loc(ctx.term, 0));
+  let branches : Vec<Ptr> = crate::language::readback::collapse(ctx.heap, ctx.prog, ctx.tid, get_loc(ctx.term, 0)).collect();
+  let list = make_list(ctx.heap, ctx.tid, &branches);
+  link(ctx.heap, *ctx.host, list);
+  collect(ctx.heap, &ctx.prog.aris, ctx.tid, load_ptr(ctx.heap, get_loc(ctx.term, 0)));
+  free(ctx.heap, ctx.tid, get_loc(ctx.term, 0), 1);
+  true
+}
+
+// HVM.exit (code: U60)
+// ---------------------
+
+fn hvm_exit_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_exit_apply(ctx: ReduceCtx) -> bool {
+  let code = normalize_nested(ctx.heap, ctx.prog, ctx.tid, get_loc(ctx.term, 0));
+  if get_tag(code) != U60 {
+    runtime_failure(ctx.heap, ctx.prog, *ctx.host);
+  }
+  fail(Failure::Exit(get_num(code) as i32));
+}
+
+// HVM.eprint (text: String) (cont: Term)
+// --------------------------------------
+
+fn hvm_eprint_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_eprint_apply(ctx: ReduceCtx) -> bool {
+  if let Some(text) = read_string(&ctx, 0) {
+    eprintln!("{}", text);
+  }
+  link(ctx.heap, *ctx.host, load_arg(ctx.heap, ctx.term, 1));
+  collect(ctx.heap, &ctx.prog.aris, ctx.tid, load_ptr(ctx.heap, get_loc(ctx.term, 0)));
+  free(ctx.heap, ctx.tid, get_loc(ctx.term, 0), 2);
+  true
+}
+
+// HVM.put (text: String) (cont: Term)
+// -----------------------------------
+
+fn hvm_put_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_put_apply(ctx: ReduceCtx) -> bool {
+  if let Some(text) = read_string(&ctx, 0) {
+    use std::io::Write;
+    print!("{}", text);
+    std::io::stdout().flush().ok();
+  }
+  link(ctx.heap, *ctx.host, load_arg(ctx.heap, ctx.term, 1));
+  collect(ctx.heap, &ctx.prog.aris, ctx.tid, load_ptr(ctx.heap, get_loc(ctx.term, 0)));
+  free(ctx.heap, ctx.tid, get_loc(ctx.term, 0), 2);
+  true
+}
+
+// HVM.env (name: String) (cont: String -> Term)
+// ----------------------------------------------
+
+fn hvm_env_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+// Unset variables, and ones which aren't valid unicode, read as the empty string
+fn hvm_env_apply(ctx: ReduceCtx) -> bool {
+  if let Some(name) = read_string(&ctx, 0) {
+    let value = std::env::var(name).unwrap_or_default();
+    let cont = load_arg(ctx.heap, ctx.term, 1);
+    let text = make_string(ctx.heap, ctx.tid, &value);
+    let app0 = alloc(ctx.heap, ctx.tid, 2);
+    link(ctx.heap, app0 + 0, cont);
+    link(ctx.heap, app0 + 1, text);
+    collect(ctx.heap, &ctx.prog.aris, ctx.tid, load_arg(ctx.heap, ctx.term, 0));
+    free(ctx.heap, ctx.tid, get_loc(ctx.term, 0), 2);
+    link(ctx.heap, *ctx.host, App(app0));
+    return true;
+  }
+  runtime_failure(ctx.heap, ctx.prog, *ctx.host);
 }
 
-//[[CODEGEN:PRECOMP-FNS]]//
+// File builtins
+// -------------
+//
+// These take a path and, last, a continuation, which receives `(Result.ok val)` on success, or
+// `(Result.err msg)` on failure, so that programs can handle failures themselves. Bytes are
+// represented as a `List` of U60 values between 0 and 255.
+
+// HVM.load_bytes (key: String) (cont: Result (List U60) String -> Term)
+fn hvm_load_bytes_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_load_bytes_apply(ctx: ReduceCtx) -> bool {
+  let result = read_path(&ctx, 0).and_then(|key| std::fs::read(key).map_err(|err| err.to_string())).map(|bytes| {
+    let bytes : Vec<Ptr> = bytes.into_iter().map(|byte| U6O(byte as u64)).collect();
+    make_list(ctx.heap, ctx.tid, &bytes)
+  });
+  file_result(&ctx, 2, result)
+}
+
+// HVM.store_bytes (key: String) (bytes: List U60) (cont: Result U60 String -> Term)
+fn hvm_store_bytes_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_store_bytes_apply(ctx: ReduceCtx) -> bool {
+  let result = read_path(&ctx, 0).and_then(|key| {
+    let bytes = read_bytes(&ctx, 1)?;
+    std::fs::write(key, bytes).map_err(|err| err.to_string())
+  });
+  file_result(&ctx, 3, result.map(|_| U6O(0)))
+}
+
+// HVM.append (key: String) (text: String) (cont: Result U60 String -> Term)
+fn hvm_append_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_append_apply(ctx: ReduceCtx) -> bool {
+  let result = read_path(&ctx, 0).and_then(|key| {
+    let text = read_string(&ctx, 1).ok_or("Expected a string.")?;
+    let mut file = std::fs::OpenOptions::new().append(true).create(true).open(key).map_err(|err| err.to_string())?;
+    std::io::Write::write_all(&mut file, text.as_bytes()).map_err(|err| err.to_string())
+  });
+  file_result(&ctx, 3, result.map(|_| U6O(0)))
+}
+
+// HVM.exists (key: String) (cont: Result U60 String -> Term), with 1 if it exists, 0 otherwise
+fn hvm_exists_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_exists_apply(ctx: ReduceCtx) -> bool {
+  let result = read_path(&ctx, 0).and_then(|key| std::path::Path::new(&key).try_exists().map_err(|err| err.to_string()));
+  file_result(&ctx, 2, result.map(|exists| U6O(exists as u64)))
+}
+
+// HVM.remove (key: String) (cont: Result U60 String -> Term)
+fn hvm_remove_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_remove_apply(ctx: ReduceCtx) -> bool {
+  let result = read_path(&ctx, 0).and_then(|key| std::fs::remove_file(key).map_err(|err| err.to_string()));
+  file_result(&ctx, 2, result.map(|_| U6O(0)))
+}
+
+// HVM.list_dir (key: String) (cont: Result (List String) String -> Term), with sorted names
+fn hvm_list_dir_visit(ctx: ReduceCtx) -> bool {
+  false
+}
+
+fn hvm_list_dir_apply(ctx: ReduceCtx) -> bool {
+  let result = read_path(&ctx, 0).and_then(|key| {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(key).map_err(|err| err.to_string())? {
+      names.push(entry.map_err(|err| err.to_string())?.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    Ok(names)
+  }).map(|names| {
+    let names : Vec<Ptr> = names.iter().map(|name| make_string(ctx.heap, ctx.tid, name)).collect();
+    make_list(ctx.heap, ctx.tid, &names)
+  });
+  file_result(&ctx, 2, result)
+}
+
+fn read_path(ctx: &ReduceCtx, n: u64) -> Result<String, String> {
+  read_string(ctx, n).ok_or_else(|| "Expected a path.".to_string())
+}
+
+// Reads a `String`, normalizing it first, with the visits this thread left pending set aside
+fn read_string(ctx: &ReduceCtx, n: u64) -> Option<String> {
+  normalize_nested(ctx.heap, ctx.prog, ctx.tid, get_loc(ctx.term, n));
+  crate::language::readback::as_normal_string(ctx.heap, get_loc(ctx.term, n))
+}
+
+// Reads a `List` of bytes, normalizing it first
+fn read_bytes(ctx: &ReduceCtx, n: u64) -> Result<Vec<u8>, String> {
+  normalize_nested(ctx.heap, ctx.prog, ctx.tid, get_loc(ctx.term, n));
+  let mut bytes = Vec::new();
+  let mut list = load_arg(ctx.heap, ctx.term, n);
+  while get_tag(list) == CTR && get_ext(list) == LIST_CONS {
+    let byte = load_arg(ctx.heap, list, 0);
+    if get_tag(byte) != U60 || get_num(byte) > 0xFF {
+      return Err("Expected a list of bytes.".to_string());
+    }
+    bytes.push(get_num(byte) as u8);
+    list = load_arg(ctx.heap, list, 1);
+  }
+  if get_tag(list) != CTR || get_ext(list) != LIST_NIL {
+    return Err("Expected a list of bytes.".to_string());
+  }
+  Ok(bytes)
+}
+
+// Applies the continuation, the last of the `arit` arguments, to the result
+fn file_result(ctx: &ReduceCtx, arit: u64, result: Result<Ptr, String>) -> bool {
+  let res0 = alloc(ctx.heap, ctx.tid, 1);
+  let result = match result {
+    Ok(val) => {
+      link(ctx.heap, res0, val);
+      Ctr(RESULT_OK, res0)
+    }
+    Err(err) => {
+      link(ctx.heap, res0, make_string(ctx.heap, ctx.tid, &err));
+      Ctr(RESULT_ERR, res0)
+    }
+  };
+  let app0 = alloc(ctx.heap, ctx.tid, 2);
+  link(ctx.heap, app0 + 0, load_arg(ctx.heap, ctx.term, arit - 1));
+  link(ctx.heap, app0 + 1, result);
+  for n in 0 .. arit - 1 {
+    collect(ctx.heap, &ctx.prog.aris, ctx.tid, load_arg(ctx.heap, ctx.term, n));
+  }
+  free(ctx.heap, ctx.tid, get_loc(ctx.term, 0), arit);
+  link(ctx.heap, *ctx.host, App(app0));
+  true
+}
+
+// Reports a builtin applied to arguments it can't handle, failing the evaluation
+fn runtime_failure(heap: &Heap, prog: &Program, host: u64) -> ! {
+  fail(Failure::Error(format!("Runtime failure on: {}", show_at(heap, prog, host, &[]))));
+}
@@ -1,6 +1,6 @@
 use crate::runtime::{*};
 use crate::language;
-use std::collections::{hash_map, HashMap};
+use std::collections::{hash_map, HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 // A runtime term
@@ -24,18 +24,27 @@ pub enum Core {
 #[derive(Clone, Debug)]
 pub struct Rule {
   pub hoas: bool,
-  pub cond: Vec<Ptr>,
+  pub cond: Vec<RuleCond>,
   pub vars: Vec<RuleVar>,
   pub core: Core,
   pub body: RuleBody,
-  pub free: Vec<(u64, u64)>,
+  pub free: Vec<(u64, Vec<u64>, u64)>, // the matched ctrs' param, path and arity, inner ones first
 }
 
-// A rule left-hand side variable
+// A rule left-hand side condition, on a param or, nested, on a field of a matched ctr
+#[derive(Clone, Debug)]
+pub enum RuleCond {
+  Var,
+  U6O { numb: u64 },
+  F6O { numb: u64 },
+  Ctr { func: u64, args: Vec<RuleCond> },
+}
+
+// A rule left-hand side variable, on a param or, following `path`, on the fields of matched ctrs
 #[derive(Clone, Debug)]
 pub struct RuleVar {
   pub param: u64,
-  pub field: Option<u64>,
+  pub path: Vec<u64>,
   pub erase: bool,
 }
 
@@ -63,6 +72,277 @@ pub struct VisitObj {
 
 pub struct ApplyObj {
   pub rules: Vec<Rule>,
+  pub tree: MatchTree,
+}
+
+// A decision tree selecting the rule a call matches, built from the rules' conditions. It tests
+// each argument, and each field of a matched constructor, at most once, instead of testing the
+// rules one after the other.
+#[derive(Clone, Debug)]
+pub enum MatchTree {
+  // The call matches the rule with this index
+  Rule { index: usize },
+  // The call matches no rule
+  Fail,
+  // Selects a branch by the constructor, or number, of an argument or, following `path`, of a
+  // field of the matched constructors, sorted by them. Other constructors and numbers go to
+  // `value`, and other terms (lambdas, erasures...) to `other`. Fields are reduced first.
+  Switch {
+    arg: u64,
+    path: Vec<u64>,
+    ctrs: Vec<(u64, MatchTree)>,
+    u60s: Vec<(u64, MatchTree)>,
+    f60s: Vec<(u64, MatchTree)>,
+    value: Box<MatchTree>,
+    other: Box<MatchTree>,
+  },
+  // Tests HOAS_OPT's condition on an argument, or field, already known to be a constructor or a
+  // number
+  Hoas { arg: u64, path: Vec<u64>, then: Box<MatchTree>, other: Box<MatchTree> },
+}
+
+// What walking a match tree found
+pub enum Selection<'a> {
+  // The call matches the rule with this index
+  Rule(usize),
+  // The call matches no rule
+  Fail,
+  // A field the call matches on is a superposition, which must be lifted to the call first
+  Sup(u64, &'a [u64]),
+  // A field the call matches on, at this location, must be reduced first
+  Reduce(u64),
+}
+
+impl MatchTree {
+  // Selects the rule the call at `term` matches. Fields it matches on must be in weak head normal
+  // form, so it asks for the first one that isn't to be reduced, and selects again after that.
+  pub fn select(&self, heap: &Heap, prog: &Program, term: Ptr) -> Selection<'_> {
+    let mut tree = self;
+    loop {
+      match tree {
+        MatchTree::Rule { index } => {
+          return Selection::Rule(*index);
+        }
+        MatchTree::Fail => {
+          return Selection::Fail;
+        }
+        MatchTree::Switch { arg: param, path, ctrs, u60s, f60s, value, other } => {
+          let arg = if path.is_empty() {
+            load_arg(heap, term, *param)
+          } else {
+            let loc = get_field_loc(heap, term, *param, path);
+            let arg = load_ptr(heap, loc);
+            if !is_whnf(arg) {
+              return Selection::Reduce(loc);
+            }
+            arg
+          };
+          let (cases, key) = match get_tag(arg) {
+            CTR => (ctrs, get_ext(arg)),
+            U60 => (u60s, get_num(arg)),
+            F60 => (f60s, get_num(arg)),
+            SUP if !path.is_empty() => {
+              return Selection::Sup(*param, path);
+            }
+            _ => {
+              tree = other;
+              continue;
+            }
+          };
+          tree = match cases.binary_search_by_key(&key, |(key, _)| *key) {
+            Ok(index) => &cases[index].1,
+            Err(_) => value,
+          };
+        }
+        MatchTree::Hoas { arg, path, then, other } => {
+          let arg = load_ptr(heap, get_field_loc(heap, term, *arg, path));
+          tree = if is_hoas_arg(&prog.aris, arg) { then } else { other };
+        }
+      }
+    }
+  }
+}
+
+// HOAS_OPT's condition, a Kind2-specific optimization: a default variable of a rule which isn't the
+// function's last only matches numbers, constructor labels, and Kind2's HOAS numbers and constructors
+pub fn is_hoas_arg(aris: &Aris, arg: Ptr) -> bool {
+  let is_num = get_tag(arg) == U60 || get_tag(arg) == F60;
+  let is_ctr = get_tag(arg) == CTR && arity_of(aris, arg) == 0;
+  let is_hoas_ctr_num = get_tag(arg) == CTR && get_ext(arg) >= KIND_TERM_CT0 && get_ext(arg) <= KIND_TERM_F60;
+  is_num || is_ctr || is_hoas_ctr_num
+}
+
+// What a rule requires from an argument or field, while building a match tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum MatchTest {
+  Any,
+  Ctr(u64, Vec<MatchTest>),
+  U60(u64),
+  F60(u64),
+  // a default variable on a strict argument or field: a constructor or a number
+  Value,
+  // a default variable under HOAS_OPT
+  Hoas,
+  // the same, on an argument or field already known to be a constructor or a number
+  HoasValue,
+}
+
+pub fn build_match_tree(rules: &[Rule], strict_map: &[bool]) -> MatchTree {
+  // A column is an argument, or the path to one of its fields. A row has a rule's tests on them.
+  type Col = (u64, Vec<u64>);
+  type Row = (usize, Vec<MatchTest>);
+
+  // An argument, or a field of it, identified by the constructor and field of each step
+  type Pos = (u64, Vec<(u64, u64)>);
+
+  // Tells if a test accepts a constructor, of the given arity, or a number, statically
+  fn accepts(test: &MatchTest, tag: u64, key: u64, arity: u64) -> bool {
+    match test {
+      MatchTest::Any | MatchTest::Value => true,
+      MatchTest::Hoas | MatchTest::HoasValue => tag != CTR || arity == 0 || (KIND_TERM_CT0 ..= KIND_TERM_F60).contains(&key),
+      MatchTest::Ctr(func, _) => tag == CTR && *func == key,
+      MatchTest::U60(numb) => tag == U60 && *numb == key,
+      MatchTest::F60(numb) => tag == F60 && *numb == key,
+    }
+  }
+
+  fn build(cols: &[Col], rows: Vec<Row>) -> MatchTree {
+    // The first rule left decides which column to test next, if any
+    let (index, tests) = match rows.first() {
+      Some(row) => row,
+      None => return MatchTree::Fail,
+    };
+    let col = match tests.iter().position(|test| *test != MatchTest::Any) {
+      Some(col) => col,
+      None => return MatchTree::Rule { index: *index },
+    };
+    let (arg, path) = cols[col].clone();
+
+    // Columns known to be constructors or numbers can only have HOAS_OPT's tests left
+    if tests[col] == MatchTest::HoasValue {
+      let then = rows.iter().cloned().map(|(index, mut tests)| {
+        if tests[col] == MatchTest::HoasValue {
+          tests[col] = MatchTest::Any;
+        }
+        (index, tests)
+      }).collect();
+      let other = rows.iter().filter(|(_, tests)| tests[col] != MatchTest::HoasValue).cloned().collect();
+      return MatchTree::Hoas { arg, path, then: Box::new(build(cols, then)), other: Box::new(build(cols, other)) };
+    }
+
+    // Otherwise, switches on the constructors and numbers the rules test this column for
+    let mut keys : Vec<(u64, u64, u64)> = Vec::new();
+    for (_, tests) in &rows {
+      let key = match &tests[col] {
+        MatchTest::Ctr(func, args) => (CTR, *func, args.len() as u64),
+        MatchTest::U60(numb) => (U60, *numb, 0),
+        MatchTest::F60(numb) => (F60, *numb, 0),
+        _ => continue,
+      };
+      if !keys.contains(&key) {
+        keys.push(key);
+      }
+    }
+    let mut ctrs = Vec::new();
+    let mut u60s = Vec::new();
+    let mut f60s = Vec::new();
+    for (tag, key, arity) in keys {
+      // The column is replaced by the constructor's fields, which the rules may test next
+      let fields : Vec<Col> = (0 .. arity).map(|field| (arg, [&path[..], &[field]].concat())).collect();
+      let cols = [&cols[.. col], &fields[..], &cols[col + 1 ..]].concat();
+      let rows = rows.iter().filter(|(_, tests)| accepts(&tests[col], tag, key, arity)).map(|(index, tests)| {
+        let args = match &tests[col] {
+          MatchTest::Ctr(_, args) => args.clone(),
+          _ => vec![MatchTest::Any; arity as usize],
+        };
+        (*index, [&tests[.. col], &args[..], &tests[col + 1 ..]].concat())
+      }).collect();
+      let tree = build(&cols, rows);
+      match tag {
+        CTR => ctrs.push((key, tree)),
+        U60 => u60s.push((key, tree)),
+        _ => f60s.push((key, tree)),
+      }
+    }
+    ctrs.sort_by_key(|(key, _)| *key);
+    u60s.sort_by_key(|(key, _)| *key);
+    f60s.sort_by_key(|(key, _)| *key);
+    let value = rows.iter().filter_map(|(index, tests)| {
+      let test = match tests[col] {
+        MatchTest::Any | MatchTest::Value => MatchTest::Any,
+        MatchTest::Hoas | MatchTest::HoasValue => MatchTest::HoasValue,
+        _ => return None,
+      };
+      let mut tests = tests.clone();
+      tests[col] = test;
+      Some((*index, tests))
+    }).collect();
+    let other = rows.iter().filter(|(_, tests)| tests[col] == MatchTest::Any).cloned().collect();
+    MatchTree::Switch {
+      arg,
+      path,
+      ctrs,
+      u60s,
+      f60s,
+      value: Box::new(build(cols, value)),
+      other: Box::new(build(cols, other)),
+    }
+  }
+
+  // Fields some rule tests are strict, like the arguments some rule tests
+  fn find_strict(cond: &RuleCond, pos: &Pos, strict: &mut HashSet<Pos>) {
+    if let RuleCond::Ctr { func, args } = cond {
+      for (field, arg) in args.iter().enumerate() {
+        let pos = (pos.0, [&pos.1[..], &[(*func, field as u64)]].concat());
+        if !matches!(arg, RuleCond::Var) {
+          strict.insert(pos.clone());
+        }
+        find_strict(arg, &pos, strict);
+      }
+    }
+  }
+
+  fn build_test(cond: &RuleCond, pos: &Pos, strict: &HashSet<Pos>, hoas: bool) -> MatchTest {
+    match cond {
+      RuleCond::U6O { numb } => MatchTest::U60(*numb),
+      RuleCond::F6O { numb } => MatchTest::F60(*numb),
+      RuleCond::Ctr { func, args } => MatchTest::Ctr(*func, args.iter().enumerate().map(|(field, arg)| {
+        build_test(arg, &(pos.0, [&pos.1[..], &[(*func, field as u64)]].concat()), strict, hoas)
+      }).collect()),
+      // If this is a strict argument, or field, then we're in a default variable
+      RuleCond::Var if strict.contains(pos) => {
+        if hoas { MatchTest::Hoas } else { MatchTest::Value }
+      }
+      RuleCond::Var => MatchTest::Any,
+    }
+  }
+
+  let mut strict = HashSet::new();
+  for (i, is_strict) in strict_map.iter().enumerate() {
+    if *is_strict {
+      strict.insert((i as u64, vec![]));
+    }
+  }
+  for rule in rules {
+    for (i, cond) in rule.cond.iter().enumerate() {
+      find_strict(cond, &(i as u64, vec![]), &mut strict);
+    }
+  }
+  let cols : Vec<Col> = (0 .. strict_map.len() as u64).map(|i| (i, vec![])).collect();
+  let rows = rules.iter().enumerate().map(|(r, rule)| {
+    let hoas = rule.hoas && r != rules.len() - 1;
+    let tests = rule.cond.iter().enumerate().map(|(i, cond)| build_test(cond, &(i as u64, vec![]), &strict, hoas)).collect();
+    (r, tests)
+  }).collect();
+  build(&cols, rows)
+}
+
+// A function implemented by the host, receiving its normalized arguments
+pub type NativeFun = Box<dyn Fn(&[language::syntax::Term]) -> language::syntax::Term + Send + Sync>;
+
+pub struct NativeObj {
+  pub func: NativeFun,
+  pub book: std::sync::Arc<language::rulebook::RuleBook>, // used to allocate the returned terms
 }
 
 pub enum Function {
@@ -75,39 +355,77 @@ pub enum Function {
     smap: Box<[bool]>,
     visit: VisitFun,
     apply: ApplyFun,
-  }
+  },
+  Native {
+    smap: Box<[bool]>,
+    visit: VisitObj,
+    apply: NativeObj,
+  },
+}
+
+// A function compiled ahead of time by `hvm compile`. Its code refers to the ids its rulebook gave
+// to each name, so it can only be installed on a program built from the same code.
+#[derive(Clone, Copy)]
+pub struct CompiledFunction {
+  pub id: u64,
+  pub name: &'static str,
+  pub smap: &'static [bool],
+  pub visit: VisitFun,
+  pub apply: ApplyFun,
+}
+
+// The entry points of a compiled function, called directly by the reducer
+#[derive(Clone, Copy)]
+pub struct FastFun {
+  pub visit: VisitFun,
+  pub apply: ApplyFun,
 }
 
 pub type Funs = U64Map<Function>;
 pub type Aris = U64Map<u64>;
 pub type Nams = U64Map<String>;
+pub type Fast = Vec<Option<FastFun>>;
 
 pub struct Program {
   pub funs: Funs,
   pub aris: Aris,
   pub nams: Nams,
+  // The compiled functions of `funs`, indexed by id, so that the reducer calls them without
+  // matching on a `Function`. Kept in sync by `insert_function`.
+  pub fast: Fast,
 }
 
 impl Program {
   pub fn new() -> Program {
-    let mut funs = U64Map::new();
-    let mut aris = U64Map::new();
-    let mut nams = U64Map::new();
+    let mut program = Program { funs: U64Map::new(), aris: U64Map::new(), nams: U64Map::new(), fast: Vec::new() };
     // Adds the built-in functions
     for fid in 0 .. crate::runtime::precomp::PRECOMP_COUNT as usize {
       if let Some(precomp) = PRECOMP.get(fid) {
         if let Some(fs) = &precomp.funs {
-          funs.insert(fid as u64, Function::Compiled {
+          program.insert_function(fid as u64, Function::Compiled {
             smap: precomp.smap.to_vec().into_boxed_slice(),
             visit: fs.visit,
             apply: fs.apply,
           });
         }
-        nams.insert(fid as u64, precomp.name.to_string());
-        aris.insert(fid as u64, precomp.smap.len() as u64);
+        program.nams.insert(fid as u64, precomp.name.to_string());
+        program.aris.insert(fid as u64, precomp.smap.len() as u64);
       }
     }
-    return Program { funs, aris, nams };
+    return program;
+  }
+
+  // Sets the function with the given id, replacing the previous one
+  pub fn insert_function(&mut self, fid: u64, function: Function) {
+    let fast = match &function {
+      Function::Compiled { visit, apply, .. } => Some(FastFun { visit: *visit, apply: *apply }),
+      _ => None,
+    };
+    if self.fast.len() <= fid as usize {
+      self.fast.resize(fid as usize + 1, None);
+    }
+    self.fast[fid as usize] = fast;
+    self.funs.insert(fid, function);
   }
 
   pub fn add_book(&mut self, book: &language::rulebook::RuleBook) {
@@ -116,7 +434,7 @@ impl Program {
     let aris : &mut Aris = &mut U64Map::new();
     for (fid, fun) in funs.data.drain(0..).enumerate() {
       if let Some(fun) = fun {
-        self.funs.insert(fid as u64, fun);
+        self.insert_function(fid as u64, fun);
       }
     }
     for (fid, nam) in nams.data.iter().enumerate() {
@@ -129,18 +447,39 @@ impl Program {
     }
   }
 
+  // Replaces the interpreted functions by their compiled versions
+  pub fn add_compiled(&mut self, book: &language::rulebook::RuleBook, functions: &[CompiledFunction]) -> Result<(), String> {
+    for function in functions {
+      if book.name_to_id.get(function.name) != Some(&function.id) || book.id_to_smap.get(&function.id).map(|smap| smap.len()) != Some(function.smap.len()) {
+        return Err(format!("The compiled function '{}' doesn't match the code it was compiled from. Is it outdated?", function.name));
+      }
+      self.insert_function(function.id, Function::Compiled {
+        smap: function.smap.to_vec().into_boxed_slice(),
+        visit: function.visit,
+        apply: function.apply,
+      });
+    }
+    Ok(())
+  }
+
   pub fn add_function(&mut self, name: String, function: Function) {
     self.nams.push(name);
-    self.funs.push(function);
+    self.insert_function(self.funs.data.len() as u64, function);
   }
 }
 
-pub fn get_var(heap: &Heap, term: Ptr, var: &RuleVar) -> Ptr {
-  let RuleVar { param, field, erase: _ } = var;
-  match field {
-    Some(i) => take_arg(heap, load_arg(heap, term, *param), *i),
-    None    => take_arg(heap, term, *param),
+// Returns the location of a param or, following `path`, of a field of the matched ctrs
+pub fn get_field_loc(heap: &Heap, term: Ptr, param: u64, path: &[u64]) -> u64 {
+  let mut loc = get_loc(term, param);
+  for field in path {
+    loc = get_loc(load_ptr(heap, loc), *field);
   }
+  loc
+}
+
+pub fn get_var(heap: &Heap, term: Ptr, var: &RuleVar) -> Ptr {
+  let RuleVar { param, path, erase: _ } = var;
+  take_ptr(heap, get_field_loc(heap, term, *param, path))
 }
 
 pub fn alloc_body(heap: &Heap, prog: &Program, tid: usize, term: Ptr, vars: &[RuleVar], body: &RuleBody) -> Ptr {
@@ -211,42 +550,49 @@ pub fn get_global_name_misc(name: &str) -> Option<u64> {
 // todo: "dups" still needs to be moved out on `alloc_body` etc.
 pub fn build_function(book: &language::rulebook::RuleBook, fn_name: &str, rules: &[language::syntax::Rule]) -> Function {
   let hoas = fn_name.starts_with("F$");
-  let dynrules = rules.iter().filter_map(|rule| {
-    if let language::syntax::Term::Ctr { ref name, ref args } = *rule.lhs {
-      let mut cond = Vec::new();
+  // Builds the condition of a param or field, collecting its variables and matched ctrs
+  fn build_cond(
+    book : &language::rulebook::RuleBook,
+    term : &language::syntax::Term,
+    param: u64,
+    path : &[u64],
+    vars : &mut Vec<RuleVar>,
+    inps : &mut Vec<String>,
+    free : &mut Vec<(u64, Vec<u64>, u64)>,
+  ) -> RuleCond {
+    match term {
+      language::syntax::Term::Ctr { name, args } => {
+        let args = args.iter().enumerate().map(|(j, arg)| {
+          build_cond(book, arg, param, &[path, &[j as u64]].concat(), vars, inps, free)
+        }).collect::<Vec<RuleCond>>();
+        free.push((param, path.to_vec(), args.len() as u64));
+        RuleCond::Ctr { func: *book.name_to_id.get(&*name).unwrap_or(&0), args }
+      }
+      language::syntax::Term::U6O { numb } => {
+        RuleCond::U6O { numb: *numb }
+      }
+      language::syntax::Term::F6O { numb } => {
+        RuleCond::F6O { numb: *numb }
+      }
+      language::syntax::Term::Var { name } => {
+        vars.push(RuleVar { param, path: path.to_vec(), erase: name == "*" });
+        inps.push(name.clone());
+        RuleCond::Var
+      }
+      _ => {
+        panic!("invalid left-hand side.");
+      }
+    }
+  }
+
+  let dynrules : Vec<Rule> = rules.iter().filter_map(|rule| {
+    if let language::syntax::Term::Ctr { ref args, .. } = *rule.lhs {
       let mut vars = Vec::new();
       let mut inps = Vec::new();
       let mut free = Vec::new();
-      for (i, arg) in args.iter().enumerate() {
-        match &**arg {
-          language::syntax::Term::Ctr { name, args } => {
-            cond.push(Ctr(*book.name_to_id.get(&*name).unwrap_or(&0), 0));
-            free.push((i as u64, args.len() as u64));
-            for (j, arg) in args.iter().enumerate() {
-              if let language::syntax::Term::Var { ref name } = **arg {
-                vars.push(RuleVar { param: i as u64, field: Some(j as u64), erase: name == "*" });
-                inps.push(name.clone());
-              } else {
-                panic!("sorry, left-hand sides can't have nested constructors yet.");
-              }
-            }
-          }
-          language::syntax::Term::U6O { numb } => {
-            cond.push(U6O(*numb as u64));
-          }
-          language::syntax::Term::F6O { numb } => {
-            cond.push(F6O(*numb as u64));
-          }
-          language::syntax::Term::Var { name } => {
-            cond.push(Var(0));
-            vars.push(RuleVar { param: i as u64, field: None, erase: name == "*" });
-            inps.push(name.clone());
-          }
-          _ => {
-            panic!("invalid left-hand side.");
-          }
-        }
-      }
+      let cond = args.iter().enumerate().map(|(i, arg)| {
+        build_cond(book, arg, i as u64, &[], &mut vars, &mut inps, &mut free)
+      }).collect();
 
       let core = term_to_core(book, &rule.rhs, &inps);
       let body = build_body(&core, vars.len() as u64);
@@ -268,10 +614,18 @@ pub fn build_function(book: &language::rulebook::RuleBook, fn_name: &str, rules:
     }
   }
 
+  // Inferred strict arguments are reduced before the call, but matched like lazy ones, as the rules
+  // don't require them to be constructors or numbers
+  let inferred = book.id_to_inferred.get(fnid);
+  let match_map : Vec<bool> = strict_map.iter().enumerate().map(|(i, is_strict)| {
+    *is_strict && !inferred.is_some_and(|inferred| inferred[i])
+  }).collect();
+  let tree = build_match_tree(&dynrules, &match_map);
+
   Function::Interpreted {
     smap,
     visit: VisitObj { strict_map, strict_idx },
-    apply: ApplyObj { rules: dynrules },
+    apply: ApplyObj { rules: dynrules, tree },
   }
 }
 
@@ -600,3 +954,14 @@ pub fn make_string(heap: &Heap, tid: usize, text: &str) -> Ptr {
   }
   return term;
 }
+
+pub fn make_list(heap: &Heap, tid: usize, items: &[Ptr]) -> Ptr {
+  let mut list = Ctr(LIST_NIL, 0);
+  for item in items.iter().rev() {
+    let ctr0 = alloc(heap, tid, 2);
+    link(heap, ctr0 + 0, *item);
+    link(heap, ctr0 + 1, list);
+    list = Ctr(LIST_CONS, ctr0);
+  }
+  list
+}
@@ -32,6 +32,7 @@ pub struct ReduceCtx<'a> {
 //     steal { ... }
 //   }
 
+#[inline]
 pub fn is_whnf(term: Ptr) -> bool {
   match get_tag(term) {
     ERA => true,
@@ -44,31 +45,106 @@ pub fn is_whnf(term: Ptr) -> bool {
   }
 }
 
+/// why an evaluation stopped before reaching its normal form.
+///
+/// a rewrite raises it with [`fail`], which unwinds through the reducer's threads up to the
+/// code which started the evaluation, where [`catch_failure`] returns it. the methods of
+/// [`Runtime`](crate::runtime::Runtime) panic with the message of an `Error` instead, so only
+/// an `Exit` reaches their caller this way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Failure {
+  /// a builtin or host function was given arguments it can't handle, with the message saying which
+  Error(String),
+  /// the program called `HVM.exit` with this code
+  Exit(i32),
+}
+
+impl std::fmt::Display for Failure {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Failure::Error(message) => write!(f, "{}", message),
+      Failure::Exit(code) => write!(f, "Exited with code {}.", code),
+    }
+  }
+}
+
+/// stops the evaluation the current rewrite is part of.
+pub fn fail(failure: Failure) -> ! {
+  std::panic::resume_unwind(Box::new(failure))
+}
+
+/// runs `eval`, returning the [`Failure`] which stopped an evaluation it made, if one did.
+/// other panics keep unwinding.
+pub fn catch_failure<T>(eval: impl FnOnce() -> T) -> Result<T, Failure> {
+  match std::panic::catch_unwind(std::panic::AssertUnwindSafe(eval)) {
+    Ok(done) => Ok(done),
+    Err(payload) => match payload.downcast::<Failure>() {
+      Ok(failure) => Err(*failure),
+      Err(payload) => std::panic::resume_unwind(payload),
+    },
+  }
+}
+
 pub fn reduce(heap: &Heap, prog: &Program, tids: &[usize], root: u64, full: bool, debug: bool) -> Ptr {
+  reduce_within_limit(heap, prog, tids, root, full, debug);
+  load_ptr(heap, root)
+}
+
+// Reduces like `reduce`, returning `None` if the limit set on the threads halted the reduction
+// before it was done. Exceeding the limit afterwards, as cancelling does, doesn't count.
+pub fn reduce_within_limit(heap: &Heap, prog: &Program, tids: &[usize], root: u64, full: bool, debug: bool) -> Option<Ptr> {
   // Halting flag
   let stop = &AtomicUsize::new(1);
+  let halt = &AtomicBool::new(false);
   let barr = &Barrier::new(tids.len());
   let locs = &tids.iter().map(|x| AtomicU64::new(u64::MAX)).collect::<Vec<AtomicU64>>();
 
+  // The first failure or panic of a thread, which halts the others, like exceeding the limit does
+  let failure = &std::sync::Mutex::new(None);
+  let limits : Vec<u64> = tids.iter().map(|tid| heap.lvar[*tid].limit.load(Ordering::Relaxed)).collect();
+
   // Spawn a thread for each worker
   std::thread::scope(|s| {
     for tid in tids {
       s.spawn(move || {
-        reducer(heap, prog, tids, stop, barr, locs, root, *tid, full, debug);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+          reducer(heap, prog, tids, stop, halt, barr, locs, root, *tid, full, debug);
+        }));
+        if let Err(payload) = result {
+          set_limit(heap, tids, 0);
+          stop.store(0, Ordering::Relaxed);
+          failure.lock().unwrap().get_or_insert(payload);
+        }
         //println!("[{}] done", tid);
       });
     }
   });
 
+  // Resumes it on the calling thread, once the threads are reset for the next reduction
+  if let Some(payload) = failure.lock().unwrap().take() {
+    for (tid, limit) in tids.iter().zip(limits) {
+      heap.lvar[*tid].limit.store(limit, Ordering::Relaxed);
+      heap.vstk[*tid].clear();
+    }
+    std::panic::resume_unwind(payload);
+  }
+
   // Return whnf term ptr
-  return load_ptr(heap, root);
+  if halt.load(Ordering::Relaxed) {
+    return None;
+  }
+  return Some(load_ptr(heap, root));
 }
 
+// Visits a thread makes between checks of the rewrite limit, as they sum the cost of every thread
+const VISIT_LIMIT_CHECK : u64 = 1024;
+
 pub fn reducer(
   heap: &Heap,
   prog: &Program,
   tids: &[usize],
   stop: &AtomicUsize,
+  halt: &AtomicBool,
   barr: &Barrier,
   locs: &[AtomicU64],
   root: u64,
@@ -85,16 +161,18 @@ pub fn reducer(
   let seen  = &mut HashSet::new();
 
   // State Vars
+  let mut until_check : u64 = 0;
   let (mut cont, mut host) = if tid == tids[0] {
     (REDEX_CONT_RET, root)
   } else {
     (0, u64::MAX)
   };
 
-  // Debug Printer
+  // Debug Printer, which shows the location of each thread, in the order of `tids`
+  let slot = tids.iter().position(|x| *x == tid).unwrap();
   let print = |tid: usize, host: u64| {
     barr.wait(stop);
-    locs[tid].store(host, Ordering::SeqCst);
+    locs[slot].store(host, Ordering::SeqCst);
     barr.wait(stop);
     if tid == tids[0] {
       println!("{}\n----------------", show_at(heap, prog, root, locs));
@@ -110,6 +188,15 @@ pub fn reducer(
       }
       'work: loop {
         'visit: loop {
+          if until_check == 0 {
+            until_check = VISIT_LIMIT_CHECK;
+            if exceeded_limit(heap, tid, tids) {
+              halt.store(true, Ordering::Relaxed);
+              stop.store(0, Ordering::Relaxed);
+              break 'main;
+            }
+          }
+          until_check -= 1;
           let term = load_ptr(heap, host);
           if debug {
             print(tid, host);
@@ -151,9 +238,16 @@ pub fn reducer(
             }
             FUN | CTR => {
               let fid = get_ext(term);
-//[[CODEGEN:FAST-VISIT]]//
+              // Compiled functions are called directly, from their index
+              if let Some(Some(fast)) = prog.fast.get(fid as usize) {
+                if (fast.visit)(ReduceCtx { heap, prog, tid, hold, term, visit, redex, cont: &mut cont, host: &mut host }) {
+                  continue 'visit;
+                } else {
+                  break 'visit;
+                }
+              }
               match &prog.funs.get(&fid) {
-                Some(Function::Interpreted { smap: fn_smap, visit: fn_visit, apply: fn_apply }) => {
+                Some(Function::Interpreted { smap: fn_smap, visit: fn_visit, .. }) | Some(Function::Native { smap: fn_smap, visit: fn_visit, .. }) => {
                   if fun::visit(ReduceCtx { heap, prog, tid, hold, term, visit, redex, cont: &mut cont, host: &mut host }, &fn_visit.strict_idx) {
                     continue 'visit;
                   } else {
@@ -180,6 +274,8 @@ pub fn reducer(
         'call: loop {
           'apply: loop {
             let term = load_ptr(heap, host);
+            #[cfg(feature = "observer")]
+            observe_redex(heap, tid, term);
             if debug {
               print(tid, host);
             }
@@ -210,7 +306,13 @@ pub fn reducer(
               }
               FUN | CTR => {
                 let fid = get_ext(term);
-//[[CODEGEN:FAST-APPLY]]//
+                if let Some(Some(fast)) = prog.fast.get(fid as usize) {
+                  if (fast.apply)(ReduceCtx { heap, prog, tid, hold, term, visit, redex, cont: &mut cont, host: &mut host }) {
+                    continue 'work;
+                  } else {
+                    break 'apply;
+                  }
+                }
                 match &prog.funs.get(&fid) {
                   Some(Function::Interpreted { smap: fn_smap, visit: fn_visit, apply: fn_apply }) => {
                     if fun::apply(ReduceCtx { heap, prog, tid, hold, term, visit, redex, cont: &mut cont, host: &mut host }, fid, fn_visit, fn_apply) {
@@ -226,6 +328,13 @@ pub fn reducer(
                       break 'apply;
                     }
                   }
+                  Some(Function::Native { smap: fn_smap, visit: fn_visit, apply: fn_apply }) => {
+                    if native::apply(ReduceCtx { heap, prog, tid, hold, term, visit, redex, cont: &mut cont, host: &mut host }, fn_apply) {
+                      continue 'work;
+                    } else {
+                      break 'apply;
+                    }
+                  }
                   None => {
                     break 'apply;
                   }
@@ -312,10 +421,15 @@ pub fn reducer(
       if stop.load(Ordering::Relaxed) == 0 {
         //println!("[{}] stop", tid);
         break 'main;
+      } else if exceeded_limit(heap, tid, tids) {
+        halt.store(true, Ordering::Relaxed);
+        break 'main;
       } else {
         for victim_tid in tids {
           if *victim_tid != tid {
             if let Some((new_cont, new_host)) = heap.vstk[*victim_tid].steal() {
+              #[cfg(feature = "observer")]
+              observe_steal(heap, tid, *victim_tid);
               cont = new_cont;
               host = new_host;
               //println!("stolen");
@@ -331,10 +445,11 @@ pub fn reducer(
 }
 
 pub fn normalize(heap: &Heap, prog: &Program, tids: &[usize], host: u64, debug: bool) -> Ptr {
-  let mut cost = get_cost(heap);
+  // Only counts the rewrites made by these threads, since others may be running other reductions
+  let mut cost = get_tids_cost(heap, tids);
   loop {
     reduce(heap, prog, tids, host, true, debug);
-    let new_cost = get_cost(heap);
+    let new_cost = get_tids_cost(heap, tids);
     if new_cost != cost {
       cost = new_cost;
     } else {
@@ -344,6 +459,27 @@ pub fn normalize(heap: &Heap, prog: &Program, tids: &[usize], host: u64, debug:
   load_ptr(heap, host)
 }
 
+// Normalizes a term from inside a rewrite rule, while this thread is in the middle of another
+// reduction. The visits that reduction left pending are put aside meanwhile, since the nested
+// reduction would otherwise take them as its own.
+pub fn normalize_nested(heap: &Heap, prog: &Program, tid: usize, host: u64) -> Ptr {
+  set_visits_aside(heap, tid, || normalize(heap, prog, &[tid], host, false))
+}
+
+fn set_visits_aside(heap: &Heap, tid: usize, reduce: impl FnOnce() -> Ptr) -> Ptr {
+  let visit = &heap.vstk[tid];
+  let init = visit.init.swap(0, Ordering::Relaxed);
+  let last = visit.last.swap(0, Ordering::Relaxed);
+  let pending : Vec<u64> = visit.data[0 .. last].iter().map(|x| x.swap(0, Ordering::Relaxed)).collect();
+  let done = reduce();
+  for (slot, value) in visit.data.iter().zip(pending) {
+    slot.store(value, Ordering::Relaxed);
+  }
+  visit.last.store(last, Ordering::Relaxed);
+  visit.init.store(init, Ordering::Relaxed);
+  done
+}
+
 //pub fn normal(heap: &Heap, prog: &Program, tids: &[usize], host: u64, seen: &mut im::HashSet<u64>, debug: bool) -> Ptr {
   //let term = load_ptr(heap, host);
   //if seen.contains(&host) {
@@ -19,6 +19,7 @@ pub struct RedexBag {
   data: Box<[AtomicU64]>,
 }
 
+#[inline]
 pub fn new_redex(host: u64, cont: u64, left: u64) -> Redex {
   return (host << 32) | (cont << 6) | left;
 }
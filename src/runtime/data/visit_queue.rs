@@ -17,6 +17,7 @@ pub struct VisitQueue {
   pub data: Box<[AtomicU64]>,
 }
 
+#[inline]
 pub fn new_visit(host: u64, hold: bool, cont: u64) -> Visit {
   return (host << 32) | (if hold { 0x80000000 } else { 0 }) | cont;
 }
@@ -48,6 +49,15 @@ impl VisitQueue {
     unsafe { self.data.get_unchecked(index) }.store(value, Ordering::Relaxed);
   }
 
+  // Drops the visits left behind by a reduction that halted early
+  pub fn clear(&self) {
+    let last = self.last.swap(0, Ordering::Relaxed);
+    for visit in &self.data[0 .. last] {
+      visit.store(0, Ordering::Relaxed);
+    }
+    self.init.store(0, Ordering::Relaxed);
+  }
+
   #[inline(always)]
   pub fn pop(&self) -> Option<(u64, u64)> {
     loop {
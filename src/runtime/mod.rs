@@ -37,32 +37,48 @@ pub fn default_heap_tids() -> usize {
 
 /// a builder for Runtime to determine its configuration
 pub struct RuntimeBuilder {
+    compiled_rules: Vec<language::syntax::Rule>,
     rules: Vec<language::syntax::Rule>,
     strictness_maps: HashMap<String, Vec<bool>>,
+    lazy_functions: Vec<String>,
     functions: HashMap<String, Function>,
+    natives: Vec<(String, usize, NativeFun)>,
+    compiled: Vec<CompiledFunction>,
     thread_count: usize,
+    evaluation_thread_count: Option<usize>,
     heap_size: usize,
+    optimizations: Optimizations,
     debug: bool,
+    #[cfg(feature = "observer")]
+    observer: Option<std::sync::Arc<dyn ReductionObserver>>,
 }
 
 /// the runtime which evaluates the HVM code
 pub struct Runtime {
     heap: Heap,
     program: Program,
-    book: language::rulebook::RuleBook,
-    thread_ids: Box<[usize]>,
+    book: std::sync::Arc<language::rulebook::RuleBook>,
+    workers: Workers,
     debug: bool,
 }
 
 impl Default for RuntimeBuilder {
     fn default() -> Self {
         Self {
+            compiled_rules: Default::default(),
             rules: Default::default(),
             strictness_maps: Default::default(),
+            lazy_functions: Default::default(),
             functions: Default::default(),
+            natives: Default::default(),
+            compiled: Default::default(),
             thread_count: default_heap_tids(),
+            evaluation_thread_count: None,
             heap_size: default_heap_size(),
+            optimizations: Optimizations::default(),
             debug: false,
+            #[cfg(feature = "observer")]
+            observer: None,
         }
     }
 }
@@ -90,15 +106,27 @@ impl RuntimeBuilder {
         self
     }
 
+    /// disables strictness inference on a function, like a `~` before its rules does.
+    ///
+    /// by default, arguments which every rule of a function reduces anyway are made strict,
+    /// so that they aren't allocated as thunks. lazy functions only get the strictness
+    /// of their patterns and annotations.
+    pub fn add_lazy_function(mut self, name: String) -> Self {
+        self.lazy_functions.push(name);
+        self
+    }
+
     /// adds the rules written in the given HVM source code.
     ///
-    /// returns the error message if the code failed to parse.
+    /// returns the error message if the code failed to parse, or defines a native function.
     pub fn add_code(mut self, code: &str) -> Result<Self, String> {
         let file = language::syntax::read_file(code)?;
+        self.check_natives(&file.rules)?;
         self.rules.extend(file.rules);
         for (name, smap) in file.smaps {
             self.strictness_maps.insert(name, smap);
         }
+        self.lazy_functions.extend(file.lazy);
         Ok(self)
     }
 
@@ -111,12 +139,91 @@ impl RuntimeBuilder {
         self
     }
 
+    /// add a function implemented by the given closure, which will be mapped to the given symbol.
+    ///
+    /// all arguments of the function are strict: they are normalized and read back before being
+    /// passed to the closure, and the term it returns is allocated in place of the call.
+    /// constructors in the returned term must also appear in the program.
+    ///
+    /// this allows exposing host services, such as database lookups, to HVM code.
+    ///
+    /// returns the error message if the name is already taken, by a builtin, another native
+    /// function, or the rules added so far. rules added later, other than through
+    /// [`RuntimeBuilder::add_code`], which checks it too, must not define it either.
+    pub fn add_native<F>(mut self, name: String, arity: usize, func: F) -> Result<Self, String>
+    where F: Fn(&[language::syntax::Term]) -> language::syntax::Term + Send + Sync + 'static {
+        if PRECOMP.iter().any(|precomp| precomp.name == name) {
+            return Err(format!("native function '{}' is already a builtin", name));
+        }
+        if self.natives.iter().any(|(other, _, _)| *other == name) {
+            return Err(format!("native function '{}' is already defined", name));
+        }
+        self.natives.push((name, arity, Box::new(func)));
+        self.check_natives(&self.compiled_rules)?;
+        self.check_natives(&self.rules)?;
+        Ok(self)
+    }
+
+    // Reports rules defining one of the native functions
+    fn check_natives(&self, rules: &[language::syntax::Rule]) -> Result<(), String> {
+        for rule in rules {
+            if let language::syntax::Term::Ctr { ref name, .. } = *rule.lhs {
+                if self.natives.iter().any(|(native, _, _)| native == name) {
+                    return Err(format!("native function '{}' is already defined by rules", name));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// installs functions compiled ahead of time by `hvm compile`, in place of the rules they
+    /// were compiled from.
+    ///
+    /// the code they were compiled from must be added to the builder first, unchanged,
+    /// otherwise [`RuntimeBuilder::build`] panics. prefer [`RuntimeBuilder::add_compiled_code`],
+    /// which doesn't depend on what else was added.
+    pub fn add_compiled(mut self, functions: &[CompiledFunction]) -> Self {
+        self.compiled.extend_from_slice(functions);
+        self
+    }
+
+    /// adds the HVM source code functions were compiled from, along with them, in place of
+    /// its rules.
+    ///
+    /// it can be called at any point, before or after other code is added, as its rules come
+    /// first on the built runtime, which gives their names the ids they were compiled with.
+    /// so, only one compiled code can be added to a builder.
+    ///
+    /// returns the error message if the code failed to parse, or defines a native function.
+    pub fn add_compiled_code(mut self, code: &str, functions: &[CompiledFunction]) -> Result<Self, String> {
+        let file = language::syntax::read_file(code)?;
+        self.check_natives(&file.rules)?;
+        self.compiled_rules.extend(file.rules);
+        for (name, smap) in file.smaps {
+            self.strictness_maps.insert(name, smap);
+        }
+        self.lazy_functions.extend(file.lazy);
+        self.compiled.extend_from_slice(functions);
+        Ok(self)
+    }
+
     /// sets the number of threads that will be used to reduce terms given to the runtime.
     pub fn set_thread_count(mut self, thread_count: usize) -> Self {
         self.thread_count = thread_count;
         self
     }
 
+    /// sets the number of threads used by each evaluation, out of those given by
+    /// [`RuntimeBuilder::set_thread_count`], which is also the default.
+    ///
+    /// the runtime can be shared between threads, and evaluations made at the same time
+    /// each take their own subset of its threads, waiting if there aren't enough left.
+    /// so, lowering it allows running several evaluations concurrently.
+    pub fn set_evaluation_thread_count(mut self, evaluation_thread_count: usize) -> Self {
+        self.evaluation_thread_count = Some(evaluation_thread_count);
+        self
+    }
+
     /// sets the size of the heap which stores the terms evaluated by the runtime,
     /// given in the number of terms that can be stored on the heap.
     ///
@@ -127,6 +234,15 @@ impl RuntimeBuilder {
         self
     }
 
+    /// sets which optimizations are run on the rules before they're evaluated,
+    /// such as [`Optimizations::level`] gives for `-O0`, `-O1` and `-O2`. none are by default.
+    ///
+    /// they don't change the results, but lower the number of graph rewrites evaluations take.
+    pub fn set_optimizations(mut self, optimizations: Optimizations) -> Self {
+        self.optimizations = optimizations;
+        self
+    }
+
     /// causes evaluation of terms to print debug output,
     /// showing how the given term was reduced step by step.
     pub fn set_debug(mut self, debug: bool) -> Self {
@@ -134,36 +250,82 @@ impl RuntimeBuilder {
         self
     }
 
+    /// registers an observer, which is told about every rewrite, allocation, free and steal.
+    ///
+    /// only available with the `observer` feature, so that runtimes without one pay nothing.
+    #[cfg(feature = "observer")]
+    pub fn set_observer(mut self, observer: std::sync::Arc<dyn ReductionObserver>) -> Self {
+        self.observer = Some(observer);
+        self
+    }
+
     /// builds a runtime with the configuration given to the builder.
     pub fn build(self) -> Runtime {
+        // The compiled code's rules come first, so that its names get the same ids as it had alone
         let file = language::syntax::File {
-            rules: self.rules,
+            rules: self.compiled_rules.into_iter().chain(self.rules).collect(),
             smaps: self.strictness_maps.into_iter().collect(),
+            lazy: self.lazy_functions,
         };
 
         // Converts the file to a Rulebook
-        let book = language::rulebook::gen_rulebook(&file);
+        let mut book = language::rulebook::gen_rulebook(&file);
+
+        // Registers the native functions, before the rules referring to them are built
+        let natives: Vec<_> = self.natives.into_iter().map(|(name, arity, func)| {
+            match language::rulebook::add_native(&mut book, &name, arity) {
+                Ok(id) => (id, arity, func),
+                Err(err) => panic!("{}", err),
+            }
+        }).collect();
+        let book = std::sync::Arc::new(book);
 
         // Creates the runtime program
         let mut program = Program::new();
 
         // Adds the interpreted functions (from the Rulebook)
         program.add_book(&book);
+        optimize(&mut program.funs, &self.optimizations);
+
+        // Adds the compiled functions
+        if let Err(err) = program.add_compiled(&book, &self.compiled) {
+            panic!("{}", err);
+        }
 
         // Adds the extra functions
         for (name, fun) in self.functions {
             program.add_function(name, fun);
         }
 
+        // Adds the native functions
+        for (id, arity, func) in natives {
+            let smap = vec![true; arity];
+            program.insert_function(id, Function::Native {
+                smap: smap.clone().into_boxed_slice(),
+                visit: VisitObj { strict_map: smap, strict_idx: (0 .. arity as u64).collect() },
+                apply: NativeObj { func, book: book.clone() },
+            });
+        }
+
         // Creates the runtime heap
-        let heap = new_heap(self.heap_size, self.thread_count);
-        let thread_ids = new_tids(self.thread_count);
+        #[allow(unused_mut)]
+        let mut heap = new_heap(self.heap_size, self.thread_count);
+        #[cfg(feature = "observer")]
+        {
+            heap.observer = self.observer;
+        }
+        let evaluation_thread_count = self.evaluation_thread_count.unwrap_or(self.thread_count).clamp(1, self.thread_count);
+        let workers = Workers {
+            free: std::sync::Mutex::new(new_tids(self.thread_count).into_vec()),
+            freed: std::sync::Condvar::new(),
+            per_evaluation: evaluation_thread_count,
+        };
 
         Runtime {
             heap,
             program,
             book,
-            thread_ids,
+            workers,
             debug: self.debug,
         }
     }
@@ -175,24 +337,186 @@ impl Runtime {
     /// this means that applications in the term are evaluated,
     /// until there are no more applications in the term.
     pub fn normalize_term(&self, term: &language::syntax::Term) -> language::syntax::Term {
-        let tid = 0;
+        self.normalize_term_as(term, language::readback::ReadbackMode::Tree)
+    }
+
+    /// reduces the given term to Normal Form, like [`Runtime::normalize_term`],
+    /// reading the result back with the given strategy.
+    ///
+    /// use [`ReadbackMode::Shared`](language::readback::ReadbackMode::Shared)
+    /// when the result has a lot of sharing, which would otherwise be expanded exponentially.
+    pub fn normalize_term_as(&self, term: &language::syntax::Term, mode: language::readback::ReadbackMode) -> language::syntax::Term {
+        self.normalize_term_with(term, &language::readback::ReadbackOptions { mode, ..Default::default() })
+    }
+
+    /// reduces the given term to Normal Form, like [`Runtime::normalize_term`],
+    /// reading the result back with the given options.
+    ///
+    /// parts of the result beyond `max_depth` or `max_nodes` are replaced by `...`,
+    /// which makes it safe to inspect huge or infinite results.
+    pub fn normalize_term_with(&self, term: &language::syntax::Term, options: &language::readback::ReadbackOptions) -> language::syntax::Term {
+        let workers = self.workers.acquire();
+        let tid = workers.tids[0];
 
         let host = alloc_term(&self.heap, &self.program, tid, &self.book, term);
-        let ptr = reduce(
+        let ptr = evaluate(|| reduce(
             &self.heap,
             &self.program,
-            &self.thread_ids,
+            &workers.tids,
             host,
             true,
             self.debug,
-        );
+        ));
 
-        let output = language::readback::as_term(&self.heap, &self.program, host);
+        let output = language::readback::as_term_with(&self.heap, &self.program, host, options);
 
         collect(&self.heap, &self.program.aris, tid, ptr);
         *output
     }
 
+    /// runs the given term as an IO action, performing its effects in order,
+    /// reading from stdin and writing to stdout, and returns the value it finishes with.
+    ///
+    /// the actions are `IO.done`, `IO.do_input`, `IO.do_output`, `IO.do_store` and `IO.do_load`.
+    pub fn run_io(&self, term: &language::syntax::Term) -> Result<language::syntax::Term, String> {
+        self.run_io_with(term, &mut std::io::stdin().lock(), &mut std::io::stdout())
+    }
+
+    /// runs the given term as an IO action, like [`Runtime::run_io`],
+    /// reading and writing from the given streams instead.
+    pub fn run_io_with(&self, term: &language::syntax::Term, input: &mut dyn std::io::BufRead, output: &mut dyn std::io::Write) -> Result<language::syntax::Term, String> {
+        let workers = self.workers.acquire();
+        let tid = workers.tids[0];
+
+        let host = alloc_term(&self.heap, &self.program, tid, &self.book, term);
+        let result = evaluate(|| run_io(&self.heap, &self.program, &workers.tids, host, input, output, self.debug))
+            .map(|_| *language::readback::as_term(&self.heap, &self.program, host));
+
+        collect(&self.heap, &self.program.aris, tid, load_ptr(&self.heap, host));
+        result
+    }
+
+    /// reduces the given term to Normal Form, like [`Runtime::normalize_term`],
+    /// giving up once it takes more than `max_rewrites` graph rewrites.
+    ///
+    /// returns `None` if the limit was exceeded. since the term is then left half-reduced,
+    /// the heap is replaced by an empty one, which is why this borrows the runtime mutably.
+    pub fn normalize_term_limited(&mut self, term: &language::syntax::Term, max_rewrites: u64) -> Option<language::syntax::Term> {
+        let workers = self.workers.acquire();
+        let tid = workers.tids[0];
+
+        let cost = get_tids_cost(&self.heap, &workers.tids);
+        set_limit(&self.heap, &workers.tids, cost.saturating_add(max_rewrites));
+        let host = alloc_term(&self.heap, &self.program, tid, &self.book, term);
+        let ptr = evaluate(|| reduce(
+            &self.heap,
+            &self.program,
+            &workers.tids,
+            host,
+            true,
+            self.debug,
+        ));
+
+        let exceeded = exceeded_limit(&self.heap, tid, &workers.tids);
+        set_limit(&self.heap, &workers.tids, u64::MAX);
+        if exceeded {
+            // Keeps the rewrite counts, so that they still cover the runtime's whole life
+            #[allow(unused_mut)]
+            let mut heap = new_heap(self.heap.node.len(), self.heap.tids);
+            #[cfg(feature = "observer")]
+            {
+                heap.observer = self.heap.observer.take();
+            }
+            for (new, old) in heap.lvar.iter().zip(self.heap.lvar.iter()) {
+                new.cost.store(old.cost.load(std::sync::atomic::Ordering::Relaxed), std::sync::atomic::Ordering::Relaxed);
+            }
+            self.heap = heap;
+            return None;
+        }
+
+        let output = language::readback::as_term(&self.heap, &self.program, host);
+
+        collect(&self.heap, &self.program.aris, tid, ptr);
+        Some(*output)
+    }
+
+    /// starts reducing the given term to Normal Form, like [`Runtime::normalize_term`],
+    /// on a background thread, returning a handle to poll, cancel or wait for it.
+    pub fn spawn_normalize(self: &std::sync::Arc<Self>, term: &language::syntax::Term) -> EvalHandle {
+        let state = std::sync::Arc::new(std::sync::Mutex::new(EvalState { tids: None, start: 0, progress: 0, cancelled: false }));
+        let thread = {
+            let runtime = self.clone();
+            let state = state.clone();
+            let term = term.clone();
+            std::thread::spawn(move || runtime.normalize_in_background(&term, &state))
+        };
+        EvalHandle { runtime: self.clone(), state, thread }
+    }
+
+    fn normalize_in_background(&self, term: &language::syntax::Term, state: &std::sync::Mutex<EvalState>) -> Result<language::syntax::Term, EvalError> {
+        let workers = self.workers.acquire();
+        let tid = workers.tids[0];
+        let _reset = BackgroundReset { heap: &self.heap, tids: &workers.tids, state };
+
+        let host = alloc_term(&self.heap, &self.program, tid, &self.book, term);
+        {
+            let mut state = state.lock().unwrap();
+            if state.cancelled {
+                collect(&self.heap, &self.program.aris, tid, load_ptr(&self.heap, host));
+                return Err(EvalError::Cancelled);
+            }
+            state.start = get_tids_cost(&self.heap, &workers.tids);
+            state.tids = Some(workers.tids.clone());
+        }
+        let ptr = evaluate(|| reduce_within_limit(
+            &self.heap,
+            &self.program,
+            &workers.tids,
+            host,
+            true,
+            self.debug,
+        ));
+
+        {
+            // From now on, cancelling doesn't touch the threads, which will be given to others
+            let mut state = state.lock().unwrap();
+            state.progress = get_tids_cost(&self.heap, &workers.tids) - state.start;
+            state.tids = None;
+        }
+        // Only a reduction halted by cancelling is dropped, as one which was done before keeps
+        // its result, even though cancelling then set the limit
+        let ptr = match ptr {
+            Some(ptr) => ptr,
+            None => {
+                // The half-reduced term can't be collected, since its dup nodes may still be
+                // locked, so its memory is leaked; the threads are reset by `BackgroundReset`
+                return Err(EvalError::Cancelled);
+            }
+        };
+
+        let output = language::readback::as_term(&self.heap, &self.program, host);
+
+        collect(&self.heap, &self.program.aris, tid, ptr);
+        Ok(*output)
+    }
+
+    /// reduces the given term to Normal Form, and enumerates the branches of its superpositions.
+    ///
+    /// branches are read back lazily, depth-first, taking the first side of a superposition
+    /// before the second; superpositions with the same label always take the same side.
+    /// so, for example, `(Pair {1 2} {3 4})` yields `(Pair 1 3)`, `(Pair 1 4)`, `(Pair 2 3)`
+    /// and `(Pair 2 4)`, in this order.
+    pub fn collapse_term(&self, term: &language::syntax::Term) -> Collapse<'_> {
+        let workers = self.workers.acquire();
+        let tid = workers.tids[0];
+
+        let host = alloc_term(&self.heap, &self.program, tid, &self.book, term);
+        evaluate(|| normalize(&self.heap, &self.program, &workers.tids, host, self.debug));
+
+        let branches = language::readback::collapse(&self.heap, &self.program, tid, host);
+        Collapse { runtime: self, host, branches }
+    }
+
     /// attempts to evaluate the given term to the target type if possible.
     ///
     /// this is done by normalizing the term, and then attempting to convert the output.
@@ -202,6 +526,27 @@ impl Runtime {
         self.normalize_term(term).try_into()
     }
 
+    /// calls the function `name` with the given arguments,
+    /// and attempts to convert its Normal Form to the target type.
+    ///
+    /// arguments are given as a tuple, such as `runtime.call("Fib", (8u64,))`.
+    /// fails if no function is called `name`, if the number of arguments doesn't match its arity,
+    /// or if the output can't be converted.
+    pub fn call<Args, Ret>(&self, name: &str, args: Args) -> Result<Ret, CallError<Ret::Error>>
+    where Args: CallArgs, Ret: TryFrom<language::syntax::Term> {
+        let id = match self.book.name_to_id.get(name) {
+            Some(id) if self.program.funs.get(id).is_some() => *id,
+            _ => return Err(CallError::UnknownFunction { name: name.to_string() }),
+        };
+        let args = args.into_args();
+        let arity = self.program.aris.get(&id).copied().unwrap_or(0) as usize;
+        if args.len() != arity {
+            return Err(CallError::WrongArity { name: name.to_string(), expected: arity, given: args.len() });
+        }
+        let output = self.normalize_term(&language::syntax::Term::constructor(name, args));
+        output.try_into().map_err(CallError::Conversion)
+    }
+
     /// returns the number graph rewrites made by the runtime,
     /// since its initialization.
     ///
@@ -209,4 +554,238 @@ impl Runtime {
     pub fn get_rewrite_count(&self) -> usize {
         get_cost(&self.heap) as _
     }
+
+    /// returns the name of the function or constructor with the given id,
+    /// such as the ones given to reduction observers.
+    pub fn get_name(&self, id: u64) -> Option<&str> {
+        self.book.id_to_name.get(&id).map(|name| name.as_str())
+    }
 }
+
+// Runs an evaluation for the API, where a failing builtin panics with its message, like other
+// misuses do. `HVM.exit` keeps unwinding with its `Failure`, which `catch_failure` returns.
+fn evaluate<T>(eval: impl FnOnce() -> T) -> T {
+    match catch_failure(eval) {
+        Ok(done) => done,
+        Err(Failure::Error(message)) => panic!("{}", message),
+        Err(failure) => fail(failure),
+    }
+}
+
+/// the branches of a superposed term, see [`Runtime::collapse_term`]
+pub struct Collapse<'a> {
+    runtime: &'a Runtime,
+    host: u64,
+    branches: language::readback::Branches<'a>,
+}
+
+impl<'a> Iterator for Collapse<'a> {
+    type Item = language::syntax::Term;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The threads are only taken while reading a branch, so that the runtime can be used
+        // in between, for example to evaluate something for each branch
+        let workers = self.runtime.workers.acquire();
+        let tid = workers.tids[0];
+        self.branches.set_tid(tid);
+        let branch = self.branches.next()?;
+
+        let heap = &self.runtime.heap;
+        let host = alloc(heap, tid, 1);
+        link(heap, host, branch);
+        let output = language::readback::as_term(heap, &self.runtime.program, host);
+
+        collect(heap, &self.runtime.program.aris, tid, branch);
+        free(heap, tid, host, 1);
+        Some(*output)
+    }
+}
+
+impl<'a> Drop for Collapse<'a> {
+    fn drop(&mut self) {
+        let workers = self.runtime.workers.acquire();
+        let tid = workers.tids[0];
+        let ptr = load_ptr(&self.runtime.heap, self.host);
+        collect(&self.runtime.heap, &self.runtime.program.aris, tid, ptr);
+    }
+}
+
+/// a handle to an evaluation running in the background, see [`Runtime::spawn_normalize`]
+pub struct EvalHandle {
+    runtime: std::sync::Arc<Runtime>,
+    state: std::sync::Arc<std::sync::Mutex<EvalState>>,
+    thread: std::thread::JoinHandle<Result<language::syntax::Term, EvalError>>,
+}
+
+struct EvalState {
+    tids: Option<Box<[usize]>>, // the threads used, while reducing
+    start: u64, // their rewrite count before reducing
+    progress: u64, // the rewrites made, once done reducing
+    cancelled: bool,
+}
+
+impl EvalHandle {
+    /// returns whether the evaluation is over, in which case [`EvalHandle::join`] won't block.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// returns the number of graph rewrites made by the evaluation so far.
+    pub fn progress(&self) -> u64 {
+        let state = self.state.lock().unwrap();
+        match &state.tids {
+            Some(tids) => get_tids_cost(&self.runtime.heap, tids) - state.start,
+            None => state.progress,
+        }
+    }
+
+    /// stops the evaluation as soon as possible, making [`EvalHandle::join`] fail,
+    /// unless the term was already reduced.
+    ///
+    /// the memory used by the half-reduced term isn't reclaimed.
+    pub fn cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.cancelled = true;
+        if let Some(tids) = &state.tids {
+            set_limit(&self.runtime.heap, tids, 0);
+        }
+    }
+
+    /// waits for the evaluation to finish, returning the Normal Form of the term.
+    pub fn join(self) -> Result<language::syntax::Term, EvalError> {
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(payload) => {
+                let message = payload.downcast_ref::<&str>().map(|x| x.to_string())
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .or_else(|| payload.downcast_ref::<Failure>().map(|x| x.to_string()))
+                    .unwrap_or_default();
+                Err(EvalError::Panicked(message))
+            }
+        }
+    }
+}
+
+/// the reason an evaluation started by [`Runtime::spawn_normalize`] failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EvalError {
+    /// the evaluation was stopped by [`EvalHandle::cancel`]
+    Cancelled,
+    /// the evaluation panicked, with the given message
+    Panicked(String),
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::Cancelled => write!(f, "Evaluation cancelled."),
+            EvalError::Panicked(message) => write!(f, "Evaluation failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+// Hands out disjoint subsets of the heap's threads, so that evaluations running at the same time
+// never share a thread's allocator, visit queue or dup labels
+struct Workers {
+    free: std::sync::Mutex<Vec<usize>>,
+    freed: std::sync::Condvar,
+    per_evaluation: usize,
+}
+
+// The threads taken by an evaluation, given back when dropped
+struct WorkerSet<'a> {
+    workers: &'a Workers,
+    tids: Box<[usize]>,
+}
+
+impl Workers {
+    fn acquire(&self) -> WorkerSet<'_> {
+        let mut free = self.free.lock().unwrap();
+        while free.len() < self.per_evaluation {
+            free = self.freed.wait(free).unwrap();
+        }
+        let count = free.len() - self.per_evaluation;
+        let tids = free.split_off(count).into_boxed_slice();
+        WorkerSet { workers: self, tids }
+    }
+}
+
+// Resets the threads of a background evaluation, however it ends, before they're given back:
+// the pending visits left by a cancelled or panicking reduction are dropped, as otherwise the
+// next evaluation on these threads would pick them up, and the limit set by cancelling is lifted
+struct BackgroundReset<'a> {
+    heap: &'a Heap,
+    tids: &'a [usize],
+    state: &'a std::sync::Mutex<EvalState>,
+}
+
+impl<'a> Drop for BackgroundReset<'a> {
+    fn drop(&mut self) {
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.tids = None;
+        set_limit(self.heap, self.tids, u64::MAX);
+        for tid in self.tids {
+            self.heap.vstk[*tid].clear();
+        }
+    }
+}
+
+impl<'a> Drop for WorkerSet<'a> {
+    fn drop(&mut self) {
+        self.workers.free.lock().unwrap().extend(self.tids.iter());
+        self.workers.freed.notify_all();
+    }
+}
+
+/// the arguments of a function called with [`Runtime::call`],
+/// implemented for tuples of values which can be converted to terms.
+pub trait CallArgs {
+    fn into_args(self) -> Vec<language::syntax::Term>;
+}
+
+macro_rules! impl_call_args {
+    ($($arg:ident),*) => {
+        impl<$($arg: Into<language::syntax::Term>),*> CallArgs for ($($arg,)*) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<language::syntax::Term> {
+                let ($($arg,)*) = self;
+                vec![$($arg.into()),*]
+            }
+        }
+    };
+}
+
+impl_call_args!();
+impl_call_args!(A);
+impl_call_args!(A, B);
+impl_call_args!(A, B, C);
+impl_call_args!(A, B, C, D);
+impl_call_args!(A, B, C, D, E);
+impl_call_args!(A, B, C, D, E, F);
+impl_call_args!(A, B, C, D, E, F, G);
+impl_call_args!(A, B, C, D, E, F, G, H);
+
+/// the reason a [`Runtime::call`] failed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CallError<E> {
+    /// no function with the given name was defined
+    UnknownFunction { name: String },
+    /// the function was given a different number of arguments than it takes
+    WrongArity { name: String, expected: usize, given: usize },
+    /// the output couldn't be converted to the return type
+    Conversion(E),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for CallError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallError::UnknownFunction { name } => write!(f, "Unknown function: `{}`.", name),
+            CallError::WrongArity { name, expected, given } => write!(f, "Function `{}` takes {} arguments, but {} were given.", name, expected, given),
+            CallError::Conversion(err) => write!(f, "Couldn't convert the output: `{}`.", err),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for CallError<E> {}
@@ -46,6 +46,17 @@ pub fn visit(ctx: ReduceCtx, sidxs: &[u64]) -> bool {
   //}
 }
 
+// Visits a field the call matches on, which isn't reduced with the strict arguments, applying the
+// call again once it is in weak head normal form. Reducing it through the visit stack, instead of
+// on a nested reducer, keeps deeply nested matches from overflowing the Rust stack.
+#[inline(always)]
+pub fn visit_field(ctx: ReduceCtx, loc: u64) -> bool {
+  let goup = ctx.redex.insert(ctx.tid, new_redex(*ctx.host, *ctx.cont, 1));
+  *ctx.cont = goup;
+  *ctx.host = loc;
+  true
+}
+
 #[inline(always)]
 pub fn apply(ctx: ReduceCtx, fid: u64, visit: &VisitObj, apply: &ApplyObj) -> bool {
   // Reduces function superpositions
@@ -57,98 +68,43 @@ pub fn apply(ctx: ReduceCtx, fid: u64, visit: &VisitObj, apply: &ApplyObj) -> bo
     }
   }
 
-  // For each rule condition vector
-  let mut matched;
-  for (r, rule) in apply.rules.iter().enumerate() {
-    // Check if the rule matches
-    matched = true;
-    
-    // Tests each rule condition (ex: `get_tag(args[0]) == SUCC`)
-    for (i, cond) in rule.cond.iter().enumerate() {
-      let i = i as u64;
-      match get_tag(*cond) {
-        U60 => {
-          let same_tag = get_tag(load_arg(ctx.heap, ctx.term, i)) == U60;
-          let same_val = get_num(load_arg(ctx.heap, ctx.term, i)) == get_num(*cond);
-          matched = matched && same_tag && same_val;
-        }
-        F60 => {
-          let same_tag = get_tag(load_arg(ctx.heap, ctx.term, i)) == F60;
-          let same_val = get_num(load_arg(ctx.heap, ctx.term, i)) == get_num(*cond);
-          matched = matched && same_tag && same_val;
-        }
-        CTR => {
-          let same_tag = get_tag(load_arg(ctx.heap, ctx.term, i)) == CTR;
-          let same_ext = get_ext(load_arg(ctx.heap, ctx.term, i)) == get_ext(*cond);
-          matched = matched && same_tag && same_ext;
-        }
-        VAR => {
-          // If this is a strict argument, then we're in a default variable
-          if unsafe { *visit.strict_map.get_unchecked(i as usize) } {
-
-            // This is a Kind2-specific optimization.
-            if rule.hoas && r != apply.rules.len() - 1 {
-
-              // Matches number literals
-              let is_num
-                =  get_tag(load_arg(ctx.heap, ctx.term, i)) == U60
-                || get_tag(load_arg(ctx.heap, ctx.term, i)) == F60;
-
-              // Matches constructor labels
-              let is_ctr
-                =  get_tag(load_arg(ctx.heap, ctx.term, i)) == CTR
-                && arity_of(&ctx.prog.aris, load_arg(ctx.heap, ctx.term, i)) == 0;
-
-              // Matches HOAS numbers and constructors
-              let is_hoas_ctr_num
-                =  get_tag(load_arg(ctx.heap, ctx.term, i)) == CTR
-                && get_ext(load_arg(ctx.heap, ctx.term, i)) >= KIND_TERM_CT0
-                && get_ext(load_arg(ctx.heap, ctx.term, i)) <= KIND_TERM_F60;
-
-              matched = matched && (is_num || is_ctr || is_hoas_ctr_num);
-
-            // Only match default variables on CTRs and NUMs
-            } else {
-              let is_ctr = get_tag(load_arg(ctx.heap, ctx.term, i)) == CTR;
-              let is_u60 = get_tag(load_arg(ctx.heap, ctx.term, i)) == U60;
-              let is_f60 = get_tag(load_arg(ctx.heap, ctx.term, i)) == F60;
-              matched = matched && (is_ctr || is_u60 || is_f60);
-            }
-          }
-        }
-        _ => {}
-      }
+  // Selects the rule the call matches, with the function's decision tree
+  let index = match apply.tree.select(ctx.heap, ctx.prog, ctx.term) {
+    Selection::Rule(index) => index,
+    Selection::Fail => return false,
+    Selection::Sup(n, path) => {
+      superpose_field(ctx.heap, &ctx.prog.aris, ctx.tid, *ctx.host, ctx.term, n, path);
+      return true;
     }
+    Selection::Reduce(loc) => {
+      return visit_field(ctx, loc);
+    }
+  };
+  let rule = &apply.rules[index];
 
-    // If all conditions are satisfied, the rule matched, so we must apply it
-    if matched {
-      // Increments the gas count
-      inc_cost(ctx.heap, ctx.tid);
-
-      // Builds the right-hand side ctx.term
-      let done = alloc_body(ctx.heap, ctx.prog, ctx.tid, ctx.term, &rule.vars, &rule.body);
-
-      // Links the *ctx.host location to it
-      link(ctx.heap, *ctx.host, done);
+  // Increments the gas count
+  inc_cost(ctx.heap, ctx.tid);
 
-      // Collects unused variables
-      for var @ RuleVar { param: _, field: _, erase } in rule.vars.iter() {
-        if *erase {
-          collect(ctx.heap, &ctx.prog.aris, ctx.tid, get_var(ctx.heap, ctx.term, var));
-        }
-      }
+  // Builds the right-hand side ctx.term
+  let done = alloc_body(ctx.heap, ctx.prog, ctx.tid, ctx.term, &rule.vars, &rule.body);
 
-      // free the matched ctrs
-      for (i, arity) in &rule.free {
-        free(ctx.heap, ctx.tid, get_loc(load_arg(ctx.heap, ctx.term, *i as u64), 0), *arity);
-      }
-      free(ctx.heap, ctx.tid, get_loc(ctx.term, 0), arity_of(&ctx.prog.aris, fid));
+  // Links the *ctx.host location to it
+  link(ctx.heap, *ctx.host, done);
 
-      return true;
+  // Collects unused variables
+  for var @ RuleVar { param: _, path: _, erase } in rule.vars.iter() {
+    if *erase {
+      collect(ctx.heap, &ctx.prog.aris, ctx.tid, get_var(ctx.heap, ctx.term, var));
     }
   }
 
-  return false;
+  // free the matched ctrs
+  for (i, path, arity) in &rule.free {
+    free(ctx.heap, ctx.tid, get_loc(load_ptr(ctx.heap, get_field_loc(ctx.heap, ctx.term, *i, path)), 0), *arity);
+  }
+  free(ctx.heap, ctx.tid, get_loc(ctx.term, 0), arity_of(&ctx.prog.aris, fid));
+
+  return true;
 }
 
 #[inline(always)]
@@ -171,9 +127,22 @@ pub fn superpose(heap: &Heap, aris: &Aris, tid: usize, host: u64, term: Ptr, arg
       link(heap, fun1 + i, take_arg(heap, argn, 1));
     }
   }
-  link(heap, par0 + 0, Fun(func, fun0));
-  link(heap, par0 + 1, Fun(func, fun1));
+  // Constructors are superposed too, when a call matches on their fields
+  let make = if get_tag(term) == CTR { Ctr } else { Fun };
+  link(heap, par0 + 0, make(func, fun0));
+  link(heap, par0 + 1, make(func, fun1));
   let done = Sup(get_ext(argn), par0);
   link(heap, host, done);
   done
 }
+
+// Lifts a superposition on a field of the matched constructors, following `path` from the argument
+// `n`, to the call, superposing each constructor on the way
+pub fn superpose_field(heap: &Heap, aris: &Aris, tid: usize, host: u64, term: Ptr, n: u64, path: &[u64]) -> Ptr {
+  for depth in (0 .. path.len()).rev() {
+    let ctr_loc = get_field_loc(heap, term, n, &path[.. depth]);
+    let ctr = load_ptr(heap, ctr_loc);
+    superpose(heap, aris, tid, ctr_loc, ctr, load_arg(heap, ctr, path[depth]), path[depth]);
+  }
+  superpose(heap, aris, tid, host, term, load_arg(heap, term, n), n)
+}
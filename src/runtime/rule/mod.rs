@@ -2,3 +2,4 @@ pub mod app;
 pub mod dup;
 pub mod op2;
 pub mod fun;
+pub mod native;
@@ -0,0 +1,77 @@
+use crate::runtime::{*};
+use crate::language;
+
+// Calls a function implemented by the host. Its arguments are strict, so, as for interpreted
+// functions, superposed arguments are lifted first. Then, each argument is normalized, read back
+// and handed to the host, and the term it returns is allocated in place of the call.
+pub fn apply(ctx: ReduceCtx, native: &NativeObj) -> bool {
+  let arit = arity_of(&ctx.prog.aris, ctx.term);
+
+  // Reduces function superpositions
+  for n in 0 .. arit {
+    if get_tag(load_arg(ctx.heap, ctx.term, n)) == SUP {
+      fun::superpose(ctx.heap, &ctx.prog.aris, ctx.tid, *ctx.host, ctx.term, load_arg(ctx.heap, ctx.term, n), n);
+      return true;
+    }
+  }
+
+  let mut args = Vec::new();
+  for n in 0 .. arit {
+    normalize_nested(ctx.heap, ctx.prog, ctx.tid, get_loc(ctx.term, n));
+    args.push(*language::readback::as_term(ctx.heap, ctx.prog, get_loc(ctx.term, n)));
+  }
+
+  match alloc_result(ctx.heap, ctx.prog, ctx.tid, &native.book, &(native.func)(&args)) {
+    Ok(done) => {
+      link(ctx.heap, *ctx.host, done);
+      for n in 0 .. arit {
+        collect(ctx.heap, &ctx.prog.aris, ctx.tid, load_arg(ctx.heap, ctx.term, n));
+      }
+      if arit > 0 {
+        free(ctx.heap, ctx.tid, get_loc(ctx.term, 0), arit);
+      }
+      true
+    }
+    Err(err) => {
+      fail(Failure::Error(format!("Runtime failure on: {}\n{}", show_at(ctx.heap, ctx.prog, *ctx.host, &[]), err)));
+    }
+  }
+}
+
+// Allocates a term returned by the host, which, unlike rule bodies, wasn't sanitized yet
+fn alloc_result(heap: &Heap, prog: &Program, tid: usize, book: &language::rulebook::RuleBook, term: &language::syntax::Term) -> Result<Ptr, String> {
+  fn check_names(book: &language::rulebook::RuleBook, term: &language::syntax::Term) -> Result<(), String> {
+    match term {
+      language::syntax::Term::Ctr { name, args } => {
+        if !book.name_to_id.contains_key(name) {
+          return Err(format!("Unknown constructor: `{}`.", name));
+        }
+        args.iter().try_for_each(|arg| check_names(book, arg))
+      }
+      language::syntax::Term::Dup { expr, body, .. } | language::syntax::Term::Let { expr, body, .. } => {
+        check_names(book, expr)?;
+        check_names(book, body)
+      }
+      language::syntax::Term::Sup { val0, val1 } | language::syntax::Term::Op2 { val0, val1, .. } => {
+        check_names(book, val0)?;
+        check_names(book, val1)
+      }
+      language::syntax::Term::App { func, argm } => {
+        check_names(book, func)?;
+        check_names(book, argm)
+      }
+      language::syntax::Term::Lam { body, .. } => check_names(book, body),
+      _ => Ok(()),
+    }
+  }
+
+  // Sanitizing it as the body of a rule linearizes its variables
+  let rule = language::syntax::Rule {
+    lhs: Box::new(language::syntax::Term::Ctr { name: "HVM_NATIVE".to_string(), args: Vec::new() }),
+    rhs: Box::new(term.clone()),
+  };
+  let rule = language::rulebook::sanitize_rule(&rule)?;
+  check_names(book, &rule.rhs)?;
+  let body = build_body(&term_to_core(book, &rule.rhs, &[]), 0);
+  Ok(alloc_body(heap, prog, tid, 0, &[], &body))
+}
@@ -0,0 +1,3 @@
+fn main() {
+    hvm::compiler::build_to_out_dir(&["src/logic.hvm"]);
+}
@@ -0,0 +1,33 @@
+mod logic {
+    include!(concat!(env!("OUT_DIR"), "/hvm.rs"));
+}
+
+fn main() {
+    // other code comes before and after the compiled code, and both call each other
+    let builder = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("(Double x) = (* x 2)\n(Pair a b) = (Tuple a b)")
+        .unwrap();
+    let runtime = logic::register(builder)
+        .add_code("(Main n) = (Pair (Double (Sum [1, 2, 3])) (Sum [n]))")
+        .unwrap()
+        .build();
+    let term = hvm::syntax::read_term("(Main 4)").unwrap();
+    println!("{}", runtime.normalize_term(&term));
+
+    // compiled loops stop when cancelled
+    let runtime = std::sync::Arc::new(runtime);
+    let handle = runtime.spawn_normalize(&hvm::syntax::read_term("(Loop 0)").unwrap());
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert!(handle.progress() > 0);
+    handle.cancel();
+    for _ in 0 .. 1000 {
+        if handle.is_finished() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(handle.is_finished(), "the loop wasn't cancelled");
+    println!("{}", handle.join().unwrap_err());
+}
@@ -0,0 +1,380 @@
+// Runs the `hvm` command on programs written to a temporary directory
+fn hvm(name: &str, code: &str, args: &[&str], envs: &[(&str, &str)]) -> std::process::Output {
+    let file = std::env::temp_dir().join(format!("hvm-cli-{}-{}.hvm", std::process::id(), name));
+    std::fs::write(&file, code).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_hvm"))
+        .args(["run", "-s", "100000", "-t", "2", "-f", file.to_str().unwrap()])
+        .args(args)
+        .envs(envs.iter().copied())
+        .output()
+        .unwrap();
+    std::fs::remove_file(&file).unwrap();
+    output
+}
+
+#[test]
+fn main_arguments() {
+    let code = "
+        (Len List.nil) = 0
+        (Len (List.cons x xs)) = (+ 1 (Len xs))
+        (Main args) = (HVM.env \"HVM_CLI_TEST\" λvar (Pair (Len args) args var))
+    ";
+    let output = hvm("args", code, &["--", "a", "b c", "-d"], &[("HVM_CLI_TEST", "value")]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "(Pair 3 [\"a\", \"b c\", \"-d\"] \"value\")\n");
+    let output = hvm("no-args", code, &[], &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "(Pair 0 [] \"\")\n");
+
+    // other expressions, and a `Main` without arguments, run as before
+    let output = hvm("expr", code, &["(Len [1, 2])"], &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    let output = hvm("main", "(Main) = 42", &["--", "a"], &[]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+}
+
+#[test]
+fn exit_codes_and_output() {
+    let code = "
+        (Main) = (HVM.put \"a\" (HVM.put \"b\" (HVM.eprint \"warning\" (HVM.print \"c\" (Done 0)))))
+        (Fail n) = (HVM.eprint \"failing\" (HVM.exit n))
+        (Bad) = (HVM.load 42 λx x)
+    ";
+    let output = hvm("output", code, &[], &[]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "abc\n(Done 0)\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "warning\n");
+
+    let output = hvm("exit", code, &["(Fail 3)"], &[]);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "failing\n");
+
+    // runtime failures, and IO type errors, are reported on stderr
+    let output = hvm("failure", code, &["(Bad)"], &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Runtime failure on:"));
+    let output = hvm("io", code, &["--io", "true", "(Bad)"], &[]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn compile_options() {
+    let dir = std::env::temp_dir().join(format!("hvm-cli-{}-compile", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let compile = |code: &str, args: &[&str]| {
+        std::fs::write(dir.join("main.hvm"), code).unwrap();
+        std::process::Command::new(env!("CARGO_BIN_EXE_hvm"))
+            .current_dir(&dir)
+            .args(["compile", "main.hvm"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = compile("(Main) = 42", &["--out", "out", "--name", "answer"]);
+    assert_eq!(output.status.code(), Some(0));
+    let cargo_toml = std::fs::read_to_string(dir.join("out/Cargo.toml")).unwrap();
+    assert!(cargo_toml.starts_with("[package]\nname = \"answer\"\n"));
+    assert!(cargo_toml.contains(&format!("\nhvm = \"{}\"\n", env!("CARGO_PKG_VERSION"))));
+    assert!(dir.join("out/src/program.rs").exists());
+
+    // the crate can depend on a local hvm instead
+    let output = compile("(Main) = 42", &["--out", "local", "--hvm-path", env!("CARGO_MANIFEST_DIR")]);
+    assert_eq!(output.status.code(), Some(0));
+    let cargo_toml = std::fs::read_to_string(dir.join("local/Cargo.toml")).unwrap();
+    let path = std::fs::canonicalize(env!("CARGO_MANIFEST_DIR")).unwrap();
+    assert!(cargo_toml.contains(&format!("\nhvm = {{ path = {:?} }}\n", path.to_string_lossy())));
+    let output = compile("(Main) = 42", &["--out", "missing", "--hvm-path", "no-such-dir"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Couldn't find 'no-such-dir'"));
+
+    // existing crates are only overwritten with `--force`
+    let output = compile("(Main) = 43", &["--out", "out", "--name", "answer"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--force"));
+    assert_eq!(std::fs::read_to_string(dir.join("out/src/program.hvm")).unwrap(), "(Main) = 42");
+    let output = compile("(Main) = 43", &["--out", "out", "--name", "answer", "--force"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(std::fs::read_to_string(dir.join("out/src/program.hvm")).unwrap(), "(Main) = 43");
+
+    // invalid code is reported, rather than compiled
+    let output = compile("(Foo x) = (Bar x)\n(Bar x y) = x", &["--out", "bad"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Inconsistent arity on: `(Bar x y)`."));
+    let output = compile("(Foo x) = y", &["--out", "bad"]);
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Unbound variable: `y`."));
+    let output = compile("(Foo x", &["--out", "bad"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!dir.join("bad").exists());
+
+    // tree-shaking only compiles what the entries lead to, and reports the rest
+    let code = "
+        (Used x) = (Some x)
+        (Unused x) = (Other (Used x))
+        (Orphan) = Lonely
+        (Main) = (Used 1)
+    ";
+    let output = compile(code, &["--out", "shaken", "--shake"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), concat!(
+        "Removed 2 functions unreachable from Main: Orphan, Unused.\n",
+        "Removed 2 constructors unreachable from Main: Lonely, Other.\n",
+        "Compiled definitions to 'shaken'.\n",
+    ));
+    let program_rs = std::fs::read_to_string(dir.join("shaken/src/program.rs")).unwrap();
+    assert!(program_rs.contains("name: \"Used\"") && !program_rs.contains("name: \"Unused\""));
+    let output = compile(code, &["--out", "shaken", "--force", "--entry", "Unused", "--entry", "Orphan"]);
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Removed 1 function unreachable from Unused, Orphan: Main.\n"));
+    let output = compile(code, &["--out", "shaken", "--force", "--entry", "Nope"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Unknown entry point: 'Nope'.\n");
+
+    // strictness can be explained before compiling
+    let code = "
+        (Sum acc 0) = acc
+        (Sum acc n) = (Sum (+ acc n) (- n 1))
+        ~(Keep x) = (+ x 1)
+        (Main) = (Keep (Sum 0 10))
+    ";
+    let output = compile(code, &["--out", "strict", "--explain-strictness"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), concat!(
+        "Keep: lazy (inference disabled)\n",
+        "Main: lazy\n",
+        "Sum: 0 inferred (returned), 1 matched\n",
+        "Compiled definitions to 'strict'.\n",
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Builds the crate `hvm compile` writes, and checks it gives the same result, in the same number of
+// rewrites, as `hvm run`
+#[test]
+fn compiled_crate() {
+    let dir = std::env::temp_dir().join(format!("hvm-cli-{}-crate", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let code = "
+        (Sum acc 0) = acc
+        (Sum acc n) = (Sum (+ acc n) (- n 1))
+        (Len List.nil) = 0
+        (Len (List.cons x xs)) = (+ 1 (Len xs))
+        (Main n) = (Pair (Sum 0 n) (Len [(Sum 1 3), 2, 3]))
+    ";
+    std::fs::write(dir.join("main.hvm"), code).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_hvm"))
+        .current_dir(&dir)
+        .args(["compile", "main.hvm", "--out", "sum", "--name", "sum"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+    // the crate is built as written, against this source instead of crates.io, which the patch
+    // only allows if its version is the one the crate requires
+    let mut manifest = std::fs::read_to_string(dir.join("sum/Cargo.toml")).unwrap();
+    manifest.push_str(&format!("\n[patch.crates-io]\nhvm = {{ path = {:?} }}\n", env!("CARGO_MANIFEST_DIR")));
+    std::fs::write(dir.join("sum/Cargo.toml"), manifest).unwrap();
+    let target = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("crates");
+    let status = std::process::Command::new(env!("CARGO"))
+        .args(["build", "--quiet", "--manifest-path"])
+        .arg(dir.join("sum/Cargo.toml"))
+        .arg("--target-dir").arg(&target)
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to build the crate");
+
+    // the cost is printed to stderr, after the time, which differs
+    let cost = |output: &std::process::Output| {
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        stderr.split("COST: ").nth(1).and_then(|rest| rest.split(' ').next()).unwrap_or_default().to_string()
+    };
+    let compiled = std::process::Command::new(target.join("debug/sum"))
+        .args(["-t", "1", "-c", "(Main 100000)"])
+        .output()
+        .unwrap();
+    let interpreted = std::process::Command::new(env!("CARGO_BIN_EXE_hvm"))
+        .args(["run", "-t", "1", "-c", "true", "-f", dir.join("main.hvm").to_str().unwrap(), "(Main 100000)"])
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&compiled.stdout), "(Pair 5000050000 3)\n");
+    assert_eq!(String::from_utf8_lossy(&compiled.stdout), String::from_utf8_lossy(&interpreted.stdout));
+    assert!(!cost(&compiled).is_empty());
+    assert_eq!(cost(&compiled), cost(&interpreted));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compile_to_c() {
+    let cc = std::env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    if std::process::Command::new(&cc).arg("--version").output().is_err() {
+        eprintln!("skipping compile_to_c: no C compiler");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("hvm-cli-{}-compile-c", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let examples = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+    std::fs::write(dir.join("args.hvm"), "
+        (Len List.nil) = 0
+        (Len (List.cons x xs)) = (+ 1 (Len xs))
+        (Main args) = (HVM.put \"a\" (HVM.print \"b\" (Pair (Len args) [args, 1.5, (/ 1.0 3.0), λx λy (x y), {1 2}])))
+    ").unwrap();
+    std::fs::write(dir.join("nested.hvm"), "
+        (Pairs (List.cons x (List.cons y t))) = (List.cons (+ x y) (Pairs t))
+        (Pairs xs) = xs
+        (Range 0 acc) = acc
+        (Range n acc) = (Range (- n 1) (List.cons n acc))
+        (Head (Pair (Pair 0 b) c)) = (Zero b c)
+        (Head (Pair a b)) = (Other a b)
+        (Apply f x) = (f x)
+        (Main) = [(Pairs (Range 5 [])), (Head (Pair (Pair (- 1 1) 2) 3)), (Head (Pair (Pair {0 1} 2) 3)), (Head (Pair (Pair λx x 2) 3)), (Apply λx (+ x 1) 2)]
+    ").unwrap();
+    std::fs::write(dir.join("deep.hvm"), "
+        (P (Cons x (Cons y xs))) = (Cons (+ x y) (P (Cons y xs)))
+        (P (Cons x Nil)) = Nil
+        (Iter 0 xs) = xs
+        (Iter n xs) = (Iter (- n 1) (P xs))
+        (Range 0 acc) = acc
+        (Range n acc) = (Range (- n 1) (Cons (% n 7) acc))
+        (Main) = (Iter 1000 (Range 1001 Nil))
+    ").unwrap();
+
+    // the compiled programs, optimized, give the same results as `hvm run`
+    let programs = [
+        ("hello", format!("{}/hello/main.hvm", examples), vec![]),
+        ("callcc", format!("{}/callcc/main.hvm", examples), vec![]),
+        ("queue", format!("{}/queue/main.hvm", examples), vec![]),
+        ("bitonic", format!("{}/sort/bitonic/main.hvm", examples), vec!["(Main 8)"]),
+        ("quick", format!("{}/sort/quick/main.hvm", examples), vec!["(Main 8)"]),
+        ("radix", format!("{}/sort/radix/main.hvm", examples), vec!["(Main 8)"]),
+        ("multiplication", format!("{}/lambda/multiplication/main.hvm", examples), vec!["(Main 4)"]),
+        ("padic_clifford", format!("{}/lambda/padic_clifford/main.hvm", examples), vec![]),
+        ("machine", format!("{}/machine/main.hvm", examples), vec!["(Main 6)"]),
+        ("args", dir.join("args.hvm").to_str().unwrap().to_string(), vec!["--", "a", "b c"]),
+        ("nested", dir.join("nested.hvm").to_str().unwrap().to_string(), vec![]),
+        ("deep", dir.join("deep.hvm").to_str().unwrap().to_string(), vec![]),
+    ];
+    for (name, file, args) in programs {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_hvm"))
+            .current_dir(&dir)
+            .args(["compile", &file, "--target", "c", "--name", name, "--build", "-O2"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}: {}", name, String::from_utf8_lossy(&output.stderr));
+        let compiled = std::process::Command::new(dir.join(name).join(name)).args(&args).output().unwrap();
+        let interpreted = std::process::Command::new(env!("CARGO_BIN_EXE_hvm"))
+            .args(["run", "-f", &file])
+            .args(&args)
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&compiled.stdout), String::from_utf8_lossy(&interpreted.stdout), "{}", name);
+    }
+
+    // builtins which need a host are rejected
+    std::fs::write(dir.join("env.hvm"), "(Main) = (HVM.env \"HOME\" λx x)").unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_hvm"))
+        .current_dir(&dir)
+        .args(["compile", "env.hvm", "--target", "c"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "The C target doesn't support 'HVM.env'.\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn c_library() {
+    let cc = std::env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    if std::process::Command::new(&cc).arg("--version").output().is_err() {
+        eprintln!("skipping c_library: no C compiler");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("hvm-cli-{}-c-library", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("double.hvm"), "
+        (Double 0) = 0
+        (Double n) = (+ 2 (Double (- n 1)))
+        (Div a b) = (/ a b)
+        (Exit n) = (HVM.print \"exiting\" (HVM.exit n))
+        (Main) = (Double 21)
+    ").unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_hvm"))
+        .current_dir(&dir)
+        .args(["compile", "double.hvm", "--target", "c"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // the program, without its `main`, is linked into another one, which evaluates terms with it
+    let status = std::process::Command::new(&cc)
+        .current_dir(&dir)
+        .args(["-O2", "-DHVM_NO_MAIN", "-c", "double/double.c", "-o", "double.o"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    std::fs::write(dir.join("app.c"), r#"
+        #include <stdio.h>
+        #include <stdlib.h>
+        #include "double/double.h"
+
+        static void eval(const char* expr) {
+            char* norm = NULL;
+            int status = hvm_eval(expr, &norm);
+            if (status == HVM_OK) {
+                printf("%s\n", norm);
+                free(norm);
+            } else if (status == HVM_ERROR) {
+                printf("error: %s\n", hvm_error());
+            } else if (status == HVM_EXIT) {
+                printf("exit: %d\n", hvm_exit_code());
+            }
+        }
+
+        int main(void) {
+            eval(NULL);
+            if (hvm_init(1 << 20) != HVM_OK) {
+                return 1;
+            }
+            eval(NULL);
+            eval("(Double 5)");
+            eval("(Div 1 0)");
+            eval("(Nope 1)");
+            eval("(Exit 3)");
+            eval("[(Double 1), (Double 2)]");
+            hvm_free();
+            return 0;
+        }
+    "#).unwrap();
+    let expected = concat!(
+        "error: The runtime isn't initialized. Call 'hvm_init' first.\n",
+        "42\n",
+        "10\n",
+        "error: Division by zero.\n",
+        "error: Invalid expression: unknown name, or wrong number of arguments: 'Nope'.\n",
+        "exiting\n",
+        "exit: 3\n",
+        "[2, 4]\n",
+    );
+    let status = std::process::Command::new(&cc)
+        .current_dir(&dir)
+        .args(["-O2", "app.c", "double.o", "-o", "app", "-lm"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = std::process::Command::new(dir.join("app")).output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+
+    // and into C++ programs, if there's a C++ compiler
+    let cxx = std::env::var("CXX").unwrap_or_else(|_| String::from("c++"));
+    if std::process::Command::new(&cxx).arg("--version").output().is_ok() {
+        let status = std::process::Command::new(&cxx)
+            .current_dir(&dir)
+            .args(["-O2", "-x", "c++", "app.c", "-x", "none", "double.o", "-o", "app-cpp", "-lm"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let output = std::process::Command::new(dir.join("app-cpp")).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
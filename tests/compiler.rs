@@ -0,0 +1,71 @@
+#[test]
+fn build_to_dir() {
+    let dir = std::env::temp_dir().join(format!("hvm-compiler-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("logic.hvm");
+    std::fs::write(&file, "(Sum (List.cons (Pair a b) xs)) = (+ (+ a b) (Sum xs))\n(Sum List.nil) = 0").unwrap();
+
+    hvm::compiler::build_to_dir(&[file.to_str().unwrap()], &dir).unwrap();
+    let hvm_rs = std::fs::read_to_string(dir.join("hvm.rs")).unwrap();
+    assert!(hvm_rs.contains("pub fn register(builder: hvm::RuntimeBuilder) -> hvm::RuntimeBuilder"));
+    assert!(hvm_rs.contains("name: \"Sum\""));
+    assert!(std::fs::read_to_string(dir.join("hvm.hvm")).unwrap().contains("(Sum List.nil) = 0"));
+
+    // the ids of the code don't change when more code is added after it, as `register` does
+    let code = std::fs::read_to_string(dir.join("hvm.hvm")).unwrap();
+    let book = hvm::rulebook::gen_rulebook(&hvm::syntax::read_file(&code).unwrap());
+    let more = hvm::rulebook::gen_rulebook(&hvm::syntax::read_file(&format!("{}\n(Foo (Bar (Baz x))) = x\n(Main) = (Sum [])", code)).unwrap());
+    for (name, id) in &book.name_to_id {
+        assert_eq!(more.name_to_id[name], *id);
+    }
+
+    // errors are reported, rather than writing anything
+    std::fs::write(&file, "(Sum").unwrap();
+    assert!(hvm::compiler::build_to_dir(&[file.to_str().unwrap()], &dir).is_err());
+    assert!(hvm::compiler::build_to_dir(&["missing.hvm"], &dir).unwrap_err().contains("missing.hvm"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Builds the crate on `tests/build_script`, which includes the `hvm.rs` its build script writes,
+// and runs it
+#[test]
+fn build_script() {
+    let root = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let output = std::process::Command::new(env!("CARGO"))
+        .args(["run", "--quiet", "--manifest-path"])
+        .arg(root.join("tests/build_script/Cargo.toml"))
+        .arg("--target-dir").arg(concat!(env!("CARGO_TARGET_TMPDIR"), "/build_script"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "failed to run the crate:\n{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "(Tuple 12 4)\nEvaluation cancelled.\n");
+}
+
+#[test]
+fn tail_loops() {
+    let code = "
+        (Sum acc 0) = acc
+        (Sum acc n) = (Sum (+ acc n) (- n 1))
+        (Swap a b 0) = (- a b)
+        (Swap a b n) = (Swap b a (- n 1))
+        (Len List.nil) = 0
+        (Len (List.cons x xs)) = (+ 1 (Len xs))
+        (Fib 0 a b) = a
+        (Fib n a b) = (Fib (- n 1) b (+ a b))
+        (Wrap f 0) = f
+        (Wrap f n) = (Wrap λx (f x) (- n 1))
+        (Apply f x) = (f x)
+    ";
+    let (rust, _) = hvm::compiler::compile::build_code(code, &Default::default(), None).unwrap();
+    let apply = |name: &str| rust.split(&format!("pub fn _{}__apply", name)).nth(1).unwrap().split("\n}\n").next().unwrap().to_string();
+
+    // self-tail-recursive rules on numbers loop, counting the rewrites of each step
+    assert!(apply("Sum").contains("loop {") && apply("Sum").contains("cost += 4;"));
+    assert!(apply("Swap").contains("let next0 = arg1;\n") && apply("Swap").contains("let next1 = arg0;\n"));
+    assert!(apply("Fib").contains("loop {"));
+    // but not those which aren't tail calls, or call back with other terms
+    assert!(!apply("Len").contains("loop {"));
+    assert!(!apply("Wrap").contains("loop {"));
+    // a single application isn't mistaken for a constructor
+    assert!(apply("Apply").contains("App("));
+}
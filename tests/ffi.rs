@@ -0,0 +1,59 @@
+// Compiles the C program on `tests/ffi` against `include/hvm.h` and the cdylib, and runs it
+
+#[cfg(unix)]
+#[test]
+fn c_program() {
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // tests are built on `target/<profile>/deps`, next to which the cdylib is
+    let exe = std::env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap().parent().unwrap();
+    let program = lib_dir.join("hvm_ffi_test");
+
+    // tests only need the rlib, so the cdylib must be built separately
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo.current_dir(&root).args(["build", "--lib", "--target-dir"]).arg(lib_dir.parent().unwrap());
+    if lib_dir.ends_with("release") {
+        cargo.arg("--release");
+    }
+    assert!(cargo.status().unwrap().success(), "failed to build the cdylib");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_string()))
+        .arg(root.join("tests/ffi/main.c"))
+        .arg("-I").arg(root.join("include"))
+        .arg("-L").arg(lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .arg("-lhvm")
+        .arg("-Wall").arg("-Werror")
+        .arg("-o").arg(&program)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to compile the C program");
+
+    let output = Command::new(&program).output().unwrap();
+    assert!(output.status.success(), "C program failed:\n{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
+
+// Regenerates `include/hvm.h` from `src/ffi.rs`, and checks it didn't fall out of date
+#[test]
+fn c_header() {
+    use std::path::PathBuf;
+
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).unwrap();
+    let mut header = Vec::new();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(root.join("src/ffi.rs"))
+        .generate()
+        .expect("failed to generate the header")
+        .write(&mut header);
+    let expected = std::fs::read_to_string(root.join("include/hvm.h")).unwrap();
+    assert!(
+        String::from_utf8(header).unwrap() == expected,
+        "include/hvm.h is out of date, regenerate it with `cbindgen --config cbindgen.toml --output include/hvm.h`",
+    );
+}
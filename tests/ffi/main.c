@@ -0,0 +1,81 @@
+// Exercises the C interface, as an embedder would. Exits with a non-zero code on failure.
+
+#include <stdio.h>
+#include <string.h>
+#include "hvm.h"
+
+static int failures = 0;
+
+static void check(int cond, const char *what) {
+  if (!cond) {
+    fprintf(stderr, "failed: %s\n", what);
+    failures++;
+  }
+}
+
+int main(void) {
+  HvmRuntime *runtime = hvm_runtime_new(1 << 20, 1);
+  char *output = NULL;
+
+  check(hvm_runtime_add_code(runtime, "(Fib 0) = 0\n(Fib 1) = 1\n(Fib n) = (+ (Fib (- n 1)) (Fib (- n 2)))", NULL) == HVM_STATUS_OK, "add code");
+  check(hvm_runtime_eval(runtime, "(Fib 10)", &output) == HVM_STATUS_OK, "eval");
+  check(strcmp(output, "55") == 0, "eval output");
+  hvm_string_free(output);
+
+  // code can be added after evaluating
+  check(hvm_runtime_add_code(runtime, "(Double x) = (* x 2)", NULL) == HVM_STATUS_OK, "add more code");
+  check(hvm_runtime_eval(runtime, "(Double (Fib 10))", &output) == HVM_STATUS_OK, "eval after adding code");
+  check(strcmp(output, "110") == 0, "eval after adding code output");
+  hvm_string_free(output);
+  check(hvm_runtime_cost(runtime) > 0, "cost");
+
+  // the limit stops runaway evaluations, and leaves the runtime usable
+  uint64_t cost = hvm_runtime_cost(runtime);
+  check(hvm_runtime_eval_limited(runtime, "(Fib 30)", 1000, &output) == HVM_STATUS_LIMIT_EXCEEDED, "limit exceeded");
+  hvm_string_free(output);
+  check(hvm_runtime_cost(runtime) > cost + 1000, "cost of limited eval");
+  check(hvm_runtime_eval_limited(runtime, "(Fib 5)", 1000, &output) == HVM_STATUS_OK, "limit not exceeded");
+  check(strcmp(output, "5") == 0, "limited eval output");
+  hvm_string_free(output);
+
+  // errors are reported with a message
+  char *error = NULL;
+  check(hvm_runtime_add_code(runtime, "(Foo = 1", &error) == HVM_STATUS_PARSE_ERROR, "code parse error");
+  check(error != NULL && strlen(error) > 0, "code parse error message");
+  hvm_string_free(error);
+  check(hvm_runtime_eval(runtime, "(Fib", &output) == HVM_STATUS_PARSE_ERROR, "expression parse error");
+  hvm_string_free(output);
+  check(hvm_runtime_eval(runtime, "(Fib (Undefined 1))", &output) == HVM_STATUS_RUNTIME_ERROR, "unbound symbol");
+  check(strstr(output, "Undefined") != NULL, "unbound symbol message");
+  hvm_string_free(output);
+  check(hvm_runtime_eval(runtime, "(Fib 3)", &output) == HVM_STATUS_OK, "eval after error");
+  check(strcmp(output, "2") == 0, "eval after error output");
+  hvm_string_free(output);
+  check(hvm_runtime_eval(runtime, "(HVM.exit λx x)", &output) == HVM_STATUS_RUNTIME_ERROR, "builtin failure");
+  check(strstr(output, "Runtime failure") != NULL, "builtin failure message");
+  hvm_string_free(output);
+
+  // exiting stops the evaluation, but not the process
+  check(hvm_runtime_exit_code(runtime) == 0, "no exit code");
+  check(hvm_runtime_eval(runtime, "(HVM.exit (+ 1 2))", NULL) == HVM_STATUS_EXITED, "exit");
+  check(hvm_runtime_exit_code(runtime) == 3, "exit code");
+  check(hvm_runtime_eval(runtime, "(Fib 4)", &output) == HVM_STATUS_OK, "eval after exit");
+  check(strcmp(output, "3") == 0, "eval after exit output");
+  hvm_string_free(output);
+  check(hvm_runtime_eval(NULL, "(Fib 3)", NULL) == HVM_STATUS_NULL_POINTER, "null runtime");
+  check(hvm_runtime_eval(runtime, NULL, NULL) == HVM_STATUS_NULL_POINTER, "null expression");
+  hvm_runtime_free(runtime);
+
+  // code which parses, but can't be built, fails on evaluation
+  runtime = hvm_runtime_new(1 << 20, 1);
+  check(hvm_runtime_add_code(runtime, "(Foo x) = (Bar x)\n(Bar x y) = x", NULL) == HVM_STATUS_OK, "add inconsistent code");
+  check(hvm_runtime_eval(runtime, "(Foo 1)", &output) == HVM_STATUS_BUILD_ERROR, "build error");
+  check(strstr(output, "Inconsistent arity") != NULL, "build error message");
+  hvm_string_free(output);
+  hvm_runtime_free(runtime);
+
+  if (failures == 0) {
+    printf("ok\n");
+  }
+  return failures == 0 ? 0 : 1;
+}
@@ -39,3 +39,884 @@ fn insertion_sort_parallel() {
         assert_eq!(list, output);
     });
 }
+
+static SQUARES: &str = "
+(Sq a) = (* a a)
+(Pow 0 x) = x
+(Pow n x) = (Sq (Pow (- n 1) x))
+";
+
+#[test]
+fn shared_readback() {
+    use hvm::readback::ReadbackMode;
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code(SQUARES)
+        .unwrap()
+        .build();
+    let term = hvm::syntax::read_term("λx (Pow 3 x)").unwrap();
+    assert_eq!(
+        runtime.normalize_term_as(&term, ReadbackMode::Tree).to_string(),
+        "λx0 (* (* (* x0 x0) (* x0 x0)) (* (* x0 x0) (* x0 x0)))",
+    );
+    assert_eq!(
+        runtime.normalize_term_as(&term, ReadbackMode::Shared).to_string(),
+        "λx0 let s1 = (* x0 x0); let s0 = (* s1 s1); (* s0 s0)",
+    );
+    // the shared output grows linearly, rather than exponentially
+    let term = hvm::syntax::read_term("λx (Pow 12 x)").unwrap();
+    let shared = runtime.normalize_term_as(&term, ReadbackMode::Shared).to_string();
+    assert_eq!(shared.matches("let").count(), 11);
+}
+
+#[test]
+fn bounded_readback() {
+    use hvm::readback::{ReadbackMode, ReadbackOptions};
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code(SQUARES)
+        .unwrap()
+        .build();
+    let term = hvm::syntax::read_term("λx (Pow 3 x)").unwrap();
+    let read = |options: ReadbackOptions| runtime.normalize_term_with(&term, &options).to_string();
+    assert_eq!(
+        read(ReadbackOptions { max_depth: Some(2), ..Default::default() }),
+        "λx0 (* (* ... ...) (* ... ...))",
+    );
+    assert_eq!(
+        read(ReadbackOptions { max_nodes: Some(5), ..Default::default() }),
+        "λx0 (* (* (* x0 ...) ...) ...)",
+    );
+    assert_eq!(
+        read(ReadbackOptions { mode: ReadbackMode::Shared, max_nodes: Some(4), ..Default::default() }),
+        "λx0 let s1 = (* ... ...); let s0 = (* s1 s1); (* s0 s0)",
+    );
+    // without limits, it behaves like a regular readback
+    assert_eq!(read(ReadbackOptions::default()), runtime.normalize_term(&term).to_string());
+}
+
+#[test]
+fn collapse_superpositions() {
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Choose) = {1 2}
+            (Same x) = dup a b = x; (Pair a b)
+            (Id x) = x
+        ")
+        .unwrap()
+        .build();
+    let collapse = |code: &str| {
+        let term = hvm::syntax::read_term(code).unwrap();
+        runtime.collapse_term(&term).map(|term| term.to_string()).collect::<Vec<_>>()
+    };
+    assert_eq!(collapse("(Pair {1 2} {3 4})"), ["(Pair 1 3)", "(Pair 1 4)", "(Pair 2 3)", "(Pair 2 4)"]);
+    // both copies of a superposition take the same side
+    assert_eq!(collapse("(Same (Choose))"), ["(Pair 1 1)", "(Pair 2 2)"]);
+    assert_eq!(collapse("λx {(+ x 1) λy y}"), ["λx0 (+ x0 1)", "λ* λx0 x0"]);
+    assert_eq!(collapse("7"), ["7"]);
+    // branches are enumerated lazily
+    let term = hvm::syntax::read_term("(Pair {1 2} {3 4})").unwrap();
+    assert_eq!(runtime.collapse_term(&term).next().unwrap().to_string(), "(Pair 1 3)");
+    // while the runtime evaluates other terms
+    let branches = runtime.collapse_term(&term).map(|branch| runtime.normalize_term(&hvm::Term::constructor("Id", [branch])).to_string());
+    assert_eq!(branches.collect::<Vec<_>>(), ["(Pair 1 3)", "(Pair 1 4)", "(Pair 2 3)", "(Pair 2 4)"]);
+    // and can also be collected from HVM code
+    let term = hvm::syntax::read_term("(HVM.collapse (Pair {1 2} {3 4}))").unwrap();
+    assert_eq!(runtime.normalize_term(&term).to_string(), "[(Pair 1 3), (Pair 1 4), (Pair 2 3), (Pair 2 4)]");
+    // even while other fields are still waiting to be normalized
+    let term = hvm::syntax::read_term("(Pair (Id 5) (HVM.collapse {1 2}))").unwrap();
+    assert_eq!(runtime.normalize_term(&term).to_string(), "(Pair 5 [1, 2])");
+    // but not with variables bound outside of them, which the branches can't share
+    let term = hvm::syntax::read_term("λx (HVM.collapse {x 1})").unwrap();
+    let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| runtime.normalize_term(&term))).unwrap_err();
+    assert_eq!(payload.downcast_ref::<String>().unwrap(), "Can't collapse a term with variables bound outside of it.");
+}
+
+#[test]
+fn native_functions() {
+    use hvm::syntax::Term;
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Main) = (Lookup (Key (+ 1 2)))
+            (Results) = (Pair (Found 0) (Missing))
+        ")
+        .unwrap()
+        .add_native("Lookup".to_string(), 1, |args| {
+            match &args[0] {
+                Term::Ctr { name, args } if name == "Key" => Term::constructor("Found", [Term::from(10 * u64::try_from((*args[0]).clone()).unwrap())]),
+                _ => Term::constructor("Missing", []),
+            }
+        })
+        .unwrap()
+        .add_native("Double".to_string(), 1, |args| {
+            // variables may be used more than once
+            Term::Lam { name: "f".to_string(), body: Box::new(Term::App {
+                func: Box::new(Term::Var { name: "f".to_string() }),
+                argm: Box::new(Term::App { func: Box::new(Term::Var { name: "f".to_string() }), argm: Box::new(args[0].clone()) }),
+            })}
+        })
+        .unwrap()
+        .build();
+    let eval = |code: &str| runtime.normalize_term(&hvm::syntax::read_term(code).unwrap()).to_string();
+    assert_eq!(eval("(Main)"), "(Found 30)");
+    assert_eq!(eval("(Lookup 7)"), "(Missing)");
+    assert_eq!(eval("(Double 7)"), "λx0 (x0 (x0 7))");
+    // superposed arguments are handled like in interpreted functions
+    assert_eq!(eval("(Double {1 2})"), "{λx0 (x0 (x0 1)) λx1 (x1 (x1 2))}");
+
+    // returning a term the runtime can't allocate fails the evaluation
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_native("Broken".to_string(), 0, |_| Term::constructor("Unknown", []))
+        .unwrap()
+        .build();
+    let term = hvm::syntax::read_term("(Broken)").unwrap();
+    let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| runtime.normalize_term(&term))).unwrap_err();
+    assert!(payload.downcast_ref::<String>().unwrap().contains("Unknown"));
+
+    // names which are already taken are rejected, whichever comes first
+    let native = |builder: hvm::RuntimeBuilder, name: &str| builder.add_native(name.to_string(), 0, |_| Term::constructor("Unknown", []));
+    let builder = || hvm::RuntimeBuilder::default().add_code("(Main) = 0").unwrap();
+    assert!(native(builder(), "Main").is_err());
+    assert!(native(builder(), "HVM.exit").is_err());
+    assert!(native(native(builder(), "Other").unwrap(), "Other").is_err());
+    assert!(native(builder(), "Other").unwrap().add_code("(Other) = 1").is_err());
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn derived_conversions() {
+    use hvm::syntax::{FromTerm, IntoTerm};
+
+    #[derive(Clone, Debug, PartialEq, IntoTerm, FromTerm)]
+    struct Point {
+        x: u64,
+        y: u64,
+    }
+
+    #[derive(Clone, Debug, PartialEq, IntoTerm, FromTerm)]
+    enum Shape {
+        Circle(Point, u64),
+        Polygon { points: Vec<Point>, name: Option<String> },
+        Empty,
+    }
+
+    #[derive(Clone, Debug, PartialEq, IntoTerm, FromTerm)]
+    struct Tagged<T>(T, char);
+
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Move (Point x y)) = (Point (+ x 1) y)
+            (MoveAll List.nil) = List.nil
+            (MoveAll (List.cons p ps)) = (List.cons (Move p) (MoveAll ps))
+            (Shift (Shape.Circle p r)) = (Shape.Circle (Move p) r)
+            (Shift (Shape.Polygon ps n)) = (Shape.Polygon (MoveAll ps) n)
+            (Shift Shape.Empty) = Shape.Empty
+            (Named (Shape.Polygon ps (Option.Some n))) = n
+            (Named shape) = Option.None
+        ")
+        .unwrap()
+        .build();
+    let shift = |shape: Shape| {
+        let term = hvm::syntax::Term::constructor("Shift", [shape.into_term()]);
+        Shape::from_term(runtime.normalize_term(&term)).unwrap()
+    };
+
+    let circle = Shape::Circle(Point { x: 1, y: 2 }, 3);
+    assert_eq!(circle.clone().into_term().to_string(), "(Shape.Circle (Point 1 2) 3)");
+    assert_eq!(shift(circle), Shape::Circle(Point { x: 2, y: 2 }, 3));
+    let polygon = Shape::Polygon { points: vec![Point { x: 0, y: 0 }, Point { x: 5, y: 1 }], name: Some("tri".to_string()) };
+    assert_eq!(shift(polygon), Shape::Polygon { points: vec![Point { x: 1, y: 0 }, Point { x: 6, y: 1 }], name: Some("tri".to_string()) });
+    assert_eq!(shift(Shape::Empty), Shape::Empty);
+
+    let tagged = Tagged(vec![Some(1u64), None], 'a');
+    assert_eq!(Tagged::from_term(tagged.clone().into_term()), Ok(tagged));
+    // on failure, the part of the term which couldn't be converted is returned
+    let term = hvm::syntax::read_term("(Point 1 λx x)").unwrap();
+    assert_eq!(Point::from_term(*term).unwrap_err().to_string(), "λx x");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_terms() {
+    use hvm::language::serde_term::{from_term, to_term};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    // the syntax tree itself can be shipped through any serde format, including terms
+    let file = hvm::syntax::read_file("(Foo (Bar x) y) = {λa (a x) (+ y 1.5)}").unwrap();
+    let back: hvm::syntax::File = from_term(to_term(&file).unwrap()).unwrap();
+    assert_eq!(back.to_string(), file.to_string());
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Point {
+        x: u64,
+        y: u64,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    enum Shape {
+        Circle(Point, f64),
+        Polygon { points: Vec<Point>, name: Option<String> },
+        Empty,
+    }
+
+    // and arbitrary values can be converted to constructor terms
+    let circle = Shape::Circle(Point { x: 1, y: 2 }, 0.5);
+    assert_eq!(to_term(&circle).unwrap().to_string(), "(Shape.Circle (Point 1 2) 0.5)");
+    let pair = (true, 'a');
+    assert_eq!(to_term(&pair).unwrap().to_string(), "(Tuple2 1 97)");
+    assert!(to_term(&-1i32).is_err());
+
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Move (Point x y)) = (Point (+ x 1) y)
+            (MoveAll List.nil) = List.nil
+            (MoveAll (List.cons p ps)) = (List.cons (Move p) (MoveAll ps))
+            (Shift (Shape.Polygon ps n)) = (Shape.Polygon (MoveAll ps) n)
+            (Shift shape) = shape
+            (Named (Shape.Polygon ps (Option.Some n))) = n
+            (Named shape) = Option.None
+        ")
+        .unwrap()
+        .build();
+    let polygon = Shape::Polygon { points: vec![Point { x: 0, y: 0 }, Point { x: 5, y: 1 }], name: Some("tri".to_string()) };
+    let term = hvm::syntax::Term::constructor("Shift", [to_term(&polygon).unwrap()]);
+    let shifted: Shape = from_term(runtime.normalize_term(&term)).unwrap();
+    assert_eq!(shifted, Shape::Polygon { points: vec![Point { x: 1, y: 0 }, Point { x: 6, y: 1 }], name: Some("tri".to_string()) });
+
+    let values = (circle, Shape::Empty, BTreeMap::from([("a".to_string(), vec![1u8, 2]), ("b".to_string(), vec![])]), ());
+    assert_eq!(from_term::<(Shape, Shape, BTreeMap<String, Vec<u8>>, ())>(to_term(&values).unwrap()), Ok(values));
+    assert!(from_term::<Point>(to_term(&Shape::Empty).unwrap()).is_err());
+}
+
+#[test]
+fn typed_calls() {
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Fib 0) = 0
+            (Fib 1) = 1
+            (Fib n) = (+ (Fib (- n 1)) (Fib (- n 2)))
+            (Greet name) = (String.concat \"hi \" name)
+            (Pair a b) = (Tuple a b)
+            (String.concat String.nil ys) = ys
+            (String.concat (String.cons x xs) ys) = (String.cons x (String.concat xs ys))
+        ")
+        .unwrap()
+        .build();
+
+    assert_eq!(runtime.call("Fib", (8u64,)), Ok(21u64));
+    assert_eq!(runtime.call("Greet", ("bob".to_string(),)), Ok("hi bob".to_string()));
+    assert_eq!(runtime.call::<_, u64>("Fob", (8u64,)), Err(hvm::CallError::UnknownFunction { name: "Fob".to_string() }));
+    // constructors aren't functions, so calling them would only build a term
+    assert_eq!(runtime.call::<_, u64>("Tuple", (1u64, 2u64)), Err(hvm::CallError::UnknownFunction { name: "Tuple".to_string() }));
+    let err = runtime.call::<_, u64>("Fib", (8u64, 1u64)).unwrap_err();
+    assert_eq!(err, hvm::CallError::WrongArity { name: "Fib".to_string(), expected: 1, given: 2 });
+    assert_eq!(err.to_string(), "Function `Fib` takes 1 arguments, but 2 were given.");
+    let err = runtime.call::<_, u64>("Pair", (1u64, 'x')).unwrap_err();
+    assert_eq!(err.to_string(), "Couldn't convert the output: `(Tuple 1 120)`.");
+}
+
+#[test]
+fn rule_selection() {
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Op Add a b) = (+ a b)
+            (Op Sub a b) = (- a b)
+            (Op Mul 0 b) = 0
+            (Op Mul a b) = (* a b)
+            (Op op 7 b) = 700
+            (Op op a b) = (Unknown op a b)
+            (Kind 0) = Zero
+            (Kind 1.5) = Float
+            (Kind (Pair a b)) = IsPair
+            (Kind x) = Other
+            (Pick (A) x) = x
+            (Pick y (B)) = B
+            (Names) = [Div Nil (C) (D)]
+        ")
+        .unwrap()
+        .build();
+
+    // the first rule which matches is applied, as the rules are tested in order
+    for (term, norm) in [
+        ("(Op Add 7 2)", "9"),
+        ("(Op Mul 0 5)", "0"),
+        ("(Op Mul 3 5)", "15"),
+        ("(Op Div 7 1)", "700"),
+        ("(Op Div 8 1)", "(Unknown (Div) 8 1)"),
+        ("(Kind 0)", "(Zero)"),
+        ("(Kind 1.5)", "(Float)"),
+        ("(Kind 2)", "(Other)"),
+        ("(Kind (Pair 1 2))", "(IsPair)"),
+        ("(Kind Nil)", "(Other)"),
+        ("(Pick (A) 5)", "5"),
+        ("(Pick (C) (B))", "(B)"),
+        // default variables on strict arguments only match constructors and numbers
+        ("(Kind λx x)", "(Kind λx0 x0)"),
+        ("(Op λx x 1 2)", "(Op λx0 x0 1 2)"),
+        ("(Pick (A) λx x)", "(Pick (A) λx0 x0)"),
+        ("(Pick (C) (D))", "(Pick (C) (D))"),
+    ] {
+        assert_eq!(runtime.normalize_term(&hvm::syntax::read_term(term).unwrap()).to_string(), norm, "{}", term);
+    }
+}
+
+#[test]
+fn nested_patterns() {
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(2)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Pairs (List.cons x (List.cons y t))) = (List.cons (+ x y) (Pairs t))
+            (Pairs (List.cons x List.nil)) = [x]
+            (Pairs List.nil) = List.nil
+            (Range 0 acc) = acc
+            (Range n acc) = (Range (- n 1) (List.cons n acc))
+            (Head (Pair (Pair 0 b) c)) = (Zero b c)
+            (Head (Pair (Pair a b) (Some x))) = (Found a b x)
+            (Head (Pair a b)) = (Other a b)
+            (Second (List.cons x (List.cons y t))) = y
+        ")
+        .unwrap()
+        .build();
+
+    // the fields matched on are reduced, and superpositions on them are lifted
+    for (term, norm) in [
+        ("(Pairs (Range 5 List.nil))", "[3, 7, 5]"),
+        ("(Head (Pair (Pair 0 1) 2))", "(Zero 1 2)"),
+        ("(Head (Pair (Pair (- 3 3) 1) 2))", "(Zero 1 2)"),
+        ("(Head (Pair (Pair 3 4) (Some 5)))", "(Found 3 4 5)"),
+        ("(Head (Pair (Pair 3 4) 5))", "(Other (Pair 3 4) 5)"),
+        ("(Head (Pair 7 8))", "(Other 7 8)"),
+        ("(Head (Pair (Pair λx x 1) 2))", "(Other (Pair λx0 x0 1) 2)"),
+        ("(Head (Pair (Pair {0 1} 1) 2))", "{(Zero 1 2) (Other (Pair 1 1) 2)}"),
+    ] {
+        assert_eq!(runtime.normalize_term(&hvm::syntax::read_term(term).unwrap()).to_string(), norm, "{}", term);
+    }
+
+    // matching on nested constructors takes a single rewrite
+    let cost = runtime.get_rewrite_count();
+    assert_eq!(runtime.normalize_term(&hvm::syntax::read_term("(Second [1, 2, 3])").unwrap()).to_string(), "2");
+    assert_eq!(runtime.get_rewrite_count(), cost + 1);
+}
+
+#[test]
+fn deeply_nested_patterns() {
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(64 * hvm::CELLS_PER_MB)
+        .add_code("
+            (P (Cons x (Cons y xs))) = (Cons (+ x y) (P (Cons y xs)))
+            (P (Cons x Nil)) = Nil
+            (Iter 0 xs) = xs
+            (Iter n xs) = (Iter (- n 1) (P xs))
+            (Range 0 acc) = acc
+            (Range n acc) = (Range (- n 1) (Cons (% n 7) acc))
+        ")
+        .unwrap()
+        .build();
+
+    // each call matches on a field that the call under it must reduce first, 1000 calls deep
+    let term = hvm::syntax::read_term("(Iter 1000 (Range 1001 Nil))").unwrap();
+    assert_eq!(runtime.normalize_term(&term).to_string(), "(Cons 944554005359826273 (Nil))");
+}
+
+#[test]
+fn optimizations() {
+    let code = "
+        (Inc x) = (+ x 1)
+        (Twice f x) = (f (f x))
+        (Const a b) = a
+        (Two) = (Inc (Inc 0))
+        (Folded) = (+ (* 2 3) ((λx (+ x 1)) 4))
+        (Kind x) = (Some x)
+        (Kind 0) = Zero
+        (Sum 0) = 0
+        (Sum n) = (+ (Inc n) (Sum (- n 1)))
+        (Pick p) = (Const (Twice λx (* x 2) p) 7)
+        (Shared x) = (Pair x (Inc x))
+    ";
+    let runtime = |level: u8| {
+        hvm::RuntimeBuilder::default()
+            .set_thread_count(1)
+            .set_heap_size(hvm::CELLS_PER_MB)
+            .set_optimizations(hvm::Optimizations::level(level))
+            .add_code(code)
+            .unwrap()
+            .build()
+    };
+    let runtimes = [runtime(0), runtime(1), runtime(2)];
+    let eval = |runtime: &hvm::Runtime, term: &str| {
+        let cost = runtime.get_rewrite_count();
+        let norm = runtime.normalize_term(&hvm::syntax::read_term(term).unwrap()).to_string();
+        (norm, runtime.get_rewrite_count() - cost)
+    };
+
+    // the optimizations don't change the results, only the rewrites it takes to reach them
+    for (term, norm, costs) in [
+        ("(Two)", "2", [5, 5, 1]),
+        ("(Folded)", "11", [5, 1, 1]),
+        ("(Kind 0)", "(Some 0)", [1, 1, 1]),
+        ("(Sum 10)", "65", [61, 61, 51]),
+        ("(Pick 3)", "12", [11, 11, 9]),
+        ("(Shared λx x)", "(Pair λx0 x0 (+ λx1 x1 1))", [4, 4, 3]),
+        ("(Shared {1 2})", "(Pair {1 2} {2 3})", [9, 9, 8]),
+    ] {
+        for (level, runtime) in runtimes.iter().enumerate() {
+            assert_eq!(eval(runtime, term), (norm.to_string(), costs[level]), "{} at -O{}", term, level);
+        }
+    }
+}
+
+#[test]
+fn strictness_inference() {
+    let code = "
+        (Sum acc 0) = acc
+        (Sum acc n) = (Sum (+ acc n) (- n 1))
+        (Apply f x) = (f x)
+        (Pair a b) = (P a b)
+        (Mix a b) = dup a0 a1 = a; (+ a0 (* a1 b))
+        ~(Lazy x) = (+ x 1)
+        (Log !x y) = y
+    ";
+    let book = hvm::rulebook::gen_rulebook(&hvm::syntax::read_file(code).unwrap());
+    let smap = |name: &str| book.id_to_smap[&book.name_to_id[name]].clone();
+
+    // arguments reduced through operations, applications and strict calls become strict
+    assert_eq!(smap("Sum"), [true, true]);
+    assert_eq!(smap("Apply"), [true, false]);
+    assert_eq!(smap("Pair"), [false, false]);
+    assert_eq!(smap("Mix"), [true, true]);
+    assert_eq!(smap("Lazy"), [false]);
+    assert_eq!(smap("Log"), [true, true]);
+    assert_eq!(hvm::rulebook::explain_strictness(&book), concat!(
+        "Apply: 0 inferred (applied)\n",
+        "Lazy: lazy (inference disabled)\n",
+        "Log: 0 annotated, 1 inferred (returned)\n",
+        "Mix: 0 inferred (used by `+`), 1 inferred (used by `*`)\n",
+        "Pair: lazy\n",
+        "Sum: 0 inferred (returned), 1 matched\n",
+    ));
+
+    // which lazy functions, like `add_lazy_function` does, don't get
+    let mut file = hvm::syntax::read_file(code).unwrap();
+    file.lazy.push(String::from("Sum"));
+    let book = hvm::rulebook::gen_rulebook(&file);
+    assert_eq!(book.id_to_smap[&book.name_to_id["Sum"]], [false, true]);
+
+    // they're only forced, so values rules don't match on are still accepted
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code(code)
+        .unwrap()
+        .add_lazy_function(String::from("Sum"))
+        .build();
+    for (term, norm) in [
+        ("(Sum 0 100)", "5050"),
+        ("(Apply λx (Pair x 2) 1)", "(P 1 2)"),
+        ("(Apply (Apply λf f λx x) 2)", "2"),
+        ("(Mix {1 2} 3)", "{4 8}"),
+        ("(Lazy 1)", "2"),
+    ] {
+        assert_eq!(runtime.normalize_term(&hvm::syntax::read_term(term).unwrap()).to_string(), norm, "{}", term);
+    }
+}
+
+#[test]
+fn rewrite_limit() {
+    let mut runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(2)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Fib 0) = 0
+            (Fib 1) = 1
+            (Fib n) = (+ (Fib (- n 1)) (Fib (- n 2)))
+            (Loop x) = (Loop (+ x 1))
+        ")
+        .unwrap()
+        .build();
+
+    let term = hvm::syntax::read_term("(Fib 20)").unwrap();
+    assert_eq!(runtime.normalize_term_limited(&term, 100), None);
+    let cost = runtime.get_rewrite_count();
+    assert!(cost > 100);
+    // the runtime stays usable after giving up
+    let term = hvm::syntax::read_term("(Loop 0)").unwrap();
+    assert_eq!(runtime.normalize_term_limited(&term, 10000), None);
+    let term = hvm::syntax::read_term("(Fib 10)").unwrap();
+    assert_eq!(runtime.normalize_term_limited(&term, 10000).map(|x| x.to_string()), Some("55".to_string()));
+    assert_eq!(runtime.normalize_term(&term).to_string(), "55");
+    assert!(runtime.get_rewrite_count() > cost + 10000);
+}
+
+#[test]
+fn concurrent_evaluations() {
+    fn assert_sync<T: Sync>(_: &T) {}
+
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(4)
+        .set_evaluation_thread_count(1)
+        .set_heap_size(16 * hvm::CELLS_PER_MB)
+        .add_code(INSERSION_SORT)
+        .unwrap()
+        .add_code("
+            (Fib 0) = 0
+            (Fib 1) = 1
+            (Fib n) = (+ (Fib (- n 1)) (Fib (- n 2)))
+            (Pow2 0 f) = f
+            (Pow2 n f) = let g = (Pow2 (- n 1) f); λx (g (g x))
+            (Tag t x) = (Tagged t x)
+        ")
+        .unwrap()
+        .build();
+    assert_sync(&runtime);
+
+    // each thread checks its own results, which would be mixed up if evaluations shared state
+    std::thread::scope(|scope| {
+        for t in 0 .. 8u64 {
+            let runtime = &runtime;
+            scope.spawn(move || {
+                for i in 0 .. 20u64 {
+                    let list: Vec<u64> = (0 .. 30).map(|x| (x * 7919 + t * 31 + i) % 101).collect();
+                    let term = hvm::Term::constructor("Sort", [vec_term(list.clone())]);
+                    let mut expected = list;
+                    expected.sort();
+                    assert_eq!(as_vec::<u64>(&runtime.normalize_term(&term)), Some(expected));
+
+                    let term = hvm::syntax::read_term(&format!("(Tag {} ((Pow2 4 λx (+ x {})) {}))", t, t + 1, i)).unwrap();
+                    assert_eq!(runtime.normalize_term(&term).to_string(), format!("(Tagged {} {})", t, i + 16 * (t + 1)));
+
+                    let term = hvm::syntax::read_term(&format!("(Fib {})", 10 + t)).unwrap();
+                    assert_eq!(runtime.eval_term::<u64, _>(&term), Ok([55, 89, 144, 233, 377, 610, 987, 1597][t as usize]));
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn debug_evaluations() {
+    // each evaluation shows where the threads it was given are, whichever they are
+    for evaluation_thread_count in [1, 2] {
+        let runtime = hvm::RuntimeBuilder::default()
+            .set_thread_count(2)
+            .set_evaluation_thread_count(evaluation_thread_count)
+            .set_heap_size(hvm::CELLS_PER_MB)
+            .set_debug(true)
+            .add_code("(Double 0) = 0\n(Double n) = (+ 2 (Double (- n 1)))")
+            .unwrap()
+            .build();
+        assert_eq!(runtime.call::<_, u64>("Double", (3u64,)).unwrap(), 6);
+    }
+}
+
+#[test]
+fn background_evaluations() {
+    let runtime = std::sync::Arc::new(hvm::RuntimeBuilder::default()
+        .set_thread_count(2)
+        .set_evaluation_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Fib 0) = 0
+            (Fib 1) = 1
+            (Fib n) = (+ (Fib (- n 1)) (Fib (- n 2)))
+            (Loop x) = (Loop x)
+            (Add a b) = (+ a b)
+        ")
+        .unwrap()
+        .add_native("Fail".to_string(), 1, |_| panic!("failed"))
+        .unwrap()
+        .build());
+
+    let handle = runtime.spawn_normalize(&hvm::syntax::read_term("(Fib 15)").unwrap());
+    assert_eq!(handle.join().map(|x| x.to_string()), Ok("610".to_string()));
+
+    let handle = runtime.spawn_normalize(&hvm::syntax::read_term("(Loop 0)").unwrap());
+    while handle.progress() < 1000 {
+        std::thread::yield_now();
+    }
+    assert!(!handle.is_finished());
+    // other evaluations aren't affected by the running one, nor by its cancellation
+    let term = hvm::syntax::read_term("(Fib 10)").unwrap();
+    assert_eq!(runtime.normalize_term(&term).to_string(), "55");
+    handle.cancel();
+    let progress = handle.progress();
+    assert_eq!(handle.join(), Err(hvm::EvalError::Cancelled));
+    assert!(progress >= 1000);
+    for _ in 0 .. 4 {
+        assert_eq!(runtime.spawn_normalize(&term).join().map(|x| x.to_string()), Ok("55".to_string()));
+    }
+
+    let handle = runtime.spawn_normalize(&term);
+    while !handle.is_finished() {
+        std::thread::yield_now();
+    }
+    let progress = handle.progress();
+    assert!(progress > 0);
+    handle.cancel();
+    assert_eq!(handle.progress(), progress);
+    assert_eq!(handle.join().map(|x| x.to_string()), Ok("55".to_string()));
+    // cancelling while the evaluation ends either stops it, or keeps its result if it was reduced
+    for _ in 0 .. 16 {
+        let handle = runtime.spawn_normalize(&term);
+        handle.cancel();
+        match handle.join() {
+            Ok(output) => assert_eq!(output.to_string(), "55"),
+            Err(err) => assert_eq!(err, hvm::EvalError::Cancelled),
+        }
+    }
+    assert!(matches!(runtime.spawn_normalize(&hvm::syntax::read_term("(Foo 1)").unwrap()).join(), Err(hvm::EvalError::Panicked(_))));
+
+    // a reduction which panics leaves no pending work to the next evaluations on its threads
+    let cost = |term: &hvm::syntax::Term| {
+        let handle = runtime.spawn_normalize(term);
+        while !handle.is_finished() {
+            std::thread::yield_now();
+        }
+        let progress = handle.progress();
+        assert_eq!(handle.join().map(|x| x.to_string()), Ok("55".to_string()));
+        progress
+    };
+    let before = cost(&term);
+    for _ in 0 .. 4 {
+        let failing = hvm::syntax::read_term("(Add (Fib 10) (Fail 1))").unwrap();
+        assert!(matches!(runtime.spawn_normalize(&failing).join(), Err(hvm::EvalError::Panicked(_))));
+        assert_eq!(cost(&term), before);
+    }
+}
+
+#[cfg(feature = "observer")]
+#[test]
+fn reduction_observer() {
+    use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+    #[derive(Default)]
+    struct Profile {
+        rewrites: AtomicU64,
+        fib_calls: AtomicU64,
+        fib_id: AtomicU64,
+        allocated: AtomicI64,
+        steals: AtomicU64,
+    }
+
+    impl hvm::ReductionObserver for Profile {
+        fn on_rewrite(&self, _heap: &hvm::Heap, _tid: usize, kind: hvm::RewriteKind) {
+            self.rewrites.fetch_add(1, Ordering::Relaxed);
+            if kind == hvm::RewriteKind::Fun(self.fib_id.load(Ordering::Relaxed)) {
+                self.fib_calls.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        fn on_alloc(&self, _heap: &hvm::Heap, _tid: usize, _loc: u64, arity: u64) {
+            self.allocated.fetch_add(arity as i64, Ordering::Relaxed);
+        }
+
+        fn on_free(&self, _heap: &hvm::Heap, _tid: usize, _loc: u64, arity: u64) {
+            self.allocated.fetch_sub(arity as i64, Ordering::Relaxed);
+        }
+
+        fn on_steal(&self, _heap: &hvm::Heap, _tid: usize, _victim: usize) {
+            self.steals.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    let profile = std::sync::Arc::new(Profile::default());
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(2)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .set_observer(profile.clone())
+        .add_code("
+            (Fib 0) = 0
+            (Fib 1) = 1
+            (Fib n) = (+ (Fib (- n 1)) (Fib (- n 2)))
+        ")
+        .unwrap()
+        .build();
+    let fib_id = (0 .. 1 << 16).find(|id| runtime.get_name(*id) == Some("Fib")).unwrap();
+    profile.fib_id.store(fib_id, Ordering::Relaxed);
+
+    let term = hvm::syntax::read_term("(Fib 15)").unwrap();
+    assert_eq!(runtime.normalize_term(&term).to_string(), "610");
+    assert_eq!(profile.rewrites.load(Ordering::Relaxed), runtime.get_rewrite_count() as u64);
+    // (Fib 15) makes 1973 calls
+    assert_eq!(profile.fib_calls.load(Ordering::Relaxed), 1973);
+    assert!(profile.allocated.load(Ordering::Relaxed) > 0);
+}
+
+#[test]
+fn io_actions() {
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(2)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Fib 0) = 0
+            (Fib 1) = 1
+            (Fib n) = (+ (Fib (- n 1)) (Fib (- n 2)))
+            (Echo 0) = (IO.done 0)
+            (Echo n) = (IO.do_input λline (IO.do_output line λx (IO.do_output (String.cons 10 String.nil) λx (Echo (- n 1)))))
+            (Copy from to) = (IO.do_load from λtext (IO.do_store to text λx (IO.done (Fib 20))))
+        ")
+        .unwrap()
+        .build();
+
+    // effects happen in the order the program sequences them
+    let mut output = Vec::new();
+    let term = hvm::syntax::read_term("(Echo 3)").unwrap();
+    let result = runtime.run_io_with(&term, &mut std::io::Cursor::new("a\nb\r\nc"), &mut output);
+    assert_eq!(result.map(|x| x.to_string()), Ok("0".to_string()));
+    assert_eq!(String::from_utf8(output).unwrap(), "a\nb\nc\n");
+
+    let dir = std::env::temp_dir();
+    let from = dir.join(format!("hvm-io-{}-from.txt", std::process::id()));
+    let to = dir.join(format!("hvm-io-{}-to.txt", std::process::id()));
+    std::fs::write(&from, "contents").unwrap();
+    let term = hvm::Term::constructor("Copy", [from.display().to_string().into(), to.display().to_string().into()]);
+    let result = runtime.run_io_with(&term, &mut std::io::empty(), &mut std::io::sink());
+    assert_eq!(result.map(|x| x.to_string()), Ok("6765".to_string()));
+    assert_eq!(std::fs::read_to_string(&to).unwrap(), "contents");
+    std::fs::remove_file(&from).unwrap();
+    std::fs::remove_file(&to).unwrap();
+
+    // loading a missing file, or running something else than an action, fails
+    let term = hvm::Term::constructor("Copy", [from.display().to_string().into(), to.display().to_string().into()]);
+    assert!(runtime.run_io_with(&term, &mut std::io::empty(), &mut std::io::sink()).is_err());
+    let term = hvm::syntax::read_term("(Fib 10)").unwrap();
+    assert!(runtime.run_io_with(&term, &mut std::io::empty(), &mut std::io::sink()).is_err());
+}
+
+#[test]
+fn failures() {
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(2)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("(Fib 0) = 0\n(Fib 1) = 1\n(Fib n) = (+ (Fib (- n 1)) (Fib (- n 2)))")
+        .unwrap()
+        .build();
+
+    // exiting unwinds to the caller with its code, instead of ending the process
+    let term = hvm::syntax::read_term("(+ (Fib 20) (HVM.exit (+ 1 2)))").unwrap();
+    assert_eq!(hvm::catch_failure(|| runtime.normalize_term(&term)), Err(hvm::Failure::Exit(3)));
+
+    // a builtin given arguments it can't handle panics with the reason
+    let runtime = hvm::RuntimeBuilder::default().set_thread_count(2).set_heap_size(hvm::CELLS_PER_MB).build();
+    let term = hvm::syntax::read_term("(HVM.exit λx x)").unwrap();
+    let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| runtime.normalize_term(&term))).unwrap_err();
+    assert!(payload.downcast_ref::<String>().unwrap().starts_with("Runtime failure on: "));
+}
+
+#[test]
+fn file_builtins() {
+    let dir = std::env::temp_dir().join(format!("hvm-files-{}", std::process::id()));
+    std::fs::create_dir(&dir).unwrap();
+    let path = |name: &str| dir.join(name).display().to_string();
+
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(2)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("
+            (Then (Result.ok x) f) = (f x)
+            (Then (Result.err e) f) = (Result.err e)
+            (Id x) = x
+            (Copy from to) =
+                (HVM.load_bytes from λbytes (Then bytes λbytes
+                (HVM.store_bytes to bytes λr (Then r λr
+                (HVM.append to \"!\" λr (Then r λr
+                (HVM.load_bytes to λr r)))))))
+        ")
+        .unwrap()
+        .build();
+    let eval = |code: String| runtime.normalize_term(&hvm::syntax::read_term(&code).unwrap()).to_string();
+
+    // bytes which aren't valid UTF-8 are kept as they are
+    std::fs::write(dir.join("a.bin"), [0, 255, 10]).unwrap();
+    assert_eq!(eval(format!("(Copy \"{}\" \"{}\")", path("a.bin"), path("b.bin"))), "(Result.ok [0, 255, 10, 33])");
+    assert_eq!(std::fs::read(dir.join("b.bin")).unwrap(), [0, 255, 10, 33]);
+    assert_eq!(eval(format!("(HVM.list_dir \"{}\" λr r)", dir.display())), "(Result.ok [\"a.bin\", \"b.bin\"])");
+    assert_eq!(eval(format!("(HVM.exists \"{}\" λr r)", path("b.bin"))), "(Result.ok 1)");
+    // arguments are normalized first, in the middle of the rest of the reduction
+    assert_eq!(eval(format!("(Then (HVM.exists (Id \"{}\") λr r) λr (+ r (Id 1)))", path("b.bin"))), "2");
+    assert_eq!(eval(format!("(HVM.remove \"{}\" λr r)", path("b.bin"))), "(Result.ok 0)");
+    assert_eq!(eval(format!("(HVM.exists \"{}\" λr r)", path("b.bin"))), "(Result.ok 0)");
+
+    // failures are values, which the program can handle
+    assert!(eval(format!("(HVM.remove \"{}\" λr r)", path("b.bin"))).starts_with("(Result.err "));
+    assert!(eval(format!("(Copy \"{}\" \"{}\")", path("b.bin"), path("c.bin"))).starts_with("(Result.err "));
+    assert_eq!(eval(format!("(HVM.store_bytes \"{}\" [1, 256] λr r)", path("c.bin"))), "(Result.err \"Expected a list of bytes.\")");
+    assert!(!dir.join("c.bin").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compiled_functions() {
+    // stands for the code `hvm compile` generates for `(Answer) = 41`, except it answers 42
+    fn answer_visit(_ctx: hvm::ReduceCtx) -> bool {
+        false
+    }
+    fn answer_apply(ctx: hvm::ReduceCtx) -> bool {
+        hvm::inc_cost(ctx.heap, ctx.tid);
+        hvm::link(ctx.heap, *ctx.host, hvm::U6O(42));
+        false
+    }
+
+    let code = "
+        (Answer) = 41
+        (Main) = (+ (Answer) 1)
+    ";
+    // ids only depend on the code, so code compiled from it can refer to them
+    let book = hvm::rulebook::gen_rulebook(&hvm::syntax::read_file(code).unwrap());
+    let id = book.name_to_id["Answer"];
+    for _ in 0 .. 8 {
+        assert_eq!(hvm::rulebook::gen_rulebook(&hvm::syntax::read_file(code).unwrap()).name_to_id["Answer"], id);
+    }
+
+    let answer = hvm::CompiledFunction { id, name: "Answer", smap: &[], visit: answer_visit, apply: answer_apply };
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code(code)
+        .unwrap()
+        .add_compiled(&[answer])
+        .build();
+    assert_eq!(runtime.normalize_term(&hvm::syntax::read_term("(Main)").unwrap()).to_string(), "43");
+
+    // added with its code, it keeps its id after other code
+    let runtime = hvm::RuntimeBuilder::default()
+        .set_thread_count(1)
+        .set_heap_size(hvm::CELLS_PER_MB)
+        .add_code("(Question) = (Answer)")
+        .unwrap()
+        .add_compiled_code(code, &[answer])
+        .unwrap()
+        .build();
+    assert_eq!(runtime.normalize_term(&hvm::syntax::read_term("(Question)").unwrap()).to_string(), "42");
+
+    // installing it on other code fails
+    let result = std::panic::catch_unwind(|| {
+        hvm::RuntimeBuilder::default()
+            .set_thread_count(1)
+            .set_heap_size(hvm::CELLS_PER_MB)
+            .add_code("(Question) = 0")
+            .unwrap()
+            .add_compiled(&[answer])
+            .build()
+    });
+    assert!(result.is_err());
+}